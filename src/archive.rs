@@ -0,0 +1,232 @@
+use std::fs;
+use std::io::Cursor;
+use std::path::{Component, Path, PathBuf};
+use tar::{Archive, Builder, EntryType};
+use crate::error::{Error, Result};
+use crate::storage;
+
+// Directories are embedded as one payload: a tar archive (ustar/gnu, as written by the tar crate)
+// of the directory tree, with paths relative to the directory ('./notes/a.txt'), permissions,
+// and mtimes. Symlinks are stored as symlinks (not followed). The envelope marks the payload as
+// an archive (flag bit 3), and its filename is the directory name.
+//
+// Unpacking only accepts regular files, directories, and links whose path (and link target)
+// stays inside the output directory; any other entry makes the whole unpack fail before anything
+// is written. Symlink targets must be relative and must not climb ('..'), since a target
+// resolved through a symlink unpacked earlier (ex: 'd -> .' then 'd/e -> ..') can leave the
+// output directory even though it looks inside it. The archive is unpacked into a temp directory
+// next to the output directory and renamed into place once complete (see storage.rs); an existing
+// output directory is moved aside first and only removed once the new one is in place, so a
+// failed unpack leaves it untouched rather than half overwritten.
+
+/// Mime-type stored for embedded directories.
+pub(crate) const ARCHIVE_MIME_TYPE: &str = "application/x-tar";
+
+/// Packs the directory tree at `dir` into an in-memory tar archive.
+pub(crate) fn pack_dir(dir: &Path) -> Result<Vec<u8>> {
+    let mut builder = Builder::new(Vec::new());
+    builder.follow_symlinks(false);
+    builder.append_dir_all(".", dir)?;
+    Ok(builder.into_inner()?)
+}
+
+/// Unpacks the tar archive `data` (an embedded directory stored under `key`) into `out_dir`
+/// (replacing it if it exists), returning the path of every file and directory created.
+pub(crate) fn unpack(key: &str, data: &[u8], out_dir: &Path) -> Result<Vec<PathBuf>> {
+    // check every entry first, so a bad archive writes nothing
+    let mut archive = Archive::new(Cursor::new(data));
+    for entry in archive.entries()? {
+        let entry = entry?;
+        let path = entry.path()?.into_owned();
+        check_entry(key, &path, entry.header().entry_type(), entry.link_name()?.as_deref())?;
+    }
+
+    // unpack into a temp directory next to out_dir, renamed into place (replacing an existing
+    // out_dir) once complete
+    let unpacked = unpack_into_temp_dir(key, data, out_dir)?;
+    Ok(unpacked.iter().map(|relative| out_dir.join(relative)).collect())
+}
+
+fn unpack_into_temp_dir(key: &str, data: &[u8], out_dir: &Path) -> Result<Vec<PathBuf>> {
+    if let Some(parent) = out_dir.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let temp_dir = storage::temp_path_for(out_dir);
+    let result = fs::create_dir(&temp_dir)
+        .map_err(Error::Io)
+        .and_then(|_| unpack_entries(key, data, &temp_dir))
+        .and_then(|unpacked| Ok(replace_with(&temp_dir, out_dir).map(|_| unpacked)?));
+    if result.is_err() {
+        let _ = fs::remove_dir_all(&temp_dir);
+    }
+    storage::forget_temp_path(&temp_dir);
+    storage::sync_parent_dir(out_dir);
+    result
+}
+
+/// Renames `temp_dir` to `out_dir`, replacing whatever is at `out_dir` (restored on failure).
+fn replace_with(temp_dir: &Path, out_dir: &Path) -> std::io::Result<()> {
+    let existing = match fs::symlink_metadata(out_dir) {
+        Ok(metadata) => metadata,
+        Err(_) => return fs::rename(temp_dir, out_dir),
+    };
+    // the old directory is not a pending temp path: an interrupted run must not remove it
+    let old_dir = storage::temp_path_for(out_dir);
+    storage::forget_temp_path(&old_dir);
+    fs::rename(out_dir, &old_dir)?;
+    if let Err(err) = fs::rename(temp_dir, out_dir) {
+        let _ = fs::rename(&old_dir, out_dir);
+        return Err(err);
+    }
+    let _ = match existing.is_dir() {
+        true => fs::remove_dir_all(&old_dir),
+        false => fs::remove_file(&old_dir),
+    };
+    Ok(())
+}
+
+/// Unpacks every entry of the (already checked) archive `data` into `dir`, returning their
+/// relative paths.
+fn unpack_entries(key: &str, data: &[u8], dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut unpacked = Vec::new();
+    let mut archive = Archive::new(Cursor::new(data));
+    archive.set_preserve_mtime(true);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let relative = match relative_path(&path) {
+            Some(relative) if relative.as_os_str().is_empty() => continue,
+            Some(relative) => relative,
+            None => return Err(unsafe_entry(key, &path, "path leaves the output directory")),
+        };
+        // unpack_in refuses (returns false or fails) to write outside dir, e.g. through
+        // a symlink that already existed in dir
+        if !entry.unpack_in(dir)? {
+            return Err(unsafe_entry(key, &path, "path leaves the output directory"));
+        }
+        unpacked.push(relative);
+    }
+    Ok(unpacked)
+}
+
+fn check_entry(key: &str, path: &Path, entry_type: EntryType, link_name: Option<&Path>) -> Result<()> {
+    // tar's unpack_in also skips any path with a '..' component
+    if relative_path(path).is_none() || path.components().any(|component| component == Component::ParentDir) {
+        return Err(unsafe_entry(key, path, "path leaves the output directory"));
+    }
+    match entry_type {
+        EntryType::Regular | EntryType::Directory => Ok(()),
+        // symlink targets are relative to the symlink's own directory, so without absolute paths
+        // or '..' they can only point at or below it (whatever symlinks they go through)
+        EntryType::Symlink => match link_name {
+            Some(target) if relative_path(target).is_some()
+                && !target.components().any(|component| component == Component::ParentDir) => Ok(()),
+            _ => Err(unsafe_entry(key, path, "symlink target leaves the output directory")),
+        },
+        // hard link targets are relative to the archive root
+        EntryType::Link => match link_name.and_then(relative_path) {
+            Some(_) => Ok(()),
+            None => Err(unsafe_entry(key, path, "hard link target leaves the output directory")),
+        },
+        _ => Err(unsafe_entry(key, path, "unsupported entry type (only files, directories, and links)")),
+    }
+}
+
+/// Resolves `path` lexically, returning `None` if it is absolute or climbs above its root.
+fn relative_path(path: &Path) -> Option<PathBuf> {
+    let mut relative = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => relative.push(name),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !relative.pop() {
+                    return None;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(relative)
+}
+
+fn unsafe_entry(key: &str, path: &Path, reason: &'static str) -> Error {
+    Error::UnsafeArchiveEntry { key: key.to_string(), path: path.display().to_string(), reason }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tar::Header;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("id3stego-archive-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn tar_with(entries: &[(&[u8], EntryType, &[u8])]) -> Vec<u8> {
+        // names and link targets are written as is (the tar crate refuses to write unsafe ones)
+        let mut builder = Builder::new(Vec::new());
+        let mut header = Header::new_gnu();
+        header.set_size(4);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, "safe.txt", &b"safe"[..]).unwrap();
+        for (name, entry_type, link_name) in entries {
+            let mut header = Header::new_old();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name);
+            header.as_old_mut().linkname[..link_name.len()].copy_from_slice(link_name);
+            header.set_entry_type(*entry_type);
+            header.set_mode(0o644);
+            header.set_size(0);
+            header.set_cksum();
+            builder.append(&header, &[][..]).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn unpacks_safe_archives() {
+        let out_dir = scratch_dir("safe");
+        let data = tar_with(&[(b"sub/", EntryType::Directory, b""), (b"sub/link", EntryType::Symlink, b"../safe.txt")]);
+        // the symlink climbs, so even this is refused
+        assert!(unpack("key", &data, &out_dir).is_err());
+
+        let data = tar_with(&[(b"sub/", EntryType::Directory, b""), (b"sub/link", EntryType::Symlink, b"other")]);
+        let unpacked = unpack("key", &data, &out_dir).unwrap();
+        assert!(unpacked.contains(&out_dir.join("safe.txt")));
+        assert_eq!(fs::read(out_dir.join("safe.txt")).unwrap(), b"safe");
+        fs::remove_dir_all(&out_dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_unsafe_entries_without_writing_anything() {
+        let cases: [(&[u8], EntryType, &[u8]); 6] = [
+            (b"../evil.txt", EntryType::Regular, b""),
+            (b"sub/../../evil.txt", EntryType::Regular, b""),
+            (b"/tmp/evil.txt", EntryType::Regular, b""),
+            (b"link", EntryType::Symlink, b"/etc/passwd"),
+            (b"hard", EntryType::Link, b"../evil.txt"),
+            (b"fifo", EntryType::Fifo, b""),
+        ];
+        for (index, entry) in cases.into_iter().enumerate() {
+            let out_dir = scratch_dir(&format!("unsafe-{}", index));
+            let err = unpack("key", &tar_with(&[entry]), &out_dir).unwrap_err();
+            assert!(matches!(err, Error::UnsafeArchiveEntry { .. }), "{:?}", err);
+            assert!(!out_dir.exists());
+        }
+    }
+
+    #[test]
+    fn keeps_existing_directory_when_unpacking_fails() {
+        let out_dir = scratch_dir("existing");
+        fs::create_dir_all(&out_dir).unwrap();
+        fs::write(out_dir.join("kept.txt"), b"kept").unwrap();
+        assert!(unpack("key", &tar_with(&[(b"../evil.txt", EntryType::Regular, b"")]), &out_dir).is_err());
+        assert_eq!(fs::read(out_dir.join("kept.txt")).unwrap(), b"kept");
+
+        unpack("key", &tar_with(&[]), &out_dir).unwrap();
+        assert!(!out_dir.join("kept.txt").exists());
+        fs::remove_dir_all(&out_dir).unwrap();
+    }
+}
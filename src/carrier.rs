@@ -0,0 +1,296 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use id3::{Content, Frame};
+use id3::frame::{Comment, EncapsulatedObject, ExtendedText, Private};
+use crate::container::{Format, MAX_FRAME_SIZE};
+use crate::error::{Error, Result};
+
+// Every carrier stores the same four things: a key, a filename, a mime-type, and the data.
+//
+// GEOB frames have a field for each of them. The other carriers pack the filename and mime-type
+// in front of the data, separated by null bytes (filename \0 mime-type \0 data):
+//  - PRIV: owner identifier is the key, private data is the packed record
+//  - TXXX: description is the key, value is the text encoded packed record
+//  - COMM: description is the key (language 'xxx'), text is the text encoded packed record
+//
+// Text encoded records start with the name of their encoding ("base64:" or "base85:"), so that
+// get mode can decode them without being told which encoding was used.
+//
+// Carriers outside the id3v2 tag (native carriers, see iff.rs, flac.rs, ogg.rs, mp4.rs, and mpeg.rs, and
+// carriers in the audio data, see lsb.rs and mpeg.rs) pack the key in front as well
+// (key \0 filename \0 mime-type \0 data), unless the container has fields of its own for them.
+
+/// Language code of COMM frames written by id3stego (unknown language).
+const COMMENT_LANG: &str = "xxx";
+
+/// id3v2 frame types (or native metadata blocks of other containers) that can carry an embedded file.
+///
+/// Each [`Format`] has a default carrier ([`Format::default_carrier`]); any other one it supports
+/// can be chosen with [`Embedder::carrier`](crate::Embedder::carrier).
+/// Files larger than [`MAX_FRAME_SIZE`](crate::MAX_FRAME_SIZE) are split into ordered chunks
+/// across several frames (or blocks, chunks, comments, or atoms), up to
+/// [`MAX_TAG_SIZE`](crate::MAX_TAG_SIZE) in all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Carrier {
+    /// General encapsulated object frame (default for mp3, wav, and aiff).
+    #[default]
+    Geob,
+    /// Private frame, keyed by its owner identifier.
+    Priv,
+    /// User defined text frame, holding text encoded data.
+    Txxx,
+    /// Comment frame, holding text encoded data.
+    Comm,
+    /// FLAC APPLICATION metadata block with id3stego's application id (default for flac).
+    FlacApplication,
+    /// FLAC PICTURE metadata block (picture type "other"), keyed by its description.
+    FlacPicture,
+    /// Vorbis comment named ID3STEGO, holding text encoded data (default for ogg vorbis and opus).
+    OggComment,
+    /// Vorbis comment named METADATA_BLOCK_PICTURE, holding a base64 encoded FLAC PICTURE block.
+    OggPicture,
+    /// iTunes style freeform ("----") MP4 atom in moov/udta/meta/ilst, keyed by its name (default
+    /// for mp4).
+    Mp4Freeform,
+    /// MP4 'uuid' atom in moov/udta with id3stego's uuid.
+    Mp4Uuid,
+    /// Custom RIFF chunk (id 'I3SG') of a wav file.
+    WavChunk,
+    /// RIFF LIST/INFO sub-chunk (id 'I3SG') of a wav file, holding text encoded data.
+    WavInfo,
+    /// AIFF application specific ('APPL') chunk with id3stego's signature 'I3SG'.
+    AiffAppl,
+    /// AIFF annotation ('ANNO') chunk, holding text encoded data.
+    AiffAnno,
+    /// Least significant bits of the PCM samples of a wav or aiff file, at positions chosen by a
+    /// stego key (see [`Embedder::stego_key`](crate::Embedder::stego_key)), without any id3v2 tag.
+    ///
+    /// Samples are changed by LSB replacement, or by ±1 LSB matching (see
+    /// [`Embedder::lsb_matching`](crate::Embedder::lsb_matching)), and matrix embedding with
+    /// (1, 2^k - 1, k) hamming codes hides k bits with at most one change in every 2^k - 1
+    /// samples (see [`Embedder::matrix_embedding`](crate::Embedder::matrix_embedding)).
+    PcmLsb,
+    /// Least significant bits of the global gains of the MPEG layer III frames of an mp3 file, at
+    /// positions chosen by a stego key, which keeps every frame decodable without re-encoding.
+    Mp3GlobalGain,
+    /// Ancillary data of the MPEG layer III frames of an mp3 file (main data bytes no frame uses),
+    /// without a stego key.
+    Mp3Ancillary,
+}
+
+/// Binary-to-text encodings used by the TXXX and COMM carriers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextEncoding {
+    /// Standard base64 (4 characters per 3 bytes, default).
+    #[default]
+    Base64,
+    /// Z85 flavor of base85 (5 characters per 4 bytes).
+    Base85,
+}
+
+impl Carrier {
+    /// id3v2 frame id (or metadata block, chunk, atom, or comment field name) of this carrier.
+    pub fn frame_id(&self) -> &'static str {
+        match self {
+            Carrier::Geob => "GEOB",
+            Carrier::Priv => "PRIV",
+            Carrier::Txxx => "TXXX",
+            Carrier::Comm => "COMM",
+            Carrier::FlacApplication => "APPLICATION",
+            Carrier::FlacPicture => "PICTURE",
+            Carrier::OggComment => "ID3STEGO",
+            Carrier::OggPicture => "METADATA_BLOCK_PICTURE",
+            Carrier::Mp4Freeform => "----",
+            Carrier::Mp4Uuid => "uuid",
+            Carrier::WavChunk => "I3SG",
+            Carrier::WavInfo => "INFO",
+            Carrier::AiffAppl => "APPL",
+            Carrier::AiffAnno => "ANNO",
+            Carrier::PcmLsb => "LSB",
+            Carrier::Mp3GlobalGain => "GLOBAL_GAIN",
+            Carrier::Mp3Ancillary => "ANCILLARY",
+        }
+    }
+
+    /// Whether this carrier is a frame of an id3v2 tag (rather than a native metadata block).
+    pub fn in_id3_tag(&self) -> bool {
+        matches!(self, Carrier::Geob | Carrier::Priv | Carrier::Txxx | Carrier::Comm)
+    }
+
+    /// Whether this carrier hides embedded files in the audio data itself (rather than in
+    /// metadata), which takes a stego key.
+    pub fn in_audio_data(&self) -> bool {
+        matches!(self, Carrier::PcmLsb | Carrier::Mp3GlobalGain)
+    }
+
+    /// Largest number of (binary) bytes one frame of this carrier can hold.
+    pub(crate) fn max_frame_data(&self, encoding: TextEncoding) -> usize {
+        match (self, encoding) {
+            (Carrier::Geob, _) | (Carrier::Priv, _) => MAX_FRAME_SIZE,
+            // flac blocks hold up to 2^24 - 1 bytes, leaving room for the key and mime-type
+            (Carrier::FlacApplication, _) | (Carrier::FlacPicture, _) => MAX_FRAME_SIZE,
+            (Carrier::Mp4Freeform, _) | (Carrier::Mp4Uuid, _) => MAX_FRAME_SIZE,
+            (Carrier::WavChunk, _) | (Carrier::AiffAppl, _) => MAX_FRAME_SIZE,
+            (Carrier::PcmLsb, _) | (Carrier::Mp3GlobalGain, _) | (Carrier::Mp3Ancillary, _) => MAX_FRAME_SIZE,
+            // ogg pictures are always base64, ogg comments use the chosen encoding like TXXX and COMM
+            (Carrier::OggPicture, _) => MAX_FRAME_SIZE / 4 * 3,
+            (_, TextEncoding::Base64) => MAX_FRAME_SIZE / 4 * 3,
+            (_, TextEncoding::Base85) => MAX_FRAME_SIZE / 5 * 4,
+        }
+    }
+}
+
+impl TextEncoding {
+    fn prefix(&self) -> &'static str {
+        match self {
+            TextEncoding::Base64 => "base64:",
+            TextEncoding::Base85 => "base85:",
+        }
+    }
+
+    pub(crate) fn encode(&self, data: &[u8]) -> String {
+        let encoded = match self {
+            TextEncoding::Base64 => BASE64.encode(data),
+            TextEncoding::Base85 => z85::encode(data),
+        };
+        format!("{}{}", self.prefix(), encoded)
+    }
+
+    /// Decodes text written by [`encode`](TextEncoding::encode), whichever encoding was used.
+    pub(crate) fn decode(text: &str) -> Option<Vec<u8>> {
+        if let Some(encoded) = text.strip_prefix(TextEncoding::Base64.prefix()) {
+            BASE64.decode(encoded).ok()
+        }
+        else if let Some(encoded) = text.strip_prefix(TextEncoding::Base85.prefix()) {
+            z85::decode(encoded).ok()
+        }
+        else {
+            None
+        }
+    }
+}
+
+/// One frame's worth of an embedded file, independent of the carrier it is stored in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Record {
+    pub key: String,
+    pub filename: String,
+    pub mime_type: String,
+    pub carrier: Carrier,
+    pub data: Vec<u8>,
+}
+
+impl Record {
+    /// Builds the id3v2 frame holding this record, for the id3v2 tag of a `format` file.
+    ///
+    /// Fails with [`Error::UnsupportedCarrier`] for native carriers ([`Carrier::in_id3_tag`] is
+    /// false), whose records are packed with [`to_native`](Record::to_native) instead.
+    pub fn into_frame(self, encoding: TextEncoding, format: Format) -> Result<Frame> {
+        let frame = match self.carrier {
+            Carrier::Geob => Frame::from(EncapsulatedObject {
+                mime_type: self.mime_type,
+                filename: self.filename,
+                description: self.key,
+                data: self.data,
+            }),
+            Carrier::Priv => Frame::from(Private {
+                private_data: pack(&self.filename, &self.mime_type, &self.data),
+                owner_identifier: self.key,
+            }),
+            Carrier::Txxx => Frame::from(ExtendedText {
+                description: self.key,
+                value: encoding.encode(&pack(&self.filename, &self.mime_type, &self.data)),
+            }),
+            Carrier::Comm => Frame::from(Comment {
+                lang: COMMENT_LANG.to_string(),
+                description: self.key,
+                text: encoding.encode(&pack(&self.filename, &self.mime_type, &self.data)),
+            }),
+            // native carriers are never stored in an id3v2 tag, see to_native
+            carrier => return Err(Error::UnsupportedCarrier { carrier, format }),
+        };
+        Ok(frame)
+    }
+
+    /// Packs this record for a native carrier without fields of its own
+    /// (key \0 filename \0 mime-type \0 data).
+    pub fn to_native(&self) -> Vec<u8> {
+        let mut packed = Vec::with_capacity(self.key.len() + 1);
+        packed.extend_from_slice(self.key.as_bytes());
+        packed.push(0);
+        packed.extend_from_slice(&pack(&self.filename, &self.mime_type, &self.data));
+        packed
+    }
+
+    /// Reads a record packed by [`to_native`](Record::to_native).
+    pub fn from_native(carrier: Carrier, packed: &[u8]) -> Option<Record> {
+        let key_end = packed.iter().position(|byte| *byte == 0)?;
+        let key = String::from_utf8(packed[..key_end].to_vec()).ok()?;
+        let (filename, mime_type, data) = unpack(&packed[key_end + 1..])?;
+        Some(Record { key, filename, mime_type, carrier, data })
+    }
+
+    /// Reads the record held by `frame`, detecting the carrier from the frame id.
+    ///
+    /// Returns `None` for frames that cannot be carriers; call only for frames whose
+    /// [`frame_key`] belongs to id3stego, since their contents must decode.
+    pub fn from_frame(frame: &Frame) -> Option<Result<Record>> {
+        let key = frame_key(frame)?.to_string();
+        let damaged = |reason| Some(Err(Error::InvalidFrame { key: key.clone(), reason }));
+        let (carrier, packed) = match frame.content() {
+            Content::EncapsulatedObject(object) => {
+                return Some(Ok(Record {
+                    key,
+                    filename: object.filename.clone(),
+                    mime_type: object.mime_type.clone(),
+                    carrier: Carrier::Geob,
+                    data: object.data.clone(),
+                }))
+            }
+            Content::Private(private) => (Carrier::Priv, private.private_data.clone()),
+            Content::ExtendedText(text) => match TextEncoding::decode(&text.value) {
+                Some(packed) => (Carrier::Txxx, packed),
+                None => return damaged("text is not base64 or base85"),
+            },
+            Content::Comment(comment) => match TextEncoding::decode(&comment.text) {
+                Some(packed) => (Carrier::Comm, packed),
+                None => return damaged("text is not base64 or base85"),
+            },
+            _ => return None,
+        };
+        match unpack(&packed) {
+            Some((filename, mime_type, data)) => Some(Ok(Record { key, filename, mime_type, carrier, data })),
+            None => damaged("missing filename or mime-type"),
+        }
+    }
+}
+
+/// Key of a frame that could carry an embedded file (GEOB, TXXX, or COMM description, or PRIV
+/// owner identifier).
+pub(crate) fn frame_key(frame: &Frame) -> Option<&str> {
+    match frame.content() {
+        Content::EncapsulatedObject(object) => Some(&object.description),
+        Content::ExtendedText(text) => Some(&text.description),
+        Content::Comment(comment) => Some(&comment.description),
+        Content::Private(private) => Some(&private.owner_identifier),
+        _ => None,
+    }
+}
+
+pub(crate) fn pack(filename: &str, mime_type: &str, data: &[u8]) -> Vec<u8> {
+    let mut packed = Vec::with_capacity(filename.len() + mime_type.len() + 2 + data.len());
+    packed.extend_from_slice(filename.as_bytes());
+    packed.push(0);
+    packed.extend_from_slice(mime_type.as_bytes());
+    packed.push(0);
+    packed.extend_from_slice(data);
+    packed
+}
+
+pub(crate) fn unpack(packed: &[u8]) -> Option<(String, String, Vec<u8>)> {
+    let filename_end = packed.iter().position(|byte| *byte == 0)?;
+    let mime_type_end = filename_end + 1 + packed[filename_end + 1..].iter().position(|byte| *byte == 0)?;
+    let filename = String::from_utf8(packed[..filename_end].to_vec()).ok()?;
+    let mime_type = String::from_utf8(packed[filename_end + 1..mime_type_end].to_vec()).ok()?;
+    Some((filename, mime_type, packed[mime_type_end + 1..].to_vec()))
+}
@@ -0,0 +1,195 @@
+use crate::container::{MAX_FRAME_SIZE, MAX_TAG_SIZE};
+use crate::error::{Error, Result};
+
+// Payloads that do not fit into a single id3v2 frame are split into ordered chunks, one per frame.
+// Each chunk's frame data starts with a fixed size header (all integers big endian):
+//
+// | Field      | Size | Value                                        |
+// |------------+------+----------------------------------------------|
+// | magic      |    4 | b"I3SC"                                      |
+// | payload id |    8 | random, shared by every chunk of the payload |
+// | sequence   |    4 | index of this chunk, starting at 0           |
+// | total      |    4 | number of chunks in the payload              |
+// | length     |    8 | length of the reassembled payload            |
+//
+// Payloads that fit into a single frame are stored as-is (no header), as in id3stego v0.2.
+
+const CHUNK_MAGIC: &[u8; 4] = b"I3SC";
+const CHUNK_HEADER_LEN: usize = 28;
+/// Most chunks a payload can be split into: payloads are at most MAX_TAG_SIZE bytes, and the
+/// smallest chunks (base85 text) hold 4/5 of a frame. The chunk count in a header is never
+/// trusted beyond it.
+const MAX_CHUNKS: u32 = (MAX_TAG_SIZE / (MAX_FRAME_SIZE / 5 * 4 - CHUNK_HEADER_LEN) + 1) as u32;
+/// Most missing sequence numbers reported in [`Error::MissingChunks`].
+const MAX_MISSING_REPORTED: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ChunkHeader {
+    pub payload_id: u64,
+    pub sequence: u32,
+    pub total: u32,
+    pub length: u64,
+}
+
+/// One chunk's header and the payload bytes that follow it.
+pub(crate) type Piece<'a> = (ChunkHeader, &'a [u8]);
+
+impl ChunkHeader {
+    /// Parses the chunk header at the start of `data`, if there is one.
+    pub fn parse(data: &[u8]) -> Option<Piece<'_>> {
+        if data.len() < CHUNK_HEADER_LEN || &data[..4] != CHUNK_MAGIC {
+            return None;
+        }
+        let header = ChunkHeader {
+            payload_id: u64::from_be_bytes(data[4..12].try_into().ok()?),
+            sequence: u32::from_be_bytes(data[12..16].try_into().ok()?),
+            total: u32::from_be_bytes(data[16..20].try_into().ok()?),
+            length: u64::from_be_bytes(data[20..28].try_into().ok()?),
+        };
+        Some((header, &data[CHUNK_HEADER_LEN..]))
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(CHUNK_MAGIC);
+        out.extend_from_slice(&self.payload_id.to_be_bytes());
+        out.extend_from_slice(&self.sequence.to_be_bytes());
+        out.extend_from_slice(&self.total.to_be_bytes());
+        out.extend_from_slice(&self.length.to_be_bytes());
+    }
+}
+
+/// Frame description used for one chunk of a payload split across multiple frames.
+pub(crate) fn description(key: &str, sequence: u32) -> String {
+    format!("{}#{}", key, sequence)
+}
+
+/// Recovers the payload key from the frame description of one chunk.
+pub(crate) fn key_from_description<'a>(description: &'a str, header: &ChunkHeader) -> &'a str {
+    description
+        .strip_suffix(&format!("#{}", header.sequence))
+        .unwrap_or(description)
+}
+
+/// Splits `data` into pieces of at most `max_frame_data` bytes, adding chunk headers only when
+/// more than one piece is needed.
+pub(crate) fn split(data: Vec<u8>, payload_id: u64, max_frame_data: usize) -> Vec<Vec<u8>> {
+    if data.len() <= max_frame_data {
+        return vec![data];
+    }
+    let max_chunk_data = max_frame_data - CHUNK_HEADER_LEN;
+    let total = data.len().div_ceil(max_chunk_data);
+    data.chunks(max_chunk_data)
+        .enumerate()
+        .map(|(sequence, piece)| {
+            let header = ChunkHeader {
+                payload_id,
+                sequence: sequence as u32,
+                total: total as u32,
+                length: data.len() as u64,
+            };
+            let mut chunk = Vec::with_capacity(CHUNK_HEADER_LEN + piece.len());
+            header.write(&mut chunk);
+            chunk.extend_from_slice(piece);
+            chunk
+        })
+        .collect()
+}
+
+/// Reassembles the chunks of one payload, checking that none are missing or inconsistent.
+///
+/// `chunks` must all share the same payload id; `key` is only used for error messages.
+pub(crate) fn join(key: &str, mut chunks: Vec<Piece<'_>>) -> Result<Vec<u8>> {
+    let invalid = |reason: &'static str| Error::InvalidChunks { key: key.to_string(), reason };
+    let (first, _) = match chunks.first() {
+        Some(chunk) => *chunk,
+        None => return Err(invalid("no chunks found")),
+    };
+    if chunks.iter().any(|(header, _)| header.total != first.total || header.length != first.length) {
+        return Err(invalid("chunks disagree on chunk count or payload length"));
+    }
+    // every chunk holds at least one byte of the payload
+    if first.total > MAX_CHUNKS || first.length > MAX_TAG_SIZE as u64 || first.total as u64 > first.length {
+        return Err(invalid("chunk count or payload length out of range"));
+    }
+
+    chunks.sort_by_key(|(header, _)| header.sequence);
+    chunks.dedup_by_key(|(header, _)| header.sequence);
+    let missing: Vec<u32> = (0..first.total)
+        .filter(|sequence| chunks.binary_search_by_key(sequence, |(header, _)| header.sequence).is_err())
+        .collect();
+    if !missing.is_empty() {
+        let missing_count = missing.len() as u32;
+        let missing = missing.into_iter().take(MAX_MISSING_REPORTED).collect();
+        return Err(Error::MissingChunks { key: key.to_string(), missing, missing_count, total: first.total });
+    }
+    if chunks.len() != first.total as usize {
+        return Err(invalid("chunk sequence number out of range"));
+    }
+
+    let data: Vec<u8> = chunks.into_iter().flat_map(|(_, piece)| piece.iter().copied()).collect();
+    if data.len() as u64 != first.length {
+        return Err(invalid("reassembled payload length does not match"));
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pieces(chunks: &[Vec<u8>]) -> Vec<Piece<'_>> {
+        chunks.iter().map(|chunk| ChunkHeader::parse(chunk).unwrap()).collect()
+    }
+
+    #[test]
+    fn split_and_join() {
+        let data: Vec<u8> = (0..1000u32).map(|value| value as u8).collect();
+        assert_eq!(split(data.clone(), 1, 1000), vec![data.clone()]);
+
+        let chunks = split(data.clone(), 1, 128);
+        assert_eq!(chunks.len(), 10);
+        assert!(chunks.iter().all(|chunk| chunk.len() <= 128));
+        let mut shuffled = pieces(&chunks);
+        shuffled.reverse();
+        assert_eq!(join("key", shuffled).unwrap(), data);
+    }
+
+    #[test]
+    fn reports_missing_chunks() {
+        let chunks = split(vec![7; 1000], 1, 128);
+        let mut found = pieces(&chunks);
+        found.remove(3);
+        found.remove(0);
+        match join("key", found) {
+            Err(Error::MissingChunks { missing, missing_count, total, .. }) => {
+                assert_eq!(missing, vec![0, 3]);
+                assert_eq!(missing_count, 2);
+                assert_eq!(total, 10);
+            }
+            other => panic!("expected missing chunks, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn bounds_the_chunk_count() {
+        // a forged header claiming u32::MAX chunks is refused before anything is listed
+        let header = ChunkHeader { payload_id: 1, sequence: 0, total: u32::MAX, length: u32::MAX as u64 };
+        let mut chunk = Vec::new();
+        header.write(&mut chunk);
+        chunk.push(0);
+        assert!(matches!(join("key", pieces(&[chunk])), Err(Error::InvalidChunks { .. })));
+
+        // at most MAX_MISSING_REPORTED of the missing sequence numbers are listed
+        let header = ChunkHeader { payload_id: 1, sequence: 0, total: MAX_CHUNKS, length: MAX_CHUNKS as u64 };
+        let mut chunk = Vec::new();
+        header.write(&mut chunk);
+        chunk.push(0);
+        match join("key", pieces(&[chunk])) {
+            Err(Error::MissingChunks { missing, missing_count, .. }) => {
+                assert_eq!(missing.len(), MAX_MISSING_REPORTED.min(MAX_CHUNKS as usize - 1));
+                assert_eq!(missing_count, MAX_CHUNKS - 1);
+            }
+            other => panic!("expected missing chunks, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::fmt;
+use std::io::Read;
+use flate2::Compression as DeflateLevel;
+use flate2::read::{DeflateDecoder, DeflateEncoder};
+use crate::error::{Error, Result};
+
+// Compressed payloads start with a small versioned header (all integers big endian):
+//
+// | Field     | Size | Value                     |
+// |-----------+------+---------------------------|
+// | magic     |    4 | b"I3SZ"                   |
+// | version   |    1 | 1                         |
+// | algorithm |    1 | 1 = zstd, 2 = deflate     |
+// | size      |    8 | original (plaintext) size |
+//
+// The header is followed by the compressed data (a zstd frame or a raw deflate stream).
+// Compression happens before encryption, so an encrypted payload hides this header too.
+//
+// Decompression stops at the original size recorded in the header, and that size may never be
+// larger than the extractor's limit (DEFAULT_MAX_DECOMPRESSED_SIZE unless raised, at most
+// MAX_DECOMPRESSED_SIZE), so a small crafted payload cannot expand into more than that
+// (decompression bomb).
+
+const COMPRESS_MAGIC: &[u8; 4] = b"I3SZ";
+const COMPRESS_VERSION: u8 = 1;
+const ALGORITHM_ZSTD: u8 = 1;
+const ALGORITHM_DEFLATE: u8 = 2;
+const HEADER_LEN: usize = 4 + 1 + 1 + 8;
+/// Most bytes reserved up front when decompressing (the recorded size is not trusted until the
+/// data decodes to it, the buffer grows as it does).
+const MAX_INITIAL_CAPACITY: usize = 1024 * 1024;
+
+/// Largest file that can be embedded with compression, and highest decompression limit an
+/// extractor can be given (10^6 used instead of 2^20, as for tags).
+pub const MAX_DECOMPRESSED_SIZE: usize = 1000 * 1000000;
+
+/// Most bytes an extractor decompresses per embedded file, unless raised with
+/// [`Extractor::max_decompressed_size`](crate::Extractor::max_decompressed_size).
+pub const DEFAULT_MAX_DECOMPRESSED_SIZE: usize = 64 * 1000000;
+
+/// Compression algorithms for embedded files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Zstandard (fast, usually smaller).
+    Zstd,
+    /// Raw deflate (as used by zip and gzip).
+    Deflate,
+}
+
+impl Compression {
+    fn id(&self) -> u8 {
+        match self {
+            Compression::Zstd => ALGORITHM_ZSTD,
+            Compression::Deflate => ALGORITHM_DEFLATE,
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Compression::Zstd => write!(f, "zstd"),
+            Compression::Deflate => write!(f, "deflate"),
+        }
+    }
+}
+
+/// Returns the algorithm named in the header if `data` starts with an id3stego compression header.
+pub(crate) fn compression(data: &[u8]) -> Option<Compression> {
+    if data.len() < HEADER_LEN || &data[..4] != COMPRESS_MAGIC || data[4] != COMPRESS_VERSION {
+        return None;
+    }
+    match data[5] {
+        ALGORITHM_ZSTD => Some(Compression::Zstd),
+        ALGORITHM_DEFLATE => Some(Compression::Deflate),
+        _ => None,
+    }
+}
+
+/// Compresses `data` with `algorithm`, prefixed with a compression header.
+pub(crate) fn compress(algorithm: Compression, data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() > MAX_DECOMPRESSED_SIZE {
+        return Err(Error::PayloadTooLarge { size: data.len(), max: MAX_DECOMPRESSED_SIZE });
+    }
+    let mut compressed = Vec::with_capacity(HEADER_LEN + data.len() / 2);
+    compressed.extend_from_slice(COMPRESS_MAGIC);
+    compressed.push(COMPRESS_VERSION);
+    compressed.push(algorithm.id());
+    compressed.extend_from_slice(&(data.len() as u64).to_be_bytes());
+    match algorithm {
+        Compression::Zstd => {
+            zstd::stream::copy_encode(data, &mut compressed, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+        }
+        Compression::Deflate => {
+            DeflateEncoder::new(data, DeflateLevel::best()).read_to_end(&mut compressed)?;
+        }
+    }
+    Ok(compressed)
+}
+
+/// Decompresses a payload produced by [`compress`], refusing original sizes above `max_size`.
+///
+/// `key` (the payload's frame description key) is only used for error messages.
+pub(crate) fn decompress(key: &str, data: &[u8], max_size: usize) -> Result<Vec<u8>> {
+    let invalid = |reason| Error::Decompression { key: key.to_string(), reason };
+    let algorithm = match compression(data) {
+        Some(algorithm) => algorithm,
+        None => return Err(invalid("unsupported compression header")),
+    };
+    let size = u64::from_be_bytes(data[6..14].try_into().unwrap_or_default());
+    if size > max_size.min(MAX_DECOMPRESSED_SIZE) as u64 {
+        return Err(invalid("original size is larger than the decompression limit"));
+    }
+
+    // read at most one byte more than the recorded size, to detect streams that keep going
+    let compressed = &data[HEADER_LEN..];
+    let mut decompressed = Vec::with_capacity((size as usize).min(MAX_INITIAL_CAPACITY));
+    let read = match algorithm {
+        Compression::Zstd => match zstd::stream::read::Decoder::new(compressed) {
+            Ok(decoder) => decoder.take(size + 1).read_to_end(&mut decompressed),
+            Err(err) => Err(err),
+        },
+        Compression::Deflate => DeflateDecoder::new(compressed).take(size + 1).read_to_end(&mut decompressed),
+    };
+    if read.is_err() {
+        return Err(invalid("compressed data is damaged"));
+    }
+    if decompressed.len() as u64 != size {
+        return Err(invalid("decompressed size does not match original size"));
+    }
+    Ok(decompressed)
+}
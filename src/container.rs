@@ -0,0 +1,368 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{Cursor, Read};
+use std::path::Path;
+use id3::{ErrorKind, Tag, TagLike, Version};
+use crate::carrier::{Carrier, Record, TextEncoding};
+use crate::error::{Error, Result};
+use crate::extract::is_id3stego_frame;
+use crate::flac;
+use crate::iff;
+use crate::lsb::{self, Capacity, EmbeddingStats, LsbCoding};
+use crate::mp4;
+use crate::mpeg;
+use crate::ogg;
+
+/// Largest frame written by id3stego (10^6 used instead of 2^20; 1,000,000 vs 1,048,576).
+///
+/// Larger files are split into chunks of this size. It stays below the frame size limit of every
+/// id3v2 version (see [`TagVersion::max_frame_size`]), since many players and tag editors
+/// handle very large frames badly.
+pub const MAX_FRAME_SIZE: usize = 16 * 1000000;
+
+/// Maximum id3v2 tag size (10^6 used instead of 2^20, as for frames).
+pub const MAX_TAG_SIZE: usize = 256 * 1000000;
+
+/// Number of leading bytes read to detect the format of an audio file.
+const DETECT_LEN: usize = 8192;
+
+/// id3v2 tag versions that can be written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagVersion {
+    /// id3v2.3, the most widely supported version (frame sizes are 32 bit integers).
+    Id3v23,
+    /// id3v2.4 (frame sizes are 28 bit "syncsafe" integers).
+    Id3v24,
+}
+
+impl TagVersion {
+    /// Version to write back for an existing tag: its own version (id3v2.2, which cannot be
+    /// written, becomes id3v2.3), or id3v2.4 for a new tag.
+    pub(crate) fn of(tag: Option<&Tag>) -> TagVersion {
+        match tag.map(Tag::version) {
+            Some(Version::Id3v22) | Some(Version::Id3v23) => TagVersion::Id3v23,
+            _ => TagVersion::Id3v24,
+        }
+    }
+
+    pub(crate) fn id3_version(&self) -> Version {
+        match self {
+            TagVersion::Id3v23 => Version::Id3v23,
+            TagVersion::Id3v24 => Version::Id3v24,
+        }
+    }
+
+    /// Largest frame (without its 10 byte header) the frame size field of this version can hold.
+    ///
+    /// Every frame must also fit into the tag, see [`MAX_TAG_SIZE`].
+    pub fn max_frame_size(&self) -> usize {
+        match self {
+            TagVersion::Id3v23 => u32::MAX as usize,
+            TagVersion::Id3v24 => (1 << 28) - 1,
+        }
+    }
+}
+
+impl fmt::Display for TagVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TagVersion::Id3v23 => write!(f, "id3v2.3"),
+            TagVersion::Id3v24 => write!(f, "id3v2.4"),
+        }
+    }
+}
+
+/// Audio container formats that can carry embedded files (in an id3v2 tag or natively).
+///
+/// The id3v2 tag of mp3, wav, and aiff files holds embedded files in GEOB frames by default, or
+/// in PRIV, TXXX, or COMM frames, and is written back in its own version (id3v2.3 or id3v2.4)
+/// unless a [`TagVersion`] is chosen. Other formats have no id3v2 tag and store embedded files
+/// in their own metadata (see [`Carrier`] for every carrier).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// id3v2 tag prepended to the mpeg audio stream, or embedded files hidden in the MPEG layer
+    /// III frames themselves (global gains or ancillary data, see mpeg.rs).
+    Mp3,
+    /// id3v2 tag stored in an `ID3 ` chunk of a RIFF/WAVE file, or embedded files stored in RIFF
+    /// chunks of their own ('I3SG' chunks or LIST/INFO entries, see iff.rs) or in the PCM samples.
+    Wav,
+    /// id3v2 tag stored in an `ID3 ` chunk of an AIFF/AIFF-C file, or embedded files stored in
+    /// APPL or ANNO chunks (see iff.rs) or in the PCM samples.
+    Aiff,
+    /// No id3v2 tag, embedded files are stored in FLAC APPLICATION (default) or PICTURE metadata
+    /// blocks (see flac.rs).
+    Flac,
+    /// No id3v2 tag, embedded files are stored in the ID3STEGO (default) or METADATA_BLOCK_PICTURE
+    /// Vorbis comments of an Ogg Vorbis or Opus stream (see ogg.rs).
+    Ogg,
+    /// No id3v2 tag, embedded files are stored in freeform ('----', default) or 'uuid' atoms of the
+    /// MP4 (m4a) movie atom (see mp4.rs).
+    Mp4,
+}
+
+impl Format {
+    /// Infers the container format from the leading bytes of an audio file.
+    pub fn detect(bytes: &[u8]) -> Result<Format> {
+        // infer only knows plain aiff, not aiff-c (form type 'AIFC', often compressed)
+        if bytes.len() >= 12 && &bytes[..4] == b"FORM" && &bytes[8..12] == b"AIFC" {
+            return Ok(Format::Aiff);
+        }
+        match infer::get(bytes) {
+            Some(kind) => Format::from_mime_type(kind.mime_type()),
+            // infer only knows mp3 files starting with an unprotected MPEG-1 layer III frame
+            None if mpeg::Header::parse(bytes).is_some() => Ok(Format::Mp3),
+            // no mimetype found
+            None => Err(Error::UnsupportedFormat(None)),
+        }
+    }
+
+    /// Infers the container format of the audio file at `path`.
+    pub fn detect_path(path: impl AsRef<Path>) -> Result<Format> {
+        // leading bytes only, as many as infer would read
+        let mut header = Vec::with_capacity(DETECT_LEN);
+        File::open(path)?.take(DETECT_LEN as u64).read_to_end(&mut header)?;
+        Format::detect(&header)
+    }
+
+    fn from_mime_type(mime_type: &str) -> Result<Format> {
+        match mime_type {
+            "audio/mpeg" => Ok(Format::Mp3),
+            "audio/x-wav" => Ok(Format::Wav),
+            "audio/x-aiff" => Ok(Format::Aiff),
+            "audio/x-flac" => Ok(Format::Flac),
+            // infer tells opus apart from other ogg streams
+            "audio/ogg" | "audio/opus" => Ok(Format::Ogg),
+            // audio-only mp4 files do not always use the m4a brand
+            "audio/m4a" | "video/mp4" => Ok(Format::Mp4),
+            // not of type mp3, wav, aiff, flac, ogg, or mp4
+            other => Err(Error::UnsupportedFormat(Some(other.to_string()))),
+        }
+    }
+
+    /// Mime-type reported by `infer` for this format.
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Format::Mp3 => "audio/mpeg",
+            Format::Wav => "audio/x-wav",
+            Format::Aiff => "audio/x-aiff",
+            Format::Flac => "audio/x-flac",
+            Format::Ogg => "audio/ogg",
+            Format::Mp4 => "audio/m4a",
+        }
+    }
+
+    /// Carrier used for embedded files unless another one is chosen.
+    pub fn default_carrier(&self) -> Carrier {
+        match self {
+            Format::Mp3 | Format::Wav | Format::Aiff => Carrier::Geob,
+            Format::Flac => Carrier::FlacApplication,
+            Format::Ogg => Carrier::OggComment,
+            Format::Mp4 => Carrier::Mp4Freeform,
+        }
+    }
+
+    /// Whether embedded files can be stored in `carrier` in this format.
+    pub fn supports(&self, carrier: Carrier) -> bool {
+        match self {
+            Format::Mp3 => carrier.in_id3_tag() || matches!(carrier, Carrier::Mp3GlobalGain | Carrier::Mp3Ancillary),
+            Format::Wav => carrier.in_id3_tag() || matches!(carrier, Carrier::WavChunk | Carrier::WavInfo | Carrier::PcmLsb),
+            Format::Aiff => carrier.in_id3_tag() || matches!(carrier, Carrier::AiffAppl | Carrier::AiffAnno | Carrier::PcmLsb),
+            Format::Flac => matches!(carrier, Carrier::FlacApplication | Carrier::FlacPicture),
+            Format::Ogg => matches!(carrier, Carrier::OggComment | Carrier::OggPicture),
+            Format::Mp4 => matches!(carrier, Carrier::Mp4Freeform | Carrier::Mp4Uuid),
+        }
+    }
+
+    /// Whether this format carries an id3v2 tag.
+    pub(crate) fn has_id3_tag(&self) -> bool {
+        !matches!(self, Format::Flac | Format::Ogg | Format::Mp4)
+    }
+
+    /// Reads every record stored by id3stego in an in-memory audio file, whatever its carrier.
+    pub(crate) fn read_records(&self, bytes: &[u8]) -> Result<Vec<Record>> {
+        let mut records = self.read_native_records(bytes)?;
+        // no tag means nothing was embedded in it
+        let tag = match self.read_tag(bytes)? {
+            Some(tag) => tag,
+            None => return Ok(records),
+        };
+        for frame in tag.frames().filter(|frame| is_id3stego_frame(frame)) {
+            if let Some(record) = Record::from_frame(frame) {
+                records.push(record?);
+            }
+        }
+        Ok(records)
+    }
+
+    /// Reads the records stored by id3stego in native carriers (outside the id3v2 tag).
+    pub(crate) fn read_native_records(&self, bytes: &[u8]) -> Result<Vec<Record>> {
+        match self {
+            Format::Wav | Format::Aiff => iff::read_records(bytes),
+            Format::Flac => flac::read_records(bytes),
+            Format::Ogg => ogg::read_records(bytes),
+            Format::Mp4 => mp4::read_records(bytes),
+            Format::Mp3 => mpeg::read_records(bytes),
+        }
+    }
+
+    /// Returns a copy of an in-memory audio file with `records` added in native carriers
+    /// (outside the id3v2 tag), text encoded with `encoding` where the carrier holds text.
+    pub(crate) fn add_native_records(&self, bytes: &[u8], records: &[Record], encoding: TextEncoding) -> Result<Vec<u8>> {
+        match self {
+            Format::Wav | Format::Aiff => iff::add_records(bytes, records, encoding),
+            Format::Flac => flac::add_records(bytes, records),
+            Format::Ogg => ogg::add_records(bytes, records, encoding),
+            Format::Mp4 => mp4::add_records(bytes, records),
+            Format::Mp3 => mpeg::add_records(bytes, records),
+        }
+    }
+
+    /// Returns a copy of an in-memory audio file without the records stored by id3stego in native
+    /// carriers, and the removed records (`None` for damaged ones, which are removed too).
+    pub(crate) fn remove_native_records(&self, bytes: &[u8]) -> Result<(Vec<u8>, Vec<Option<Record>>)> {
+        match self {
+            Format::Wav | Format::Aiff => iff::remove_records(bytes),
+            Format::Flac => flac::remove_records(bytes),
+            Format::Ogg => ogg::remove_records(bytes),
+            Format::Mp4 => mp4::remove_records(bytes),
+            Format::Mp3 => mpeg::remove_records(bytes),
+        }
+    }
+
+    /// Computes how much `carrier` (a carrier in the audio data, or the ancillary data of mp3
+    /// frames) can hide with `coding` in an in-memory audio file.
+    pub(crate) fn capacity(&self, bytes: &[u8], carrier: Carrier, coding: LsbCoding) -> Result<Capacity> {
+        match (self, carrier) {
+            (Format::Wav | Format::Aiff, Carrier::PcmLsb) => lsb::capacity(&iff::pcm_samples(bytes)?, coding),
+            (Format::Mp3, Carrier::Mp3GlobalGain) => lsb::capacity(&mpeg::global_gains(bytes)?, coding),
+            (Format::Mp3, Carrier::Mp3Ancillary) => mpeg::ancillary_capacity(bytes),
+            _ => Err(Error::UnsupportedCarrier { carrier, format: *self }),
+        }
+    }
+
+    /// Reads the records hidden with `stego_key` in the audio data of an in-memory audio file
+    /// (none if its audio data cannot hide anything).
+    pub(crate) fn read_audio_records(&self, bytes: &[u8], stego_key: &str) -> Result<Vec<Record>> {
+        let records = match self {
+            Format::Wav | Format::Aiff => iff::pcm_samples(bytes).and_then(|samples| lsb::read_records(bytes, &samples, stego_key)),
+            Format::Mp3 => mpeg::global_gains(bytes).and_then(|gains| lsb::read_records(bytes, &gains, stego_key)),
+            _ => Ok(Vec::new()),
+        };
+        match records {
+            Err(Error::UnsupportedSamples { .. }) => Ok(Vec::new()),
+            records => records,
+        }
+    }
+
+    /// Returns a copy of an in-memory audio file with `records` hidden in its audio data with
+    /// `stego_key` and `coding` (records already hidden with the same stego key are kept), and how
+    /// much the audio data changed.
+    pub(crate) fn add_audio_records(&self, bytes: &[u8], records: &[Record], stego_key: &str, coding: LsbCoding)
+        -> Result<(Vec<u8>, EmbeddingStats)> {
+        match self {
+            Format::Wav | Format::Aiff => lsb::add_records(bytes, &iff::pcm_samples(bytes)?, records, stego_key, coding),
+            Format::Mp3 => lsb::add_records(bytes, &mpeg::global_gains(bytes)?, records, stego_key, coding),
+            _ => match records.first() {
+                Some(record) => Err(Error::UnsupportedCarrier { carrier: record.carrier, format: *self }),
+                None => Ok((bytes.to_vec(), EmbeddingStats { bits: 0, changes: 0 })),
+            },
+        }
+    }
+
+    /// Reads the id3v2 tag from an in-memory audio file, if there is one.
+    pub(crate) fn read_tag(&self, bytes: &[u8]) -> Result<Option<Tag>> {
+        if !self.has_id3_tag() {
+            return Ok(None);
+        }
+        // the id3 crate finds the tag by itself (header for mp3, 'ID3 ' chunk for wav and aiff)
+        match Tag::read_from2(Cursor::new(bytes)) {
+            Ok(tag) => Ok(Some(tag)),
+            Err(id3::Error { kind: ErrorKind::NoTag, .. }) => Ok(None),
+            Err(err) => Err(Error::Tag(err)),
+        }
+    }
+
+    /// Returns the raw bytes of the id3v2 tag (header included) of an in-memory audio file, if
+    /// there is one.
+    pub(crate) fn raw_tag<'a>(&self, bytes: &'a [u8]) -> Option<&'a [u8]> {
+        match self {
+            Format::Mp3 => match mpeg::id3v2_len(bytes) {
+                0 => None,
+                len => Some(&bytes[..len]),
+            },
+            Format::Wav | Format::Aiff => iff::id3_chunk(bytes).filter(|tag| tag.starts_with(b"ID3")),
+            Format::Flac | Format::Ogg | Format::Mp4 => None,
+        }
+    }
+
+    /// Duration of the audio of an in-memory audio file in seconds, if it can be worked out
+    /// (mp3, wav, and aiff files).
+    pub(crate) fn duration(&self, bytes: &[u8]) -> Option<f64> {
+        match self {
+            Format::Mp3 => mpeg::duration(bytes).ok(),
+            Format::Wav | Format::Aiff => iff::duration(bytes).ok(),
+            Format::Flac | Format::Ogg | Format::Mp4 => None,
+        }
+    }
+
+    /// Returns a copy of an in-memory audio file with its id3v2 tag replaced by `tag`, written
+    /// as `version`.
+    pub(crate) fn write_tag(&self, bytes: &[u8], tag: &Tag, version: TagVersion) -> Result<Vec<u8>> {
+        let mut encoded_tag = Vec::new();
+        tag.write_to(&mut encoded_tag, version.id3_version())?;
+        if encoded_tag.len() > MAX_TAG_SIZE {
+            return Err(Error::TagTooLarge { size: encoded_tag.len(), max: MAX_TAG_SIZE });
+        }
+        // the id3 crate silently truncates frame sizes that do not fit the version's size field,
+        // so check each frame (only needed if the whole tag is larger than one frame may be)
+        if encoded_tag.len() > version.max_frame_size() {
+            check_frame_sizes(tag, version)?;
+        }
+        match self {
+            Format::Mp3 => {
+                // tag is always at the start of an mp3, replace everything up to the end of the old one
+                // (skip leaves the cursor wherever it stopped looking if there is no tag)
+                let mut cursor = Cursor::new(bytes);
+                let audio_start = match Tag::skip(&mut cursor)? {
+                    true => cursor.position() as usize,
+                    false => 0,
+                };
+                encoded_tag.extend_from_slice(&bytes[audio_start..]);
+                Ok(encoded_tag)
+            }
+            Format::Wav | Format::Aiff => iff::write_id3_chunk(bytes, Some(&encoded_tag)),
+            Format::Flac | Format::Ogg | Format::Mp4 => Err(Error::UnsupportedCarrier { carrier: Carrier::Geob, format: *self }),
+        }
+    }
+
+    /// Returns a copy of an in-memory audio file without its id3v2 tag.
+    pub(crate) fn remove_tag(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Format::Mp3 => {
+                let mut cursor = Cursor::new(bytes);
+                match Tag::skip(&mut cursor)? {
+                    true => Ok(bytes[cursor.position() as usize..].to_vec()),
+                    false => Ok(bytes.to_vec()),
+                }
+            }
+            Format::Wav | Format::Aiff => iff::write_id3_chunk(bytes, None),
+            Format::Flac | Format::Ogg | Format::Mp4 => Ok(bytes.to_vec()),
+        }
+    }
+}
+
+fn check_frame_sizes(tag: &Tag, version: TagVersion) -> Result<()> {
+    // frames are encoded one at a time as id3v2.3, whose 32 bit sizes hold every frame that fits
+    // in memory, then measured without the tag and frame headers (10 bytes each)
+    for frame in tag.frames() {
+        let mut single = Tag::with_version(Version::Id3v23);
+        single.add_frame(frame.clone());
+        let mut encoded_frame = Vec::new();
+        single.write_to(&mut encoded_frame, Version::Id3v23)?;
+        let size = encoded_frame.len().saturating_sub(20);
+        if size > version.max_frame_size() {
+            return Err(Error::FrameTooLarge { frame_id: frame.id().to_string(), size, max: version.max_frame_size(), version });
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,264 @@
+use argon2::{Algorithm, Argon2, Params};
+use chacha20poly1305::{KeyInit, XChaCha20Poly1305, XNonce};
+use chacha20poly1305::aead::{Aead, Payload};
+use hkdf::Hkdf;
+use rand::RngCore;
+use rand::rngs::OsRng;
+use sha2::Sha256;
+use x25519_dalek::{EphemeralSecret, PublicKey};
+use crate::error::{Error, Result};
+use crate::keys::{Identity, Recipient};
+
+// Encrypted payloads start with a small versioned header (all integers big endian):
+//
+// | Field   | Size | Value                                                       |
+// |---------+------+-------------------------------------------------------------|
+// | magic   |    4 | b"I3SE"                                                     |
+// | version |    1 | 1                                                           |
+// | method  |    1 | 1 = passphrase (argon2id, xchacha20-poly1305)               |
+// |         |      | 2 = recipients (x25519, hkdf-sha256, xchacha20-poly1305)    |
+//
+// Passphrase (method 1) header continues with:
+//
+// | m_cost  |    4 | argon2 memory cost in KiB                                   |
+// | t_cost  |    4 | argon2 iterations                                           |
+// | p_cost  |    4 | argon2 parallelism                                          |
+// | salt    |   16 | random                                                      |
+// | nonce   |   24 | random                                                      |
+//
+// Recipients (method 2) header continues with (similar to age):
+//
+// | count   |    2 | number of recipient stanzas                                 |
+// | stanzas | 80 x | per recipient: ephemeral x25519 public key (32) followed by |
+// |         |count | the file key wrapped for that recipient (32 + 16 byte tag)  |
+// | nonce   |   24 | random                                                      |
+//
+// Each stanza wraps the random file key with xchacha20-poly1305 (all zero nonce, the wrapping key
+// is never reused) under hkdf-sha256(ikm = x25519 shared secret, salt = ephemeral public key +
+// recipient public key, info = "id3stego x25519").
+//
+// The header is followed by the xchacha20-poly1305 ciphertext (plaintext length + 16 byte tag).
+// The whole header is authenticated as associated data, so tampering with the kdf parameters
+// or stanzas is detected the same way as tampering with the ciphertext.
+
+const CRYPTO_MAGIC: &[u8; 4] = b"I3SE";
+const CRYPTO_VERSION: u8 = 1;
+const METHOD_PASSPHRASE: u8 = 1;
+const METHOD_RECIPIENTS: u8 = 2;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const KEY_LEN: usize = 32;
+const TAG_LEN: usize = 16;
+const PASSPHRASE_HEADER_LEN: usize = 4 + 1 + 1 + 12 + SALT_LEN + NONCE_LEN;
+const STANZA_LEN: usize = 32 + KEY_LEN + TAG_LEN;
+const WRAP_INFO: &[u8] = b"id3stego x25519";
+
+// refuse kdf parameters that would take unreasonable time or memory to check (max 2 GiB)
+const MAX_M_COST: u32 = 2 * 1024 * 1024;
+const MAX_T_COST: u32 = 64;
+const MAX_P_COST: u32 = 64;
+
+/// A passphrase, kept out of `Debug` output.
+#[derive(Clone)]
+pub(crate) struct Passphrase(pub String);
+
+impl std::fmt::Debug for Passphrase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Passphrase(..)")
+    }
+}
+
+/// How an embedded file was encrypted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncryptionMethod {
+    /// Key derived from a passphrase.
+    Passphrase,
+    /// Random key, wrapped for one or more x25519 recipients.
+    Recipients,
+}
+
+/// Returns true if `data` starts with an id3stego encryption header.
+pub(crate) fn is_encrypted(data: &[u8]) -> bool {
+    data.len() >= 6 && &data[..4] == CRYPTO_MAGIC
+}
+
+/// Encryption method named in the header of an encrypted payload.
+pub(crate) fn method(data: &[u8]) -> Option<EncryptionMethod> {
+    if !is_encrypted(data) {
+        return None;
+    }
+    match data[5] {
+        METHOD_PASSPHRASE => Some(EncryptionMethod::Passphrase),
+        METHOD_RECIPIENTS => Some(EncryptionMethod::Recipients),
+        _ => None,
+    }
+}
+
+/// Encrypts `plaintext` with a key derived from `passphrase`, returning header + ciphertext.
+pub(crate) fn seal_with_passphrase(passphrase: &str, plaintext: &[u8]) -> Result<Vec<u8>> {
+    let mut salt = [0; SALT_LEN];
+    let mut nonce = [0; NONCE_LEN];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let params = Params::default();
+    let mut header = Vec::with_capacity(PASSPHRASE_HEADER_LEN);
+    header.extend_from_slice(CRYPTO_MAGIC);
+    header.push(CRYPTO_VERSION);
+    header.push(METHOD_PASSPHRASE);
+    header.extend_from_slice(&params.m_cost().to_be_bytes());
+    header.extend_from_slice(&params.t_cost().to_be_bytes());
+    header.extend_from_slice(&params.p_cost().to_be_bytes());
+    header.extend_from_slice(&salt);
+    header.extend_from_slice(&nonce);
+
+    let key = derive_key(passphrase, &salt, params)
+        .ok_or(Error::Encryption("unable to derive key from passphrase"))?;
+    let ciphertext = XChaCha20Poly1305::new(&key.into())
+        .encrypt(XNonce::from_slice(&nonce), Payload { msg: plaintext, aad: &header })
+        .map_err(|_| Error::Encryption("unable to encrypt payload"))?;
+
+    header.extend_from_slice(&ciphertext);
+    Ok(header)
+}
+
+/// Encrypts `plaintext` with a random file key, wrapped for each of `recipients`.
+pub(crate) fn seal_to_recipients(recipients: &[Recipient], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let count = u16::try_from(recipients.len())
+        .map_err(|_| Error::Encryption("too many recipients"))?;
+    if count == 0 {
+        return Err(Error::Encryption("no recipients"));
+    }
+    let mut file_key = [0; KEY_LEN];
+    let mut nonce = [0; NONCE_LEN];
+    OsRng.fill_bytes(&mut file_key);
+    OsRng.fill_bytes(&mut nonce);
+
+    let mut header = Vec::with_capacity(8 + STANZA_LEN * recipients.len() + NONCE_LEN);
+    header.extend_from_slice(CRYPTO_MAGIC);
+    header.push(CRYPTO_VERSION);
+    header.push(METHOD_RECIPIENTS);
+    header.extend_from_slice(&count.to_be_bytes());
+    for recipient in recipients {
+        let ephemeral_secret = EphemeralSecret::random_from_rng(OsRng);
+        let ephemeral_public = PublicKey::from(&ephemeral_secret);
+        let shared_secret = ephemeral_secret.diffie_hellman(&recipient.0);
+        if !shared_secret.was_contributory() {
+            return Err(Error::Encryption("recipient public key is invalid"));
+        }
+        let wrapping_key = wrapping_key(shared_secret.as_bytes(), &ephemeral_public, &recipient.0);
+        let wrapped_key = XChaCha20Poly1305::new(&wrapping_key.into())
+            .encrypt(&XNonce::default(), file_key.as_slice())
+            .map_err(|_| Error::Encryption("unable to wrap file key"))?;
+        header.extend_from_slice(ephemeral_public.as_bytes());
+        header.extend_from_slice(&wrapped_key);
+    }
+    header.extend_from_slice(&nonce);
+
+    let ciphertext = XChaCha20Poly1305::new(&file_key.into())
+        .encrypt(XNonce::from_slice(&nonce), Payload { msg: plaintext, aad: &header })
+        .map_err(|_| Error::Encryption("unable to encrypt payload"))?;
+
+    header.extend_from_slice(&ciphertext);
+    Ok(header)
+}
+
+/// Decrypts a payload produced by [`seal_with_passphrase`] or [`seal_to_recipients`].
+///
+/// `key` (the payload's frame description key) is only used for error messages.
+pub(crate) fn open(key: &str, data: &[u8], passphrase: Option<&str>, identities: &[Identity]) -> Result<Vec<u8>> {
+    let invalid = |reason| Error::InvalidHeader { key: key.to_string(), reason };
+    if !is_encrypted(data) {
+        return Err(invalid("not an encrypted payload"));
+    }
+    if data[4] != CRYPTO_VERSION {
+        return Err(invalid("unsupported encryption header version"));
+    }
+    match data[5] {
+        METHOD_PASSPHRASE => open_with_passphrase(key, data, passphrase),
+        METHOD_RECIPIENTS => open_with_identities(key, data, identities),
+        _ => Err(invalid("unsupported encryption method")),
+    }
+}
+
+fn open_with_passphrase(key: &str, data: &[u8], passphrase: Option<&str>) -> Result<Vec<u8>> {
+    let invalid = |reason| Error::InvalidHeader { key: key.to_string(), reason };
+    if data.len() < PASSPHRASE_HEADER_LEN {
+        return Err(invalid("encryption header is truncated"));
+    }
+    let passphrase = match passphrase {
+        Some(passphrase) => passphrase,
+        None => return Err(Error::PassphraseRequired { key: key.to_string() }),
+    };
+
+    let (header, ciphertext) = data.split_at(PASSPHRASE_HEADER_LEN);
+    let read_u32 = |offset: usize| u32::from_be_bytes([header[offset], header[offset + 1], header[offset + 2], header[offset + 3]]);
+    let (m_cost, t_cost, p_cost) = (read_u32(6), read_u32(10), read_u32(14));
+    if m_cost > MAX_M_COST || t_cost > MAX_T_COST || p_cost > MAX_P_COST {
+        return Err(invalid("key derivation parameters out of range"));
+    }
+    let params = Params::new(m_cost, t_cost, p_cost, Some(KEY_LEN))
+        .map_err(|_| invalid("key derivation parameters out of range"))?;
+    let salt = &header[18..18 + SALT_LEN];
+    let nonce = &header[18 + SALT_LEN..];
+
+    let derived_key = derive_key(passphrase, salt, params).ok_or_else(|| invalid("unable to derive key"))?;
+    XChaCha20Poly1305::new(&derived_key.into())
+        .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: header })
+        .map_err(|_| Error::Decryption { key: key.to_string() })
+}
+
+fn open_with_identities(key: &str, data: &[u8], identities: &[Identity]) -> Result<Vec<u8>> {
+    let invalid = |reason| Error::InvalidHeader { key: key.to_string(), reason };
+    if data.len() < 8 {
+        return Err(invalid("encryption header is truncated"));
+    }
+    let count = u16::from_be_bytes([data[6], data[7]]) as usize;
+    let header_len = 8 + STANZA_LEN * count + NONCE_LEN;
+    if count == 0 || data.len() < header_len {
+        return Err(invalid("encryption header is truncated"));
+    }
+    if identities.is_empty() {
+        return Err(Error::IdentityRequired { key: key.to_string() });
+    }
+
+    // try every identity against every stanza until one unwraps the file key
+    let (header, ciphertext) = data.split_at(header_len);
+    let stanzas = header[8..8 + STANZA_LEN * count].chunks(STANZA_LEN);
+    let file_key = stanzas
+        .flat_map(|stanza| identities.iter().map(move |identity| (stanza, identity)))
+        .find_map(|(stanza, identity)| {
+            let ephemeral_public = PublicKey::from(<[u8; 32]>::try_from(&stanza[..32]).ok()?);
+            let shared_secret = identity.0.diffie_hellman(&ephemeral_public);
+            let wrapping_key = wrapping_key(shared_secret.as_bytes(), &ephemeral_public, &identity.recipient().0);
+            XChaCha20Poly1305::new(&wrapping_key.into())
+                .decrypt(&XNonce::default(), &stanza[32..])
+                .ok()
+        })
+        .ok_or_else(|| Error::NoMatchingIdentity { key: key.to_string() })?;
+    let file_key = <[u8; KEY_LEN]>::try_from(file_key.as_slice())
+        .map_err(|_| invalid("wrapped file key has wrong length"))?;
+
+    let nonce = &header[header_len - NONCE_LEN..];
+    XChaCha20Poly1305::new(&file_key.into())
+        .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: header })
+        .map_err(|_| Error::Decryption { key: key.to_string() })
+}
+
+fn wrapping_key(shared_secret: &[u8], ephemeral_public: &PublicKey, recipient: &PublicKey) -> [u8; KEY_LEN] {
+    let mut salt = [0; 64];
+    salt[..32].copy_from_slice(ephemeral_public.as_bytes());
+    salt[32..].copy_from_slice(recipient.as_bytes());
+    let mut wrapping_key = [0; KEY_LEN];
+    // expanding 32 bytes from hkdf-sha256 cannot fail (limit is 255 * 32 bytes)
+    let _ = Hkdf::<Sha256>::new(Some(&salt), shared_secret).expand(WRAP_INFO, &mut wrapping_key);
+    wrapping_key
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], params: Params) -> Option<[u8; KEY_LEN]> {
+    let mut key = [0; KEY_LEN];
+    Argon2::new(Algorithm::Argon2id, argon2::Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .ok()?;
+    Some(key)
+}
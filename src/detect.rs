@@ -0,0 +1,427 @@
+use std::fmt;
+use std::io::{Read, Seek};
+use std::ops::Range;
+use std::path::Path;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use id3::{Content, Frame, Tag};
+use crate::carrier::{self, TextEncoding};
+use crate::container::Format;
+use crate::error::Result;
+use crate::mpeg;
+use crate::storage;
+
+// Detect mode looks for the traces that hiding data in an id3v2 tag usually leaves, whether or
+// not the data was hidden by id3stego:
+//  - entropy: GEOB, PRIV, TXXX, and COMM payloads (TXXX and COMM values decoded first if they
+//    are base64 or base85 text) whose bytes are spread almost evenly over all 256 values, like
+//    encrypted or compressed data, rather than like text or ordinary metadata
+//  - mime type: GEOB and APIC frames whose declared mime type does not match the signature
+//    infer sniffs in their data
+//  - tag size: tags holding many more bytes of frames (pictures aside) than the duration of
+//    their audio calls for
+//  - padding: non-zero or unusually large padding after the last frame of the tag, and bytes
+//    between the tag and the first frame of an mp3 file
+//
+// Each finding is scored from 1 (hardly suspicious) to 100 (almost certainly hidden data). The
+// scores are heuristics: ordinary files can trigger them (a compressed file in a GEOB frame looks
+// random, some taggers leave large padding), and careful hiding can avoid them.
+
+const ID3V2_HEADER_LEN: usize = 10;
+
+/// Payloads shorter than this are not scored for entropy (too few bytes to tell).
+const MIN_ENTROPY_LEN: usize = 64;
+/// Share of the highest entropy possible for a payload's length above which it is flagged.
+const ENTROPY_THRESHOLD: f64 = 0.9;
+/// infer needs a few bytes to find a signature (and never returns for 1 or 2 byte buffers).
+const MIN_SNIFF_LEN: usize = 16;
+/// Tag bytes per second of audio (pictures and padding aside) above which tags are flagged; the
+/// score grows with every doubling of the rate.
+const TAG_RATE_THRESHOLD: f64 = 256.0;
+/// Tags smaller than this are never flagged as oversized, however short their audio.
+const MIN_TAG_SIZE: usize = 4096;
+/// Zeroed padding larger than this is flagged as unusually large.
+const LARGE_PADDING: usize = 16384;
+
+/// Kind of trace a [`Finding`] is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    /// GEOB, PRIV, TXXX, or COMM payload that looks random (encrypted or compressed).
+    Entropy,
+    /// GEOB or APIC frame whose declared mime type does not match its data.
+    MimeType,
+    /// Tag much larger than the duration of the audio calls for.
+    TagSize,
+    /// Non-zero or unusually large padding, or bytes between the tag and the audio.
+    Padding,
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Check::Entropy => write!(f, "entropy"),
+            Check::MimeType => write!(f, "mime-type"),
+            Check::TagSize => write!(f, "tag-size"),
+            Check::Padding => write!(f, "padding"),
+        }
+    }
+}
+
+/// One suspicious trace found by a [`Detector`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub check: Check,
+    /// Frame the finding is about (its id, then its description or owner identifier), if any.
+    pub frame: Option<String>,
+    /// How suspicious the trace is, from 1 (hardly) to 100 (almost certainly hidden data).
+    pub score: u8,
+    /// Why the trace is suspicious.
+    pub explanation: String,
+}
+
+/// Describes the id3v2 tag of an audio file scanned by a [`Detector`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Detection {
+    pub format: Format,
+    /// Size of the id3v2 tag in bytes (header included), or none if there is no tag.
+    pub tag_size: Option<usize>,
+    /// Duration of the audio in seconds, if it could be worked out (mp3, wav, and aiff files).
+    pub duration: Option<f64>,
+    /// Suspicious traces, highest score first.
+    pub findings: Vec<Finding>,
+}
+
+/// Scans the id3v2 tag of mp3, wav, and aiff files for traces of hidden data, whether or not
+/// it was hidden by id3stego (flac, ogg, and m4a files have no id3v2 tag to scan).
+#[derive(Debug, Clone)]
+pub struct Detector {
+    min_score: u8,
+}
+
+impl Default for Detector {
+    fn default() -> Detector {
+        Detector { min_score: 1 }
+    }
+}
+
+impl Detector {
+    pub fn new() -> Detector {
+        Detector::default()
+    }
+
+    /// Only reports findings scoring at least `min_score` (every finding by default).
+    pub fn min_score(mut self, min_score: u8) -> Detector {
+        self.min_score = min_score;
+        self
+    }
+
+    /// Scans the id3v2 tag of the audio file at `audio_path`.
+    pub fn detect_path(&self, audio_path: impl AsRef<Path>) -> Result<Detection> {
+        let audio = std::fs::read(audio_path)?;
+        self.detect_bytes(&audio)
+    }
+
+    /// Scans the id3v2 tag of the audio file held by `audio`.
+    pub fn detect<R: Read + Seek>(&self, mut audio: R) -> Result<Detection> {
+        let bytes = storage::read_all(&mut audio)?;
+        self.detect_bytes(&bytes)
+    }
+
+    fn detect_bytes(&self, audio: &[u8]) -> Result<Detection> {
+        let format = Format::detect(audio)?;
+        let raw_tag = format.raw_tag(audio);
+        // tags the id3 crate cannot read are still checked for their size and padding
+        let tag = format.read_tag(audio).unwrap_or(None);
+        let duration = format.duration(audio);
+
+        let mut findings = Vec::new();
+        for frame in tag.iter().flat_map(Tag::frames) {
+            check_frame(frame, &mut findings);
+        }
+        if let Some(raw_tag) = raw_tag {
+            // pictures are often large, and already checked against their mime type (padding is
+            // checked on its own)
+            let pictures: usize = tag.iter().flat_map(Tag::pictures).map(|picture| picture.data.len()).sum();
+            let padding = padding(raw_tag).map_or(0, |padding| padding.len());
+            if let Some(duration) = duration {
+                check_tag_size(raw_tag.len().saturating_sub(pictures + padding), duration, &mut findings);
+            }
+            check_padding(raw_tag, &mut findings);
+        }
+        if format == Format::Mp3 {
+            check_leading_bytes(audio, &mut findings);
+        }
+
+        findings.retain(|finding| finding.score >= self.min_score);
+        // stable sort, findings of equal score stay in tag order
+        findings.sort_by_key(|finding| std::cmp::Reverse(finding.score));
+        Ok(Detection { format, tag_size: raw_tag.map(<[u8]>::len), duration, findings })
+    }
+}
+
+fn check_frame(frame: &Frame, findings: &mut Vec<Finding>) {
+    let label = frame_label(frame);
+    match frame.content() {
+        Content::EncapsulatedObject(object) => {
+            check_entropy(&label, "encapsulated object", &object.data, findings);
+            check_mime_type(&label, &object.mime_type, &object.data, findings);
+        }
+        Content::Private(private) => check_entropy(&label, "private data", &private.private_data, findings),
+        Content::ExtendedText(text) => check_text_entropy(&label, &text.value, findings),
+        Content::Comment(comment) => check_text_entropy(&label, &comment.text, findings),
+        // "-->" pictures only hold a link to the picture
+        Content::Picture(picture) if picture.mime_type != "-->" => {
+            check_mime_type(&label, &picture.mime_type, &picture.data, findings);
+        }
+        _ => {}
+    }
+}
+
+fn frame_label(frame: &Frame) -> String {
+    let key = match frame.content() {
+        Content::Picture(picture) => Some(picture.description.as_str()),
+        _ => carrier::frame_key(frame),
+    };
+    match key {
+        Some(key) if !key.is_empty() => format!("{} \"{}\"", frame.id(), key),
+        _ => frame.id().to_string(),
+    }
+}
+
+fn check_text_entropy(label: &str, text: &str, findings: &mut Vec<Finding>) {
+    // binary data hidden in text frames has to be encoded as text first, so decode it back
+    // (base64 or base85 written by id3stego, or plain base64 written by other programs)
+    let trimmed = text.trim();
+    let plain_base64 = trimmed.len() >= MIN_ENTROPY_LEN
+        && trimmed.bytes().all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'+' | b'/' | b'='));
+    match TextEncoding::decode(trimmed) {
+        Some(decoded) => check_entropy(label, "decoded text", &decoded, findings),
+        None => match plain_base64.then(|| BASE64.decode(trimmed).ok()).flatten() {
+            Some(decoded) => check_entropy(label, "base64 decoded text", &decoded, findings),
+            None => check_entropy(label, "text", text.as_bytes(), findings),
+        },
+    }
+}
+
+fn check_entropy(label: &str, what: &str, data: &[u8], findings: &mut Vec<Finding>) {
+    if data.len() < MIN_ENTROPY_LEN {
+        return;
+    }
+    // n bytes hold at most log2(n) bits of entropy per byte (8 from 256 bytes on)
+    let bits = entropy(data);
+    let share = (bits / (data.len() as f64).log2().min(8.0)).min(1.0);
+    if share < ENTROPY_THRESHOLD {
+        return;
+    }
+    let mut score = score((share - ENTROPY_THRESHOLD) / (1.0 - ENTROPY_THRESHOLD) * 100.0);
+    let mut explanation = format!(
+        "{} bytes of {} have an entropy of {:.2} bits per byte ({:.0}% of the most possible): \
+         encrypted or compressed data looks random, text and ordinary metadata do not",
+        data.len(), what, bits, share * 100.0
+    );
+    if let Some(sniffed) = sniff(data) {
+        // media and archive files are compressed, so they look random too
+        score = (score / 4).max(1);
+        explanation.push_str(&format!(", but this is {} data, which is compressed anyway", sniffed));
+    }
+    findings.push(Finding { check: Check::Entropy, frame: Some(label.to_string()), score, explanation });
+}
+
+/// Shannon entropy of `data` in bits per byte, with the Miller-Madow correction (the plain
+/// estimate is too low for short data, which cannot hold every byte value).
+fn entropy(data: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for byte in data {
+        counts[*byte as usize] += 1;
+    }
+    let len = data.len() as f64;
+    let observed = counts.iter().filter(|count| **count > 0).count() as f64;
+    let plain: f64 = counts
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let probability = *count as f64 / len;
+            -probability * probability.log2()
+        })
+        .sum();
+    plain + (observed - 1.0) / (2.0 * len * std::f64::consts::LN_2)
+}
+
+fn check_mime_type(label: &str, declared: &str, data: &[u8], findings: &mut Vec<Finding>) {
+    if data.len() < MIN_SNIFF_LEN {
+        return;
+    }
+    let declared = canonical_mime_type(declared);
+    let generic = declared.is_empty() || declared == "application/octet-stream";
+    let (score, explanation) = match sniff(data) {
+        Some(sniffed) if sniffed == declared => return,
+        Some(sniffed) if generic => (30, format!(
+            "declares {} but holds {} data: the writer did not look at the data, or did not want to say what it is",
+            if declared.is_empty() { "no mime type".to_string() } else { format!("'{}'", declared) }, sniffed
+        )),
+        Some(sniffed) => (80, format!("declares '{}' but holds {} data (found by its signature)", declared, sniffed)),
+        // types infer does not know (text among them) have no signature to miss
+        None if generic || !infer::is_mime_supported(&declared) => return,
+        None => (60, format!(
+            "declares '{}' but its data has no {} signature: it may be encrypted, compressed, or wrapped",
+            declared, declared
+        )),
+    };
+    findings.push(Finding { check: Check::MimeType, frame: Some(label.to_string()), score, explanation });
+}
+
+/// Mime type infer finds by the signature at the start of `data`, if any.
+fn sniff(data: &[u8]) -> Option<&'static str> {
+    match data.len() < MIN_SNIFF_LEN {
+        true => None,
+        false => infer::get(data).map(|kind| kind.mime_type()),
+    }
+}
+
+fn canonical_mime_type(mime_type: &str) -> String {
+    // aliases for the types infer reports, and the picture formats of id3v2.2 APIC frames
+    let mime_type = mime_type.trim().to_ascii_lowercase();
+    match mime_type.as_str() {
+        "jpg" | "jpeg" | "image/jpg" | "image/pjpeg" => "image/jpeg".to_string(),
+        "png" => "image/png".to_string(),
+        "audio/mp3" | "audio/x-mp3" | "audio/mpeg3" | "audio/x-mpeg" => "audio/mpeg".to_string(),
+        "audio/wav" | "audio/wave" | "audio/vnd.wave" => "audio/x-wav".to_string(),
+        "audio/aiff" => "audio/x-aiff".to_string(),
+        "audio/flac" => "audio/x-flac".to_string(),
+        "application/x-zip-compressed" => "application/zip".to_string(),
+        _ => mime_type,
+    }
+}
+
+fn check_tag_size(size: usize, duration: f64, findings: &mut Vec<Finding>) {
+    if size < MIN_TAG_SIZE || duration <= 0.0 {
+        return;
+    }
+    let rate = size as f64 / duration;
+    if rate <= TAG_RATE_THRESHOLD {
+        return;
+    }
+    findings.push(Finding {
+        check: Check::TagSize,
+        frame: None,
+        // 25 more for every doubling of the rate
+        score: score((rate / TAG_RATE_THRESHOLD).log2() * 25.0),
+        explanation: format!(
+            "tag holds {} bytes of frames besides pictures for {:.1} seconds of audio, {:.0} bytes per second \
+             (ordinary metadata rarely needs more than {:.0})",
+            size, duration, rate, TAG_RATE_THRESHOLD
+        ),
+    });
+}
+
+fn check_padding(tag: &[u8], findings: &mut Vec<Finding>) {
+    let padding = match padding(tag) {
+        Some(padding) if !padding.is_empty() => &tag[padding],
+        _ => return,
+    };
+    let non_zero = padding.iter().filter(|byte| **byte != 0).count();
+    let (score, explanation) = match non_zero {
+        0 if padding.len() > LARGE_PADDING => (
+            // 15 more for every doubling of the padding, up to 60
+            score((padding.len() as f64 / LARGE_PADDING as f64).log2() * 15.0 + 10.0).min(60),
+            format!(
+                "{} bytes of padding after the last frame: taggers usually leave a few kilobytes at most, \
+                 more is room for data that players never read",
+                padding.len()
+            ),
+        ),
+        0 => return,
+        _ => (
+            if non_zero >= 16 { 90 } else { 50 },
+            format!(
+                "{} of the {} bytes after the last frame are not zero: padding must be zeros, and frames \
+                 that cannot be read are skipped by players",
+                non_zero, padding.len()
+            ),
+        ),
+    };
+    findings.push(Finding { check: Check::Padding, frame: None, score, explanation });
+}
+
+/// Padding of the raw id3v2 tag `tag` (from the end of its last frame to the end of the tag,
+/// footer excluded), or none if the frames cannot be walked.
+fn padding(tag: &[u8]) -> Option<Range<usize>> {
+    if tag.len() < ID3V2_HEADER_LEN || &tag[..3] != b"ID3" {
+        return None;
+    }
+    let (major, flags) = (tag[3], tag[5]);
+    // frame sizes of unsynchronised tags before id3v2.4 count the bytes before unsynchronisation,
+    // and compressed id3v2.2 tags have no frames to walk
+    if !(2..=4).contains(&major) || (major < 4 && flags & 0x80 != 0) || (major == 2 && flags & 0x40 != 0) {
+        return None;
+    }
+    let end = (ID3V2_HEADER_LEN + syncsafe(&tag[6..10])).min(tag.len());
+    let mut start = ID3V2_HEADER_LEN;
+    if major > 2 && flags & 0x40 != 0 {
+        // extended header size excludes its own size field in id3v2.3, includes it in id3v2.4
+        let size = tag.get(start..start + 4)?;
+        start += match major {
+            3 => 4 + size.iter().fold(0, |size, byte| (size << 8) | *byte as usize),
+            _ => syncsafe(size),
+        };
+    }
+    let frames_end = match major {
+        2 => walk_frames(tag, start, end, 3, false),
+        3 => walk_frames(tag, start, end, 4, false),
+        // some programs write id3v2.4 frame sizes as plain integers, as in id3v2.3
+        _ => walk_frames(tag, start, end, 4, true).max(walk_frames(tag, start, end, 4, false)),
+    };
+    Some(frames_end.min(end)..end)
+}
+
+/// Offset of the end of the last frame starting at `position` (the start of the padding), with
+/// frame ids and sizes of `id_len` bytes (3 for id3v2.2, 4 for later versions).
+fn walk_frames(tag: &[u8], mut position: usize, end: usize, id_len: usize, syncsafe_sizes: bool) -> usize {
+    // id, size, and (after id3v2.2) 2 bytes of flags
+    let header_len = if id_len == 3 { 6 } else { 10 };
+    while position + header_len <= end {
+        let id = &tag[position..position + id_len];
+        if !id.iter().all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit()) {
+            break;
+        }
+        let size_field = &tag[position + id_len..position + 2 * id_len];
+        let size = match syncsafe_sizes {
+            true => syncsafe(size_field),
+            false => size_field.iter().fold(0, |size, byte| (size << 8) | *byte as usize),
+        };
+        if end - position - header_len < size {
+            break;
+        }
+        position += header_len + size;
+    }
+    position
+}
+
+fn syncsafe(bytes: &[u8]) -> usize {
+    // 7 bits per byte
+    bytes.iter().fold(0, |size, byte| (size << 7) | (*byte & 0x7f) as usize)
+}
+
+fn check_leading_bytes(audio: &[u8], findings: &mut Vec<Finding>) {
+    // players skip anything between the tag and the first frame of the audio
+    let tag_end = mpeg::id3v2_len(audio);
+    let first_frame = match mpeg::frames(audio).ok().and_then(|frames| frames.first().map(|frame| frame.offset)) {
+        Some(offset) if offset > tag_end => offset,
+        _ => return,
+    };
+    let gap = &audio[tag_end..first_frame];
+    let non_zero = gap.iter().filter(|byte| **byte != 0).count();
+    findings.push(Finding {
+        check: Check::Padding,
+        frame: None,
+        score: if non_zero > 0 { 70 } else { 20 },
+        explanation: format!(
+            "{} bytes ({} of them not zero) between the {} and the first mpeg frame, which players skip",
+            gap.len(), non_zero, if tag_end > 0 { "end of the id3v2 tag" } else { "start of the file" }
+        ),
+    });
+}
+
+fn score(value: f64) -> u8 {
+    value.round().clamp(1.0, 100.0) as u8
+}
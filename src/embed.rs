@@ -0,0 +1,466 @@
+use std::io::{Read, Seek};
+use std::path::Path;
+use std::time::SystemTime;
+use id3::TagLike;
+use crate::archive::{self, ARCHIVE_MIME_TYPE};
+use crate::carrier::{self, Carrier, Record, TextEncoding};
+use crate::chunk;
+use crate::compress::{self, Compression};
+use crate::container::{Format, TagVersion, MAX_TAG_SIZE};
+use crate::crypto::{self, EncryptionMethod, Passphrase};
+use crate::envelope::Envelope;
+use crate::error::{Error, Result};
+use crate::keys::Recipient;
+use crate::lsb::{Capacity, EmbeddingStats, LsbCoding};
+use crate::paths::{get_filename_drop_path, random_alphanumeric};
+use crate::storage::{self, Storage};
+
+// Frame description keys (the GEOB, TXXX, or COMM description, or the PRIV owner identifier):
+//  - generated keys are KEY_PREFIX followed by 10 random letters and digits (ex: id3stegoAbCdE12345)
+//  - custom keys (Embedder::key) are 1 to MAX_KEY_LEN ascii letters, digits, or '-', '_', '.', ':',
+//    or '/' (no glob characters, so that every key can also be matched exactly by a glob pattern)
+//  - files split into chunks use the key + '#' + chunk number for each frame ('#' is reserved)
+// Keys are unique within a tag. Get mode finds frames by their key (containing KEY_PREFIX) or by
+// their envelope, so custom keys do not need the prefix.
+
+/// Prefix of every frame description key generated by id3stego.
+pub const KEY_PREFIX: &str = "id3stego";
+
+/// Maximum length of a custom frame description key.
+pub const MAX_KEY_LEN: usize = 64;
+
+/// Mime-type of files of unknown type, and of every encrypted file in its frame.
+const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+/// Describes a file embedded by an [`Embedder`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Embedded {
+    /// Frame description key used to find the file again in get mode.
+    pub key: String,
+    /// Filename of the other file (no path), stored in the frame unless the file is encrypted
+    /// (then only in its encrypted envelope).
+    pub filename: String,
+    /// Mime-type inferred from the other file's contents, stored like the filename.
+    pub mime_type: String,
+    /// Number of bytes embedded (size of the other file).
+    pub size: usize,
+    /// SHA-256 digest of the other file, recorded in the payload envelope.
+    pub sha256: [u8; 32],
+    /// Number of frames used (more than one if the file was split into chunks).
+    pub frames: usize,
+    /// Type of frame the file was stored in.
+    pub carrier: Carrier,
+    /// Whether a directory was embedded (as a tar archive) rather than a file.
+    pub archive: bool,
+    /// How the file was compressed, if at all.
+    pub compression: Option<Compression>,
+    /// How the file was encrypted, if at all.
+    pub encryption: Option<EncryptionMethod>,
+    /// Version of the id3v2 tag written (none for carriers outside the id3v2 tag).
+    pub tag_version: Option<TagVersion>,
+    /// How much the audio data changed, for carriers in the audio data (the same for every file
+    /// embedded at once, since they are hidden together).
+    pub embedding: Option<EmbeddingStats>,
+}
+
+/// Embeds other files into the id3v2 metadata of mp3, wav, and aiff files, the metadata blocks
+/// of flac files, the comments of ogg vorbis and opus files, or the atoms of m4a files, or hides
+/// them in the sample LSBs of wav and aiff files.
+#[derive(Debug, Clone, Default)]
+pub struct Embedder {
+    carrier: Option<Carrier>,
+    text_encoding: TextEncoding,
+    key: Option<String>,
+    compression: Option<Compression>,
+    encryption: Option<Encryption>,
+    tag_version: Option<TagVersion>,
+    stego_key: Option<String>,
+    lsb_coding: LsbCoding,
+}
+
+#[derive(Debug, Clone)]
+enum Encryption {
+    Passphrase(Passphrase),
+    Recipients(Vec<Recipient>),
+}
+
+impl Embedder {
+    pub fn new() -> Embedder {
+        Embedder::default()
+    }
+
+    /// Sets the type of frame (or metadata block) used to store embedded files (by default GEOB
+    /// for mp3, wav, and aiff, APPLICATION for flac, an ID3STEGO comment for ogg, and a freeform
+    /// atom for m4a, see [`Format::default_carrier`]).
+    ///
+    /// Embedding fails if the audio format cannot hold `carrier` (see [`Format::supports`]).
+    pub fn carrier(mut self, carrier: Carrier) -> Embedder {
+        self.carrier = Some(carrier);
+        self
+    }
+
+    /// Sets the binary-to-text encoding used by the TXXX and COMM carriers (base64 by default).
+    pub fn text_encoding(mut self, text_encoding: TextEncoding) -> Embedder {
+        self.text_encoding = text_encoding;
+        self
+    }
+
+    /// Stores embedded files under the frame description key `key` instead of a generated one.
+    ///
+    /// Custom keys are 1 to [`MAX_KEY_LEN`] ascii letters, digits, or `-_.:/` characters, and must
+    /// not be used by another frame in the tag already.
+    pub fn key(mut self, key: impl Into<String>) -> Embedder {
+        self.key = Some(key.into());
+        self
+    }
+
+    /// Compresses embedded files with `compression` before encrypting them (not compressed by
+    /// default).
+    pub fn compression(mut self, compression: Compression) -> Embedder {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Writes the id3v2 tag as `tag_version`, instead of keeping the audio file's own tag version
+    /// (id3v2.2 tags are written as id3v2.3, new tags as id3v2.4).
+    pub fn tag_version(mut self, tag_version: TagVersion) -> Embedder {
+        self.tag_version = Some(tag_version);
+        self
+    }
+
+    /// Hides embedded files at positions of the audio data chosen by `stego_key`, for carriers in
+    /// the audio data (see [`Carrier::in_audio_data`]), which fail with
+    /// [`Error::StegoKeyRequired`](crate::Error::StegoKeyRequired) without one.
+    ///
+    /// The same stego key is needed to find the files again (see
+    /// [`Extractor::stego_key`](crate::Extractor::stego_key)).
+    pub fn stego_key(mut self, stego_key: impl Into<String>) -> Embedder {
+        self.stego_key = Some(stego_key.into());
+        self
+    }
+
+    /// Changes samples (or global gains) hiding embedded files ([`Carrier::PcmLsb`] and
+    /// [`Carrier::Mp3GlobalGain`]) by adding or subtracting 1 at random (±1 LSB matching) instead
+    /// of flipping their least significant bit (LSB replacement, the default). Both are read back
+    /// the same way, but matching is harder to detect.
+    pub fn lsb_matching(mut self, matching: bool) -> Embedder {
+        self.lsb_coding.matching = matching;
+        self
+    }
+
+    /// Hides every `k` bits of embedded files ([`Carrier::PcmLsb`] and [`Carrier::Mp3GlobalGain`])
+    /// in 2^k - 1 samples (or global gains) with a (1, 2^k - 1, k) hamming code, changing at most
+    /// one of them (matrix embedding). Fewer samples change per hidden bit, but less fits (see
+    /// [`capacity_path`](Embedder::capacity_path)).
+    ///
+    /// `k` is 1 (one bit per sample, the default) to [`MAX_MATRIX_K`](crate::MAX_MATRIX_K); the
+    /// extractor reads it from the samples.
+    pub fn matrix_embedding(mut self, k: u8) -> Embedder {
+        self.lsb_coding.matrix_k = k;
+        self
+    }
+
+    /// Encrypts embedded files with a key derived from `passphrase` (not encrypted by default).
+    ///
+    /// Replaces any recipients set before.
+    pub fn passphrase(mut self, passphrase: impl Into<String>) -> Embedder {
+        self.encryption = Some(Encryption::Passphrase(Passphrase(passphrase.into())));
+        self
+    }
+
+    /// Encrypts embedded files to `recipient`, in addition to any recipients set before.
+    ///
+    /// Replaces a passphrase set before.
+    pub fn recipient(self, recipient: Recipient) -> Embedder {
+        self.recipients([recipient])
+    }
+
+    /// Encrypts embedded files to every one of `recipients`, in addition to any recipients set
+    /// before. Any one matching [`Identity`](crate::Identity) can decrypt them.
+    ///
+    /// Replaces a passphrase set before.
+    pub fn recipients(mut self, recipients: impl IntoIterator<Item = Recipient>) -> Embedder {
+        match &mut self.encryption {
+            Some(Encryption::Recipients(existing)) => existing.extend(recipients),
+            _ => self.encryption = Some(Encryption::Recipients(recipients.into_iter().collect())),
+        }
+        self
+    }
+
+    /// Computes how much the chosen carrier (a carrier in the audio data, see
+    /// [`Carrier::in_audio_data`], or [`Carrier::Mp3Ancillary`]) can hide in the audio file at
+    /// `audio_path`.
+    pub fn capacity_path(&self, audio_path: impl AsRef<Path>) -> Result<Capacity> {
+        let audio = std::fs::read(audio_path)?;
+        self.capacity_bytes(&audio)
+    }
+
+    /// Computes how much the chosen carrier can hide in the audio file held by `audio`, as
+    /// [`capacity_path`](Embedder::capacity_path) does for files on disk.
+    pub fn capacity<R: Read + Seek>(&self, mut audio: R) -> Result<Capacity> {
+        let bytes = storage::read_all(&mut audio)?;
+        self.capacity_bytes(&bytes)
+    }
+
+    /// Embeds `other_path` into a copy of `audio_path` written to `output_path`.
+    ///
+    /// If `other_path` is a directory, its whole tree is packed into a tar archive (relative paths,
+    /// permissions, and mtimes) and embedded as one file. Neither input is modified.
+    pub fn embed_path(
+        &self,
+        audio_path: impl AsRef<Path>,
+        other_path: impl AsRef<Path>,
+        output_path: impl AsRef<Path>,
+    ) -> Result<Embedded> {
+        let mut embedded = self.embed_paths(audio_path, [other_path], output_path)?;
+        Ok(embedded.remove(0))
+    }
+
+    /// Embeds every one of `other_paths` into a copy of `audio_path` written to `output_path`,
+    /// adding all of them to the same tag with a single write.
+    ///
+    /// With a custom [`key`](Embedder::key) and more than one file, each file's key is the custom
+    /// key + '-' + its number, starting at 1 (ex: backup-1, backup-2). No input file is modified.
+    pub fn embed_paths(
+        &self,
+        audio_path: impl AsRef<Path>,
+        other_paths: impl IntoIterator<Item = impl AsRef<Path>>,
+        output_path: impl AsRef<Path>,
+    ) -> Result<Vec<Embedded>> {
+        let mut others = Vec::new();
+        for other_path in other_paths {
+            let other_path = other_path.as_ref();
+            let metadata = std::fs::metadata(other_path)?;
+            others.push(OtherFile {
+                // do not embed full file path for other file (only filename)
+                filename: get_filename_drop_path(other_path, ""),
+                modified: metadata.modified().ok(),
+                data: if metadata.is_dir() { archive::pack_dir(other_path)? } else { std::fs::read(other_path)? },
+                archive: metadata.is_dir(),
+            });
+        }
+        let audio = std::fs::read(audio_path)?;
+
+        let (output, embedded) = self.embed_bytes(&audio, others)?;
+        storage::write_atomic(output_path, &output)?;
+        Ok(embedded)
+    }
+
+    /// Embeds `data` (stored under `filename`) into the audio file held by `audio`.
+    ///
+    /// The whole stream is read from the start and rewritten in place. No modification time is
+    /// recorded for `data`.
+    pub fn embed<F: Storage>(&self, audio: F, filename: &str, data: Vec<u8>) -> Result<Embedded> {
+        let mut embedded = self.embed_files(audio, vec![(filename.to_string(), data)])?;
+        Ok(embedded.remove(0))
+    }
+
+    /// Embeds every one of `files` (filename and data) into the audio file held by `audio`, as
+    /// [`embed_paths`](Embedder::embed_paths) does for files on disk.
+    pub fn embed_files<F: Storage>(&self, mut audio: F, files: Vec<(String, Vec<u8>)>) -> Result<Vec<Embedded>> {
+        let others = files
+            .into_iter()
+            .map(|(filename, data)| OtherFile { filename, data, modified: None, archive: false })
+            .collect();
+        let bytes = storage::read_all(&mut audio)?;
+        let (output, embedded) = self.embed_bytes(&bytes, others)?;
+        storage::replace_all(&mut audio, &output)?;
+        Ok(embedded)
+    }
+
+    fn capacity_bytes(&self, audio: &[u8]) -> Result<Capacity> {
+        let format = Format::detect(audio)?;
+        format.capacity(audio, self.carrier.unwrap_or(format.default_carrier()), self.lsb_coding)
+    }
+
+    fn embed_bytes(&self, audio: &[u8], others: Vec<OtherFile>) -> Result<(Vec<u8>, Vec<Embedded>)> {
+        let format = Format::detect(audio)?;
+        let carrier = self.carrier.unwrap_or(format.default_carrier());
+        if !format.supports(carrier) {
+            return Err(Error::UnsupportedCarrier { carrier, format });
+        }
+        if carrier.in_audio_data() && self.stego_key.is_none() {
+            return Err(Error::StegoKeyRequired);
+        }
+
+        // search for id3 tag in audio file, create if none found
+        // the tag is written back as the version it was read as, unless overridden
+        let existing_tag = format.read_tag(audio)?;
+        let tag_version = match carrier.in_id3_tag() {
+            true => Some(self.tag_version.unwrap_or(TagVersion::of(existing_tag.as_ref()))),
+            false => None,
+        };
+        let mut tag = existing_tag.unwrap_or_default();
+
+        // keys already used by any frame of the tag, or by files in native carriers (wav files
+        // can hold both, and get mode reads both), or hidden in the audio data with the stego key
+        let mut used_keys: Vec<String> = tag.frames().filter_map(carrier::frame_key).map(str::to_string).collect();
+        used_keys.extend(format.read_native_records(audio)?.into_iter().map(|record| record.key));
+        if let Some(stego_key) = &self.stego_key {
+            used_keys.extend(format.read_audio_records(audio, stego_key)?.into_iter().map(|record| record.key));
+        }
+
+        let numbered = others.len() > 1;
+        let mut embedded = Vec::with_capacity(others.len());
+        let mut records = Vec::new();
+        for (index, other) in others.into_iter().enumerate() {
+            let key = match &self.key {
+                Some(key) => {
+                    let key = if numbered { format!("{}-{}", key, index + 1) } else { key.clone() };
+                    validate_key(&key)?;
+                    if key_in_use(&used_keys, &key) {
+                        return Err(Error::FrameKeyInUse { key });
+                    }
+                    key
+                }
+                None => unused_key(&used_keys),
+            };
+            let (file, file_records) = self.add_file(key, other, carrier, tag_version)?;
+            used_keys.extend(file_records.iter().map(|record| record.key.clone()));
+            records.extend(file_records);
+            embedded.push(file);
+        }
+
+        let output = match (tag_version, &self.stego_key) {
+            (Some(tag_version), _) => {
+                for record in records {
+                    tag.add_frame(record.into_frame(self.text_encoding, format)?);
+                }
+                format.write_tag(audio, &tag, tag_version)?
+            }
+            (None, Some(stego_key)) if carrier.in_audio_data() => {
+                let (output, stats) = format.add_audio_records(audio, &records, stego_key, self.lsb_coding)?;
+                for file in &mut embedded {
+                    file.embedding = Some(stats);
+                }
+                output
+            }
+            (None, _) => format.add_native_records(audio, &records, self.text_encoding)?,
+        };
+        Ok((output, embedded))
+    }
+
+    fn add_file(&self, key: String, other: OtherFile, carrier: Carrier, tag_version: Option<TagVersion>)
+        -> Result<(Embedded, Vec<Record>)> {
+        let OtherFile { filename, data, modified, archive } = other;
+        if self.compression.is_none() && data.len() > MAX_TAG_SIZE {
+            return Err(Error::PayloadTooLarge { size: data.len(), max: MAX_TAG_SIZE });
+        }
+
+        // if possible, infer mimetype of other file from buffer
+        let mime_type = match infer::get(&data) {
+            _ if archive => ARCHIVE_MIME_TYPE.to_string(),
+            Some(kind) => kind.mime_type().to_string(),
+            None => DEFAULT_MIME_TYPE.to_string(),
+        };
+
+        // compress before encrypting (ciphertext is indistinguishable from random bytes, so it
+        // does not compress), and encrypt after inferring the mime-type
+        // (the other file's own bytes may look like a compressed or encrypted payload, so the
+        // envelope flags follow the options, never the data)
+        let size = data.len();
+        let mut envelope = Envelope::new(&filename, &data, modified, archive);
+        envelope.compressed = self.compression.is_some();
+        let data = match self.compression {
+            Some(compression) => compress::compress(compression, &data)?,
+            None => data,
+        };
+
+        // wrap the result in an envelope describing the original file, encrypted along with it
+        // (behind an outer envelope that only says it is encrypted, see envelope.rs)
+        if self.encryption.is_some() {
+            envelope.mime_type = Some(mime_type.clone());
+        }
+        let (data, encryption) = match &self.encryption {
+            Some(Encryption::Passphrase(passphrase)) => {
+                let sealed = crypto::seal_with_passphrase(&passphrase.0, &envelope.wrap(&data))?;
+                (Envelope::sealed().wrap(&sealed), Some(EncryptionMethod::Passphrase))
+            }
+            Some(Encryption::Recipients(recipients)) => {
+                let sealed = crypto::seal_to_recipients(recipients, &envelope.wrap(&data))?;
+                (Envelope::sealed().wrap(&sealed), Some(EncryptionMethod::Recipients))
+            }
+            None => (envelope.wrap(&data), None),
+        };
+        if data.len() > MAX_TAG_SIZE {
+            return Err(Error::PayloadTooLarge { size: data.len(), max: MAX_TAG_SIZE });
+        }
+
+        let chunks = chunk::split(data, rand::random(), carrier.max_frame_data(self.text_encoding));
+        let embedded = Embedded {
+            key: key.clone(),
+            filename: filename.clone(),
+            mime_type: mime_type.clone(),
+            size,
+            sha256: envelope.sha256,
+            frames: chunks.len(),
+            carrier,
+            archive,
+            compression: self.compression,
+            encryption,
+            tag_version,
+            embedding: None,
+        };
+
+        // the frame of an encrypted file tells nothing about it either: no filename and a generic
+        // mime-type (the real ones are in its envelope)
+        let (filename, mime_type) = match encryption {
+            Some(_) => (String::new(), DEFAULT_MIME_TYPE.to_string()),
+            None => (filename, mime_type),
+        };
+
+        // files larger than one frame get one frame per chunk, with the chunk number appended to the key
+        let single_frame = chunks.len() == 1;
+        let mut records = Vec::with_capacity(chunks.len());
+        for (sequence, chunk_data) in chunks.into_iter().enumerate() {
+            records.push(Record {
+                key: if single_frame { key.clone() } else { chunk::description(&key, sequence as u32) },
+                filename: filename.clone(),
+                mime_type: mime_type.clone(),
+                carrier,
+                data: chunk_data,
+            });
+        }
+        Ok((embedded, records))
+    }
+}
+
+/// An other file (or directory, packed as an archive) to embed, read into memory.
+struct OtherFile {
+    filename: String,
+    data: Vec<u8>,
+    modified: Option<SystemTime>,
+    archive: bool,
+}
+
+fn unused_key(used_keys: &[String]) -> String {
+    // set frame description key to 'id3stego' + random 10 character string
+    // prevent collisions if audio file already contains another file previously embedded by id3stego
+    loop {
+        let key = format!("{}{}", KEY_PREFIX, random_alphanumeric(10));
+        if !key_in_use(used_keys, &key) {
+            return key;
+        }
+    }
+}
+
+fn key_in_use(used_keys: &[String], key: &str) -> bool {
+    // a key is in use if a frame has it, or has it with a chunk number appended
+    used_keys
+        .iter()
+        .any(|used_key| used_key == key || used_key.strip_prefix(key).is_some_and(|rest| rest.starts_with('#')))
+}
+
+fn validate_key(key: &str) -> Result<()> {
+    let invalid = |reason| Err(Error::InvalidFrameKey { key: key.to_string(), reason });
+    if key.is_empty() {
+        return invalid("must not be empty");
+    }
+    if key.len() > MAX_KEY_LEN {
+        return invalid("must be at most 64 characters");
+    }
+    if !key.chars().all(|c| c.is_ascii_alphanumeric() || "-_.:/".contains(c)) {
+        return invalid("only ascii letters, digits, and '-', '_', '.', ':', or '/' are allowed");
+    }
+    Ok(())
+}
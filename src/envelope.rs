@@ -0,0 +1,228 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use sha2::{Digest, Sha256};
+use crate::error::{Error, Result};
+
+// Every payload written by id3stego is wrapped in an envelope (all integers big endian):
+//
+// | Field        | Size | Value                                                  |
+// |--------------+------+--------------------------------------------------------|
+// | magic        |    4 | b"I3SP"                                                |
+// | version      |    1 | 1                                                      |
+// | flags        |    1 | bit 0 = compressed, bit 1 = encrypted, bit 2 = mtime,  |
+// |              |      | bit 3 = archive (embedded directory, see archive.rs),  |
+// |              |      | bit 4 = mime-type                                      |
+// | size         |    8 | size of the original file                              |
+// | sha-256      |   32 | digest of the original file                            |
+// | mtime        |    8 | original modification time (seconds since unix epoch)  |
+// |              |      | or 0 if flag bit 2 is not set                          |
+// | filename len |    2 | length of filename                                     |
+// | filename     |    n | original filename (utf-8, no path)                     |
+//
+// followed, if flag bit 4 (mime-type) is set, by:
+//
+// | mime len     |    1 | length of mime-type                                    |
+// | mime-type    |    n | mime-type inferred from the original file              |
+//
+// The envelope is followed by the body: the original file, compressed if its flags say so (with
+// its own header, see compress.rs). Get mode checks size and digest after decompressing.
+//
+// Encrypted payloads are wrapped twice: the envelope and body above are encrypted together (see
+// crypto.rs), and the ciphertext is wrapped in an outer envelope with only the encrypted flag set
+// (size 0, all-zero digest, no mtime, empty filename). The inner envelope also records the
+// mime-type, since the frame only shows a generic one and no filename (see embed.rs). Without the
+// passphrase or private key, nothing about the original file can be read from an encrypted
+// payload, nor a guess confirmed.
+// The envelope of an unencrypted payload can be read by anyone (size, sha-256 digest, filename,
+// and mtime), like the file itself.
+//
+// Payloads without an envelope (id3stego v0.2) are still extracted, without verification.
+
+const ENVELOPE_MAGIC: &[u8; 4] = b"I3SP";
+const ENVELOPE_VERSION: u8 = 1;
+const FLAG_COMPRESSED: u8 = 1;
+const FLAG_ENCRYPTED: u8 = 1 << 1;
+const FLAG_MTIME: u8 = 1 << 2;
+const FLAG_ARCHIVE: u8 = 1 << 3;
+const FLAG_MIME_TYPE: u8 = 1 << 4;
+const FLAGS_KNOWN: u8 = FLAG_COMPRESSED | FLAG_ENCRYPTED | FLAG_MTIME | FLAG_ARCHIVE | FLAG_MIME_TYPE;
+const FIXED_LEN: usize = 4 + 1 + 1 + 8 + 32 + 8 + 2;
+
+/// Returns true if `payload` starts with envelope magic bytes.
+pub(crate) fn is_envelope(payload: &[u8]) -> bool {
+    payload.len() >= 4 && &payload[..4] == ENVELOPE_MAGIC
+}
+
+/// Metadata describing the original file inside a payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Envelope {
+    pub compressed: bool,
+    pub encrypted: bool,
+    pub archive: bool,
+    pub size: u64,
+    pub sha256: [u8; 32],
+    pub modified: Option<SystemTime>,
+    pub filename: String,
+    /// Only recorded for encrypted files (see embed.rs).
+    pub mime_type: Option<String>,
+}
+
+impl Envelope {
+    /// Describes the original file `data`. Compressed and encrypted flags start out false.
+    pub fn new(filename: &str, data: &[u8], modified: Option<SystemTime>, archive: bool) -> Envelope {
+        Envelope {
+            compressed: false,
+            encrypted: false,
+            archive,
+            size: data.len() as u64,
+            sha256: Sha256::digest(data).into(),
+            // only whole seconds after the epoch are stored
+            modified: modified
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|since_epoch| UNIX_EPOCH + Duration::from_secs(since_epoch.as_secs())),
+            filename: filename.to_string(),
+            mime_type: None,
+        }
+    }
+
+    /// Outer envelope of an encrypted payload, which tells nothing about the original file (its
+    /// own envelope is encrypted with it).
+    pub fn sealed() -> Envelope {
+        Envelope {
+            compressed: false,
+            encrypted: true,
+            archive: false,
+            size: 0,
+            sha256: [0; 32],
+            modified: None,
+            filename: String::new(),
+            mime_type: None,
+        }
+    }
+
+    /// Builds the payload: this envelope followed by `body`.
+    pub fn wrap(&self, body: &[u8]) -> Vec<u8> {
+        // filenames (and mime-types) longer than their length field allows are truncated (on a char
+        // boundary)
+        let filename_len = truncated_len(&self.filename, u16::MAX as usize);
+        let mime_type = self.mime_type.as_deref().map(|mime_type| &mime_type[..truncated_len(mime_type, u8::MAX as usize)]);
+        let mut flags = 0;
+        if self.compressed {
+            flags |= FLAG_COMPRESSED;
+        }
+        if self.encrypted {
+            flags |= FLAG_ENCRYPTED;
+        }
+        if self.archive {
+            flags |= FLAG_ARCHIVE;
+        }
+        if mime_type.is_some() {
+            flags |= FLAG_MIME_TYPE;
+        }
+        let mtime = match self.modified.and_then(|time| time.duration_since(UNIX_EPOCH).ok()) {
+            Some(since_epoch) => {
+                flags |= FLAG_MTIME;
+                since_epoch.as_secs()
+            }
+            None => 0,
+        };
+
+        let mut payload = Vec::with_capacity(FIXED_LEN + filename_len + 1 + mime_type.map_or(0, str::len) + body.len());
+        payload.extend_from_slice(ENVELOPE_MAGIC);
+        payload.push(ENVELOPE_VERSION);
+        payload.push(flags);
+        payload.extend_from_slice(&self.size.to_be_bytes());
+        payload.extend_from_slice(&self.sha256);
+        payload.extend_from_slice(&mtime.to_be_bytes());
+        payload.extend_from_slice(&(filename_len as u16).to_be_bytes());
+        payload.extend_from_slice(&self.filename.as_bytes()[..filename_len]);
+        if let Some(mime_type) = mime_type {
+            payload.push(mime_type.len() as u8);
+            payload.extend_from_slice(mime_type.as_bytes());
+        }
+        payload.extend_from_slice(body);
+        payload
+    }
+
+    /// Parses the envelope at the start of `payload`, returning it with the body that follows.
+    ///
+    /// Returns `Ok(None)` for payloads without an envelope (id3stego v0.2).
+    /// `key` (the payload's frame description key) is only used for error messages.
+    pub fn parse<'a>(key: &str, payload: &'a [u8]) -> Result<Option<(Envelope, &'a [u8])>> {
+        let invalid = |reason| Error::InvalidHeader { key: key.to_string(), reason };
+        if !is_envelope(payload) {
+            return Ok(None);
+        }
+        if payload.len() < FIXED_LEN {
+            return Err(invalid("envelope is truncated"));
+        }
+        if payload[4] != ENVELOPE_VERSION {
+            return Err(invalid("unsupported envelope version"));
+        }
+        let flags = payload[5];
+        if flags & !FLAGS_KNOWN != 0 {
+            return Err(invalid("unsupported envelope flags"));
+        }
+        let read_u64 = |at: usize| u64::from_be_bytes(payload[at..at + 8].try_into().unwrap_or_default());
+        let size = read_u64(6);
+        let sha256 = payload[14..46].try_into().unwrap_or_default();
+        let mtime = read_u64(46);
+        let filename_len = u16::from_be_bytes([payload[54], payload[55]]) as usize;
+        if payload.len() < FIXED_LEN + filename_len {
+            return Err(invalid("envelope is truncated"));
+        }
+        let filename = match std::str::from_utf8(&payload[FIXED_LEN..FIXED_LEN + filename_len]) {
+            Ok(filename) => filename.to_string(),
+            Err(_) => return Err(invalid("envelope filename is not utf-8")),
+        };
+        let mut body_start = FIXED_LEN + filename_len;
+        let mime_type = match flags & FLAG_MIME_TYPE != 0 {
+            true => {
+                let mime_type_len = match payload.get(body_start) {
+                    Some(len) => *len as usize,
+                    None => return Err(invalid("envelope is truncated")),
+                };
+                let mime_type = match payload.get(body_start + 1..body_start + 1 + mime_type_len) {
+                    Some(mime_type) => std::str::from_utf8(mime_type).map_err(|_| invalid("envelope mime-type is not utf-8"))?,
+                    None => return Err(invalid("envelope is truncated")),
+                };
+                body_start += 1 + mime_type_len;
+                Some(mime_type.to_string())
+            }
+            false => None,
+        };
+        let envelope = Envelope {
+            compressed: flags & FLAG_COMPRESSED != 0,
+            encrypted: flags & FLAG_ENCRYPTED != 0,
+            archive: flags & FLAG_ARCHIVE != 0,
+            size,
+            sha256,
+            modified: match flags & FLAG_MTIME != 0 {
+                true => UNIX_EPOCH.checked_add(Duration::from_secs(mtime)),
+                false => None,
+            },
+            filename,
+            mime_type,
+        };
+        Ok(Some((envelope, &payload[body_start..])))
+    }
+
+    /// Checks that `data` (the decrypted and decompressed body) is the original file.
+    pub fn verify(&self, key: &str, data: &[u8]) -> Result<()> {
+        if data.len() as u64 != self.size {
+            return Err(Error::Corrupted { key: key.to_string(), reason: "size does not match original size" });
+        }
+        if Sha256::digest(data).as_slice() != self.sha256 {
+            return Err(Error::Corrupted { key: key.to_string(), reason: "sha-256 digest does not match" });
+        }
+        Ok(())
+    }
+}
+
+/// Length of the longest prefix of `text` of at most `max` bytes that ends on a char boundary.
+fn truncated_len(text: &str, max: usize) -> usize {
+    let mut len = text.len().min(max);
+    while !text.is_char_boundary(len) {
+        len -= 1;
+    }
+    len
+}
@@ -0,0 +1,181 @@
+use std::fmt;
+use std::io;
+use crate::carrier::Carrier;
+use crate::container::{Format, TagVersion};
+
+/// Errors returned by the id3stego library.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file or buffer failed.
+    Io(io::Error),
+    /// The id3 crate was unable to read or write an id3v2 tag.
+    Tag(id3::Error),
+    /// The audio data is not mp3, wav, aiff, flac, ogg, or m4a (holds the inferred mime-type, if any).
+    UnsupportedFormat(Option<String>),
+    /// The chosen carrier cannot be stored in this audio format.
+    UnsupportedCarrier { carrier: Carrier, format: Format },
+    /// The audio container (outside the id3v2 tag) could not be read.
+    InvalidContainer { format: &'static str, reason: &'static str },
+    /// The samples of the audio file cannot hide embedded files (not integer PCM of 8, 16, or 24 bits).
+    UnsupportedSamples { format: &'static str, reason: &'static str },
+    /// The other file does not fit into an id3v2 tag.
+    PayloadTooLarge { size: usize, max: usize },
+    /// The embedded files do not fit into the audio data (see [`Capacity`](crate::Capacity)).
+    CapacityExceeded { size: usize, capacity: usize },
+    /// The finished id3v2 tag would exceed the maximum tag size.
+    TagTooLarge { size: usize, max: usize },
+    /// A frame is too large for the frame size field of the id3v2 version being written.
+    FrameTooLarge { frame_id: String, size: usize, max: usize, version: TagVersion },
+    /// Some chunks of a payload split across multiple frames were not found (`missing` holds the
+    /// first few of their sequence numbers, `missing_count` how many are missing in all).
+    MissingChunks { key: String, missing: Vec<u32>, missing_count: u32, total: u32 },
+    /// The chunks of a payload split across multiple frames do not fit together.
+    InvalidChunks { key: String, reason: &'static str },
+    /// A frame written by id3stego could not be decoded.
+    InvalidFrame { key: String, reason: &'static str },
+    /// A header (envelope, compression, or encryption) in front of an embedded file could not be read.
+    InvalidHeader { key: String, reason: &'static str },
+    /// Encrypting an embedded file failed.
+    Encryption(&'static str),
+    /// An embedded file is encrypted, but no passphrase was given.
+    PassphraseRequired { key: String },
+    /// An embedded file could not be decrypted (wrong passphrase or tampered payload).
+    Decryption { key: String },
+    /// An embedded file is encrypted to recipients, but no private key was given.
+    IdentityRequired { key: String },
+    /// None of the given private keys can decrypt an embedded file.
+    NoMatchingIdentity { key: String },
+    /// An embedded file does not match the size or digest recorded in its envelope.
+    Corrupted { key: String, reason: &'static str },
+    /// An embedded file could not be decompressed (damaged data or decompression limit exceeded).
+    Decompression { key: String, reason: &'static str },
+    /// A public or private key could not be parsed.
+    InvalidKey(String),
+    /// A carrier in the audio data was chosen, but no stego key was given.
+    StegoKeyRequired,
+    /// Matrix embedding was chosen with a hamming code outside 1 to [`MAX_MATRIX_K`](crate::MAX_MATRIX_K).
+    InvalidMatrixCode { k: u8 },
+    /// A frame description key (or key pattern) chosen by the user is not allowed.
+    InvalidFrameKey { key: String, reason: &'static str },
+    /// A frame description key chosen by the user is already used by another frame in the tag
+    /// (or by a file in a native carrier).
+    FrameKeyInUse { key: String },
+    /// An embedded directory contains an entry that would be unpacked outside the output directory
+    /// (or that is not a file, directory, or link).
+    UnsafeArchiveEntry { key: String, path: String, reason: &'static str },
+    /// An embedded file was unpacked as a directory, but is not an archive.
+    NotAnArchive { key: String },
+}
+
+/// Result type used throughout the id3stego library.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Tag(err) => write!(f, "id3v2 tag error: {}", err),
+            Error::UnsupportedFormat(Some(mime_type)) => {
+                write!(f, "unsupported audio file type '{}' (mp3, wav, aiff, flac, ogg, or m4a required)", mime_type)
+            }
+            Error::UnsupportedFormat(None) => {
+                write!(f, "unable to determine audio file type (mp3, wav, aiff, flac, ogg, or m4a required)")
+            }
+            Error::UnsupportedCarrier { carrier, format } => {
+                write!(f, "{} carrier cannot be used in '{}' files", carrier.frame_id(), format.mime_type())
+            }
+            Error::InvalidContainer { format, reason } => {
+                write!(f, "{} file is damaged ({})", format, reason)
+            }
+            Error::UnsupportedSamples { format, reason } => {
+                write!(f, "{} file samples cannot hide embedded files ({})", format, reason)
+            }
+            Error::PayloadTooLarge { size, max } => {
+                write!(f, "other file of {} bytes exceeds {} bytes (id3v2 max tag size)", size, max)
+            }
+            Error::CapacityExceeded { size, capacity } => {
+                write!(f, "embedded files of {} bytes exceed {} bytes (capacity of the audio data)", size, capacity)
+            }
+            Error::TagTooLarge { size, max } => {
+                write!(f, "id3v2 tag of {} bytes exceeds {} bytes (id3v2 max tag size)", size, max)
+            }
+            Error::FrameTooLarge { frame_id, size, max, version } => {
+                write!(f, "{} frame of {} bytes exceeds {} bytes ({} max frame size)", frame_id, size, max, version)
+            }
+            Error::MissingChunks { key, missing, missing_count, total } => {
+                let mut missing: Vec<String> = missing.iter().map(|sequence| (sequence + 1).to_string()).collect();
+                if (missing.len() as u32) < *missing_count {
+                    missing.push("...".to_string());
+                }
+                write!(f, "embedded file '{}' is incomplete, missing {} chunk(s) of {} ({})", key, missing_count, total, missing.join(", "))
+            }
+            Error::InvalidChunks { key, reason } => {
+                write!(f, "embedded file '{}' is damaged ({})", key, reason)
+            }
+            Error::InvalidFrame { key, reason } => {
+                write!(f, "frame '{}' is damaged ({})", key, reason)
+            }
+            Error::InvalidHeader { key, reason } => {
+                write!(f, "embedded file '{}' has an invalid header ({})", key, reason)
+            }
+            Error::Encryption(reason) => write!(f, "encryption failed ({})", reason),
+            Error::PassphraseRequired { key } => {
+                write!(f, "embedded file '{}' is encrypted, a passphrase is required", key)
+            }
+            Error::Decryption { key } => {
+                write!(f, "unable to decrypt embedded file '{}' (wrong passphrase or tampered payload)", key)
+            }
+            Error::IdentityRequired { key } => {
+                write!(f, "embedded file '{}' is encrypted to recipients, a private key is required", key)
+            }
+            Error::NoMatchingIdentity { key } => {
+                write!(f, "embedded file '{}' is not encrypted to any of the given private keys", key)
+            }
+            Error::Corrupted { key, reason } => {
+                write!(f, "embedded file '{}' is corrupted ({})", key, reason)
+            }
+            Error::Decompression { key, reason } => {
+                write!(f, "unable to decompress embedded file '{}' ({})", key, reason)
+            }
+            Error::InvalidKey(reason) => write!(f, "invalid key ({})", reason),
+            Error::StegoKeyRequired => write!(f, "a stego key is required to hide files in the audio data"),
+            Error::InvalidMatrixCode { k } => {
+                write!(f, "matrix embedding with k = {} is not supported (k must be 1 to {})", k, crate::MAX_MATRIX_K)
+            }
+            Error::InvalidFrameKey { key, reason } => {
+                write!(f, "invalid frame description key '{}' ({})", key, reason)
+            }
+            Error::FrameKeyInUse { key } => {
+                write!(f, "frame description key '{}' is already used in the audio file", key)
+            }
+            Error::UnsafeArchiveEntry { key, path, reason } => {
+                write!(f, "embedded directory '{}' has an unsafe entry '{}' ({}), nothing unpacked", key, path, reason)
+            }
+            Error::NotAnArchive { key } => {
+                write!(f, "embedded file '{}' is not a directory archive", key)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Tag(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<id3::Error> for Error {
+    fn from(err: id3::Error) -> Error {
+        Error::Tag(err)
+    }
+}
@@ -0,0 +1,392 @@
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use globset::Glob;
+use id3::Frame;
+use crate::archive;
+use crate::carrier::{self, Carrier, Record};
+use crate::chunk::{self, ChunkHeader, Piece};
+use crate::compress::{self, Compression, DEFAULT_MAX_DECOMPRESSED_SIZE};
+use crate::container::Format;
+use crate::crypto::{self, EncryptionMethod, Passphrase};
+use crate::embed::KEY_PREFIX;
+use crate::envelope::{self, Envelope};
+use crate::error::{Error, Result};
+use crate::keys::Identity;
+use crate::storage;
+
+/// A file found in an audio file's id3v2 metadata by an [`Extractor`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extracted {
+    /// Frame description key the file was stored under.
+    pub key: String,
+    /// Original filename (no path), from the payload envelope or else the frame.
+    pub filename: String,
+    /// Mime-type, from the payload envelope (of encrypted files) or else the frame.
+    pub mime_type: String,
+    /// Type of frame the file was stored in.
+    pub carrier: Carrier,
+    /// Whether the file is an embedded directory (a tar archive, see [`Extracted::unpack`]).
+    pub archive: bool,
+    /// Original modification time of the file, if it was recorded.
+    pub modified: Option<SystemTime>,
+    /// SHA-256 digest of the file, verified against `data` (`None` for files embedded by
+    /// id3stego v0.2, which have no envelope).
+    pub sha256: Option<[u8; 32]>,
+    /// How the file was compressed, if at all (`data` is always decompressed).
+    pub compression: Option<Compression>,
+    /// How the file was encrypted, if at all (`data` is always decrypted).
+    pub encryption: Option<EncryptionMethod>,
+    /// Contents of the embedded file.
+    pub data: Vec<u8>,
+}
+
+/// An embedded file found by [`Extractor::list`], described without extracting it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Listed {
+    /// Frame description key the file was stored under.
+    pub key: String,
+    /// Original filename (no path), from the payload envelope or else the frame (empty for
+    /// encrypted files, whose envelope is encrypted with them).
+    pub filename: String,
+    /// Mime-type stored in the frame (generic for encrypted files).
+    pub mime_type: String,
+    /// Size of the original file (for files embedded by id3stego v0.2, size of the stored data),
+    /// unknown for encrypted files, whose envelope is encrypted with them.
+    pub size: Option<u64>,
+    /// Number of bytes stored in the frames (after compression and encryption, with headers).
+    pub stored_size: usize,
+    /// Type of frame the file was stored in.
+    pub carrier: Carrier,
+    /// Number of frames the file was stored in.
+    pub frames: usize,
+    /// Whether the file is an embedded directory (a tar archive; always false for encrypted files).
+    pub archive: bool,
+    /// Original modification time of the file, if it was recorded (and is not encrypted).
+    pub modified: Option<SystemTime>,
+    /// SHA-256 digest recorded for the file (not verified; `None` for id3stego v0.2 files and
+    /// encrypted files).
+    pub sha256: Option<[u8; 32]>,
+    /// Whether the file was compressed, unknown for encrypted files.
+    pub compressed: Option<bool>,
+    /// How the file was encrypted, if at all.
+    pub encryption: Option<EncryptionMethod>,
+}
+
+impl Extracted {
+    /// Number of bytes extracted.
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Unpacks an embedded directory into `out_dir` (created, or replaced if it exists), returning
+    /// the path of every file and directory created.
+    ///
+    /// Fails without writing anything if any entry would end up outside `out_dir`. The directory
+    /// is unpacked under a temp name and renamed into place once complete, so a failed unpack
+    /// leaves an existing `out_dir` untouched.
+    pub fn unpack(&self, out_dir: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        if !self.archive {
+            return Err(Error::NotAnArchive { key: self.key.clone() });
+        }
+        archive::unpack(&self.key, &self.data, out_dir.as_ref())
+    }
+}
+
+/// Extracts files previously embedded by id3stego from mp3, wav, aiff, flac, ogg, and m4a files.
+#[derive(Debug, Clone, Default)]
+pub struct Extractor {
+    passphrase: Option<Passphrase>,
+    identities: Vec<Identity>,
+    key_pattern: Option<String>,
+    stego_key: Option<String>,
+    max_decompressed_size: Option<usize>,
+}
+
+impl Extractor {
+    pub fn new() -> Extractor {
+        Extractor::default()
+    }
+
+    /// Decrypts encrypted embedded files with a key derived from `passphrase`.
+    ///
+    /// Without a passphrase, extracting an encrypted file fails with
+    /// [`Error::PassphraseRequired`](crate::Error::PassphraseRequired).
+    pub fn passphrase(mut self, passphrase: impl Into<String>) -> Extractor {
+        self.passphrase = Some(Passphrase(passphrase.into()));
+        self
+    }
+
+    /// Decrypts embedded files encrypted to the recipient of `identity`.
+    ///
+    /// Can be called more than once; every identity is tried. Without any identity, extracting a
+    /// file encrypted to recipients fails with
+    /// [`Error::IdentityRequired`](crate::Error::IdentityRequired).
+    pub fn identity(mut self, identity: Identity) -> Extractor {
+        self.identities.push(identity);
+        self
+    }
+
+    /// Only extracts (or lists) embedded files whose frame description key matches `pattern`,
+    /// either exactly or as a glob (ex: `backup-*`; `*` and `?` match any characters).
+    pub fn key(mut self, pattern: impl Into<String>) -> Extractor {
+        self.key_pattern = Some(pattern.into());
+        self
+    }
+
+    /// Also searches the audio data for files hidden with `stego_key` (see
+    /// [`Embedder::stego_key`](crate::Embedder::stego_key)); without it, only metadata is searched.
+    pub fn stego_key(mut self, stego_key: impl Into<String>) -> Extractor {
+        self.stego_key = Some(stego_key.into());
+        self
+    }
+
+    /// Refuses to decompress embedded files larger than `max_size` bytes (by default
+    /// [`DEFAULT_MAX_DECOMPRESSED_SIZE`](crate::DEFAULT_MAX_DECOMPRESSED_SIZE), at most
+    /// [`MAX_DECOMPRESSED_SIZE`](crate::MAX_DECOMPRESSED_SIZE)), which fail with
+    /// [`Error::Decompression`](crate::Error::Decompression) instead.
+    pub fn max_decompressed_size(mut self, max_size: usize) -> Extractor {
+        self.max_decompressed_size = Some(max_size);
+        self
+    }
+
+    /// Extracts every embedded file from the audio file at `audio_path`.
+    ///
+    /// Each embedded file is extracted on its own: one that is incomplete, corrupted, or cannot
+    /// be decrypted is returned as an error in its place, and the others are still extracted.
+    /// Only an audio file that cannot be read at all fails as a whole.
+    pub fn extract_path(&self, audio_path: impl AsRef<Path>) -> Result<Vec<Result<Extracted>>> {
+        let audio = std::fs::read(audio_path)?;
+        self.extract_bytes(&audio)
+    }
+
+    /// Extracts every embedded file from the audio file held by `audio`, each on its own (see
+    /// [`extract_path`](Extractor::extract_path)).
+    pub fn extract<R: Read + Seek>(&self, mut audio: R) -> Result<Vec<Result<Extracted>>> {
+        let bytes = storage::read_all(&mut audio)?;
+        self.extract_bytes(&bytes)
+    }
+
+    /// Lists every embedded file in the audio file at `audio_path`, without decrypting,
+    /// decompressing, or verifying them.
+    ///
+    /// Like [`extract_path`](Extractor::extract_path), each embedded file is listed on its own: one
+    /// that is incomplete or has a damaged envelope is returned as an error in its place.
+    pub fn list_path(&self, audio_path: impl AsRef<Path>) -> Result<Vec<Result<Listed>>> {
+        let audio = std::fs::read(audio_path)?;
+        self.list_bytes(&audio)
+    }
+
+    /// Lists every embedded file in the audio file held by `audio`, each on its own (see
+    /// [`list_path`](Extractor::list_path)).
+    pub fn list<R: Read + Seek>(&self, mut audio: R) -> Result<Vec<Result<Listed>>> {
+        let bytes = storage::read_all(&mut audio)?;
+        self.list_bytes(&bytes)
+    }
+
+    fn extract_bytes(&self, audio: &[u8]) -> Result<Vec<Result<Extracted>>> {
+        let mut extracted = Vec::new();
+        for (file, _) in self.payloads(audio)? {
+            extracted.push(file.and_then(|mut file| {
+                self.open_payload(&mut file)?;
+                Ok(file)
+            }));
+        }
+        Ok(extracted)
+    }
+
+    fn list_bytes(&self, audio: &[u8]) -> Result<Vec<Result<Listed>>> {
+        Ok(list_payloads(self.payloads(audio)?))
+    }
+
+    /// Payloads of every embedded file whose key matches the key pattern, if one was set.
+    fn payloads(&self, audio: &[u8]) -> Result<Vec<(Result<Extracted>, usize)>> {
+        let format = Format::detect(audio)?;
+        let mut records = format.read_records(audio)?;
+        if let Some(stego_key) = &self.stego_key {
+            records.extend(format.read_audio_records(audio, stego_key)?);
+        }
+
+        let pattern = match &self.key_pattern {
+            Some(pattern) => pattern,
+            None => return payloads(&records, |_| true),
+        };
+        let matcher = match Glob::new(pattern) {
+            Ok(glob) => glob.compile_matcher(),
+            Err(_) => return Err(Error::InvalidFrameKey { key: pattern.clone(), reason: "invalid glob pattern" }),
+        };
+        payloads(&records, |key| key == pattern || matcher.is_match(key))
+    }
+
+    /// Unwraps the envelope of a whole (reassembled) payload, then decrypts, decompresses, and
+    /// verifies the file inside it.
+    fn open_payload(&self, file: &mut Extracted) -> Result<()> {
+        let payload = std::mem::take(&mut file.data);
+        // the envelope tells what was done to the file (whose own bytes may start with any magic);
+        // encrypted payloads hold the envelope describing the file inside the ciphertext, and
+        // payloads of id3stego v0.2 have no envelope, so their headers are sniffed instead
+        let plaintext;
+        let (envelope, body) = match Envelope::parse(&file.key, &payload)? {
+            Some((outer, sealed)) if outer.encrypted => {
+                plaintext = self.decrypt(file, sealed)?;
+                match Envelope::parse(&file.key, &plaintext)? {
+                    Some((envelope, body)) if !envelope.encrypted => (Some(envelope), body),
+                    _ => {
+                        let reason = "encrypted payload holds no envelope";
+                        return Err(Error::InvalidHeader { key: file.key.clone(), reason });
+                    }
+                }
+            }
+            Some((envelope, body)) => (Some(envelope), body),
+            None if crypto::is_encrypted(&payload) => {
+                plaintext = self.decrypt(file, &payload)?;
+                (None, plaintext.as_slice())
+            }
+            None => (None, payload.as_slice()),
+        };
+        let compressed = match &envelope {
+            Some(envelope) => envelope.compressed,
+            None => compress::compression(body).is_some(),
+        };
+        let data = match compressed {
+            true => {
+                file.compression = compress::compression(body);
+                let max_size = self.max_decompressed_size.unwrap_or(DEFAULT_MAX_DECOMPRESSED_SIZE);
+                compress::decompress(&file.key, body, max_size)?
+            }
+            false => body.to_vec(),
+        };
+
+        if let Some(envelope) = envelope {
+            envelope.verify(&file.key, &data)?;
+            if !envelope.filename.is_empty() {
+                file.filename = envelope.filename;
+            }
+            if let Some(mime_type) = envelope.mime_type {
+                file.mime_type = mime_type;
+            }
+            file.archive = envelope.archive;
+            file.modified = envelope.modified;
+            file.sha256 = Some(envelope.sha256);
+        }
+        file.data = data;
+        Ok(())
+    }
+
+    fn decrypt(&self, file: &mut Extracted, sealed: &[u8]) -> Result<Vec<u8>> {
+        let passphrase = self.passphrase.as_ref().map(|passphrase| passphrase.0.as_str());
+        file.encryption = crypto::method(sealed);
+        crypto::open(&file.key, sealed, passphrase, &self.identities)
+    }
+}
+
+/// Collects the whole (reassembled) payload of every embedded file in `records` whose key is
+/// accepted by `wanted`, with the number of frames it was stored in (an error in place of files
+/// whose chunks cannot be joined). Payloads are not opened (`data` still starts with the
+/// envelope).
+fn payloads(records: &[Record], wanted: impl Fn(&str) -> bool) -> Result<Vec<(Result<Extracted>, usize)>> {
+    // files split into chunks are collected by payload id, in order of their first chunk
+    let mut payloads = Vec::new();
+    let mut chunked: Vec<(u64, Extracted, Vec<Piece>)> = Vec::new();
+    for record in records {
+        match ChunkHeader::parse(&record.data) {
+            Some((header, _)) if !wanted(chunk::key_from_description(&record.key, &header)) => {}
+            None if !wanted(&record.key) => {}
+            Some((header, piece)) => {
+                match chunked.iter_mut().find(|(payload_id, _, _)| *payload_id == header.payload_id) {
+                    Some((_, _, pieces)) => pieces.push((header, piece)),
+                    None => {
+                        let key = chunk::key_from_description(&record.key, &header);
+                        chunked.push((header.payload_id, unopened(record, key, Vec::new()), vec![(header, piece)]));
+                    }
+                }
+            }
+            None => payloads.push((Ok(unopened(record, &record.key, record.data.clone())), 1)),
+        }
+    }
+
+    for (_, file, pieces) in chunked {
+        let frames = pieces.len();
+        let joined = chunk::join(&file.key, pieces).map(|data| Extracted { data, ..file });
+        payloads.push((joined, frames));
+    }
+    Ok(payloads)
+}
+
+fn unopened(record: &Record, key: &str, payload: Vec<u8>) -> Extracted {
+    Extracted {
+        key: key.to_string(),
+        filename: record.filename.clone(),
+        mime_type: record.mime_type.clone(),
+        carrier: record.carrier,
+        archive: false,
+        modified: None,
+        sha256: None,
+        compression: None,
+        encryption: None,
+        data: payload,
+    }
+}
+
+fn list_payloads(payloads: Vec<(Result<Extracted>, usize)>) -> Vec<Result<Listed>> {
+    payloads.into_iter().map(|(file, frames)| file.and_then(|file| list_payload(&file, frames))).collect()
+}
+
+fn list_payload(file: &Extracted, frames: usize) -> Result<Listed> {
+    // the envelope of an encrypted payload is encrypted too, so only the frame tells about it
+    let (envelope, body) = match Envelope::parse(&file.key, &file.data)? {
+        Some((envelope, body)) if envelope.encrypted => (None, body),
+        Some((envelope, body)) => (Some(envelope), body),
+        None => (None, file.data.as_slice()),
+    };
+    let encryption = match &envelope {
+        Some(_) => None,
+        None => crypto::method(body),
+    };
+    Ok(Listed {
+        filename: match &envelope {
+            Some(envelope) if !envelope.filename.is_empty() => envelope.filename.clone(),
+            _ => file.filename.clone(),
+        },
+        size: match &envelope {
+            Some(envelope) => Some(envelope.size),
+            None if encryption.is_some() => None,
+            None => Some(body.len() as u64),
+        },
+        stored_size: file.data.len(),
+        modified: envelope.as_ref().and_then(|envelope| envelope.modified),
+        sha256: envelope.as_ref().map(|envelope| envelope.sha256),
+        compressed: match &envelope {
+            Some(envelope) => Some(envelope.compressed),
+            None if encryption.is_some() => None,
+            None => Some(compress::compression(body).is_some()),
+        },
+        encryption,
+        key: file.key.clone(),
+        mime_type: file.mime_type.clone(),
+        carrier: file.carrier,
+        frames,
+        archive: envelope.as_ref().is_some_and(|envelope| envelope.archive),
+    })
+}
+
+/// Returns true if `frame` was placed by id3stego.
+///
+/// Frames placed by id3stego have a key (description or owner identifier) containing 'id3stego',
+/// or else data starting with an envelope or chunk header (damaged frames of other programs are
+/// skipped, not reported).
+pub(crate) fn is_id3stego_frame(frame: &Frame) -> bool {
+    if carrier::frame_key(frame).is_some_and(|key| key.contains(KEY_PREFIX)) {
+        return true;
+    }
+    match Record::from_frame(frame) {
+        Some(Ok(record)) => is_id3stego_record(&record),
+        _ => false,
+    }
+}
+
+/// Returns true if `record` (from a carrier shared with other tools) was written by id3stego:
+/// its key contains id3stego's prefix, or its data starts with an envelope or chunk header.
+pub(crate) fn is_id3stego_record(record: &Record) -> bool {
+    record.key.contains(KEY_PREFIX) || envelope::is_envelope(&record.data) || ChunkHeader::parse(&record.data).is_some()
+}
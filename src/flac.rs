@@ -0,0 +1,194 @@
+use crate::carrier::{Carrier, Record};
+use crate::error::{Error, Result};
+use crate::extract::is_id3stego_record;
+
+// FLAC files start with b"fLaC" and a list of metadata blocks, followed by the audio frames.
+// Every metadata block starts with a 4 byte header:
+//
+// | Field  | Size    | Value                                                            |
+// |--------+---------+------------------------------------------------------------------|
+// | type   | 1 byte  | bit 7 = last metadata block, bits 0-6 = block type               |
+// | length | 3 bytes | length of the block data (big endian, at most 2^24 - 1 bytes)   |
+//
+// Embedded files are stored in blocks of their own, inserted after the last existing block
+// (but before trailing PADDING), so STREAMINFO, every other block, and the audio frames are
+// copied byte for byte:
+//  - APPLICATION (type 2): application id b"I3SG", then the record packed as key \0 filename \0
+//    mime-type \0 data (see carrier.rs)
+//  - PICTURE (type 6): picture type 0 ("other"), the mime-type and description (the key) fields,
+//    width, height, depth, and colors 0, then the data
+//
+// APPLICATION blocks with id3stego's application id always belong to id3stego; PICTURE blocks
+// only if they look like it (see is_id3stego_record).
+
+const FLAC_MAGIC: &[u8; 4] = b"fLaC";
+const BLOCK_HEADER_LEN: usize = 4;
+const LAST_BLOCK: u8 = 0x80;
+const MAX_BLOCK_LEN: usize = (1 << 24) - 1;
+
+const BLOCK_STREAMINFO: u8 = 0;
+const BLOCK_PADDING: u8 = 1;
+const BLOCK_APPLICATION: u8 = 2;
+const BLOCK_PICTURE: u8 = 6;
+
+/// Application id of APPLICATION blocks written by id3stego.
+const APPLICATION_ID: &[u8; 4] = b"I3SG";
+const PICTURE_TYPE_OTHER: u32 = 0;
+
+/// One metadata block: its type and data (without the header).
+struct Block<'a> {
+    block_type: u8,
+    data: &'a [u8],
+}
+
+/// Returns true if `bytes` starts with flac magic bytes.
+pub(crate) fn is_flac(bytes: &[u8]) -> bool {
+    bytes.len() >= 4 && &bytes[..4] == FLAC_MAGIC
+}
+
+/// Reads every record stored by id3stego in the flac file `bytes`.
+pub(crate) fn read_records(bytes: &[u8]) -> Result<Vec<Record>> {
+    let (blocks, _) = parse(bytes)?;
+    let mut records = Vec::new();
+    for block in &blocks {
+        if let Some(record) = block_record(block)? {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+/// Returns a copy of the flac file `bytes` with one new metadata block per record.
+pub(crate) fn add_records(bytes: &[u8], records: &[Record]) -> Result<Vec<u8>> {
+    let (blocks, audio_start) = parse(bytes)?;
+    let mut new_blocks = Vec::with_capacity(records.len());
+    for record in records {
+        new_blocks.push(match record.carrier {
+            Carrier::FlacPicture => (BLOCK_PICTURE, picture_data(record)),
+            _ => (BLOCK_APPLICATION, [APPLICATION_ID.as_slice(), &record.to_native()].concat()),
+        });
+    }
+    for (_, data) in &new_blocks {
+        if data.len() > MAX_BLOCK_LEN {
+            return Err(Error::PayloadTooLarge { size: data.len(), max: MAX_BLOCK_LEN });
+        }
+    }
+
+    // new blocks go in front of trailing padding, so tag editors can still grow other blocks in place
+    let padding_start = blocks.iter().rposition(|block| block.block_type != BLOCK_PADDING).map_or(0, |index| index + 1);
+    let mut output_blocks: Vec<(u8, &[u8])> = Vec::with_capacity(blocks.len() + new_blocks.len());
+    output_blocks.extend(blocks[..padding_start].iter().map(|block| (block.block_type, block.data)));
+    output_blocks.extend(new_blocks.iter().map(|(block_type, data)| (*block_type, data.as_slice())));
+    output_blocks.extend(blocks[padding_start..].iter().map(|block| (block.block_type, block.data)));
+    Ok(write(&output_blocks, &bytes[audio_start..]))
+}
+
+/// Returns a copy of the flac file `bytes` without the blocks holding id3stego records, with
+/// the removed records (`None` for damaged APPLICATION blocks, which are removed too).
+pub(crate) fn remove_records(bytes: &[u8]) -> Result<(Vec<u8>, Vec<Option<Record>>)> {
+    let (blocks, audio_start) = parse(bytes)?;
+    let mut kept = Vec::with_capacity(blocks.len());
+    let mut removed = Vec::new();
+    for block in &blocks {
+        match block_record(block) {
+            Ok(Some(record)) => removed.push(Some(record)),
+            Err(_) => removed.push(None),
+            Ok(None) => kept.push((block.block_type, block.data)),
+        }
+    }
+    Ok((write(&kept, &bytes[audio_start..]), removed))
+}
+
+fn parse(bytes: &[u8]) -> Result<(Vec<Block<'_>>, usize)> {
+    // returns metadata blocks and the offset of the first audio frame
+    let invalid = |reason| Error::InvalidContainer { format: "flac", reason };
+    if !is_flac(bytes) {
+        return Err(invalid("missing fLaC marker"));
+    }
+    let mut blocks = Vec::new();
+    let mut offset = FLAC_MAGIC.len();
+    loop {
+        if bytes.len() < offset + BLOCK_HEADER_LEN {
+            return Err(invalid("metadata block header is truncated"));
+        }
+        let header = &bytes[offset..offset + BLOCK_HEADER_LEN];
+        let length = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        let data_start = offset + BLOCK_HEADER_LEN;
+        if bytes.len() < data_start + length {
+            return Err(invalid("metadata block is truncated"));
+        }
+        blocks.push(Block { block_type: header[0] & !LAST_BLOCK, data: &bytes[data_start..data_start + length] });
+        offset = data_start + length;
+        if header[0] & LAST_BLOCK != 0 {
+            break;
+        }
+    }
+    if blocks.first().map(|block| block.block_type) != Some(BLOCK_STREAMINFO) {
+        return Err(invalid("first metadata block is not STREAMINFO"));
+    }
+    Ok((blocks, offset))
+}
+
+fn write(blocks: &[(u8, &[u8])], audio: &[u8]) -> Vec<u8> {
+    let blocks_len: usize = blocks.iter().map(|(_, data)| BLOCK_HEADER_LEN + data.len()).sum();
+    let mut output = Vec::with_capacity(FLAC_MAGIC.len() + blocks_len + audio.len());
+    output.extend_from_slice(FLAC_MAGIC);
+    for (index, (block_type, data)) in blocks.iter().enumerate() {
+        let last = if index + 1 == blocks.len() { LAST_BLOCK } else { 0 };
+        output.push(block_type | last);
+        output.extend_from_slice(&(data.len() as u32).to_be_bytes()[1..]);
+        output.extend_from_slice(data);
+    }
+    output.extend_from_slice(audio);
+    output
+}
+
+fn block_record(block: &Block) -> Result<Option<Record>> {
+    // success: return the record held by an id3stego block, or none for any other block
+    // failure: an APPLICATION block with id3stego's id that cannot be read
+    match block.block_type {
+        BLOCK_APPLICATION if block.data.starts_with(APPLICATION_ID) => {
+            match Record::from_native(Carrier::FlacApplication, &block.data[APPLICATION_ID.len()..]) {
+                Some(record) => Ok(Some(record)),
+                None => Err(Error::InvalidContainer { format: "flac", reason: "APPLICATION block is missing key, filename, or mime-type" }),
+            }
+        }
+        BLOCK_PICTURE => Ok(parse_picture(block.data, Carrier::FlacPicture).filter(is_id3stego_record)),
+        _ => Ok(None),
+    }
+}
+
+/// Packs `record` as the data of a PICTURE block (also used by ogg's METADATA_BLOCK_PICTURE).
+pub(crate) fn picture_data(record: &Record) -> Vec<u8> {
+    let mut data = Vec::with_capacity(32 + record.mime_type.len() + record.key.len() + record.data.len());
+    data.extend_from_slice(&PICTURE_TYPE_OTHER.to_be_bytes());
+    data.extend_from_slice(&(record.mime_type.len() as u32).to_be_bytes());
+    data.extend_from_slice(record.mime_type.as_bytes());
+    data.extend_from_slice(&(record.key.len() as u32).to_be_bytes());
+    data.extend_from_slice(record.key.as_bytes());
+    // width, height, color depth, and number of colors are unknown
+    data.extend_from_slice(&[0; 16]);
+    data.extend_from_slice(&(record.data.len() as u32).to_be_bytes());
+    data.extend_from_slice(&record.data);
+    data
+}
+
+/// Reads the record packed by [`picture_data`], stored in `carrier`.
+pub(crate) fn parse_picture(data: &[u8], carrier: Carrier) -> Option<Record> {
+    // skips picture type, then reads length prefixed fields
+    let mut offset = 4;
+    let mime_type = String::from_utf8(read_field(data, &mut offset)?.to_vec()).ok()?;
+    let key = String::from_utf8(read_field(data, &mut offset)?.to_vec()).ok()?;
+    // width, height, color depth, and number of colors come before the data
+    offset += 16;
+    let picture = read_field(data, &mut offset)?;
+    Some(Record { key, filename: String::new(), mime_type, carrier, data: picture.to_vec() })
+}
+
+fn read_field<'a>(data: &'a [u8], offset: &mut usize) -> Option<&'a [u8]> {
+    // reads one field (big endian u32 length, then data) at offset, moving offset past it
+    let len = u32::from_be_bytes(data.get(*offset..*offset + 4)?.try_into().ok()?) as usize;
+    let field = data.get(*offset + 4..(*offset + 4).checked_add(len)?)?;
+    *offset += 4 + len;
+    Some(field)
+}
@@ -0,0 +1,501 @@
+use crate::carrier::{Carrier, Record, TextEncoding};
+use crate::error::{Error, Result};
+use crate::extract::is_id3stego_record;
+use crate::lsb::{self, Cover};
+
+// WAV (RIFF) and AIFF (IFF) files are a root chunk holding a list of chunks:
+//
+// | Field | Size    | Value                                                                |
+// |-------+---------+----------------------------------------------------------------------|
+// | id    | 4 bytes | b"RIFF" (wav, sizes little endian) or b"FORM" (aiff, big endian)     |
+// | size  | 4 bytes | size of everything after this field                                  |
+// | type  | 4 bytes | b"WAVE", or b"AIFF" / b"AIFC"                                        |
+//
+// followed by chunks, each an id (4 bytes), a size (4 bytes), and the data, plus one padding
+// byte if the size is odd (so every chunk starts on an even offset).
+//
+// Embedded files are stored in chunks of their own, appended after the last chunk of the root
+// chunk (whose size is updated); every other chunk, the 'ID3 ' chunk included, is copied
+// (the 'ID3 ' chunk holding the id3v2 tag is written by write_id3_chunk):
+//  - wav custom chunk (id b"I3SG"): the record packed as key \0 filename \0 mime-type \0 data
+//    (see carrier.rs)
+//  - wav LIST/INFO sub-chunk (id b"I3SG"): the packed record text encoded with base64 or base85
+//    like TXXX and COMM frames, null terminated (INFO values are text), added to the existing
+//    LIST/INFO chunk or to a new one
+//  - aiff APPL chunk: application signature b"I3SG", then the packed record
+//  - aiff ANNO chunk: the packed record text encoded with base64 or base85 (annotations are text)
+//
+// AIFF-C files (form type b"AIFC") have the same chunk layout; their compressed sound data is
+// copied like every other chunk. Chunks (and sub-chunks) with id3stego's id, and APPL chunks with
+// its signature, always belong to id3stego; ANNO chunks only if they look like it (see
+// is_id3stego_record).
+//
+// The integer PCM samples ('data' chunk of wav files, 'SSND' chunk of aiff files) can hide
+// embedded files as well, in their least significant bits (see lsb.rs).
+
+const CHUNK_HEADER_LEN: usize = 8;
+const ROOT_HEADER_LEN: usize = 12;
+
+/// Chunk id of wav chunks (and LIST/INFO sub-chunks), and signature of aiff APPL chunks, written
+/// by id3stego.
+const ID3STEGO_CHUNK_ID: &[u8; 4] = b"I3SG";
+const APPLICATION_CHUNK_ID: &[u8; 4] = b"APPL";
+const ANNOTATION_CHUNK_ID: &[u8; 4] = b"ANNO";
+const LIST_CHUNK_ID: &[u8; 4] = b"LIST";
+const ID3_CHUNK_ID: &[u8; 4] = b"ID3 ";
+const INFO_LIST_TYPE: &[u8; 4] = b"INFO";
+const WAV_FORMAT_CHUNK_ID: &[u8; 4] = b"fmt ";
+const WAV_DATA_CHUNK_ID: &[u8; 4] = b"data";
+const AIFF_COMMON_CHUNK_ID: &[u8; 4] = b"COMM";
+const AIFF_SOUND_CHUNK_ID: &[u8; 4] = b"SSND";
+
+/// wav format tags of integer PCM samples (plain, or WAVE_FORMAT_EXTENSIBLE with a PCM sub-format).
+const WAV_FORMAT_PCM: u16 = 1;
+const WAV_FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+/// One chunk: its id and data (without header and padding).
+struct Chunk<'a> {
+    id: [u8; 4],
+    data: &'a [u8],
+    /// Offset of the data in the whole file.
+    offset: usize,
+}
+
+/// Where the integer PCM samples of a wav or aiff file are (see lsb.rs).
+pub(crate) struct Samples {
+    /// Offset of the first sample in the whole file.
+    pub offset: usize,
+    /// Number of samples (of every channel).
+    pub count: usize,
+    /// Bits per sample (8, 16, or 24).
+    pub bits: u16,
+    /// Whether samples are big endian (aiff) rather than little endian (wav, aiff-c 'sowt').
+    pub big_endian: bool,
+    /// Whether samples are signed (all but 8 bit wav samples, which are unsigned).
+    pub signed: bool,
+}
+
+impl Samples {
+    /// Offset in the whole file of the byte holding the least significant bit of sample `index`.
+    pub fn lsb_offset(&self, index: usize) -> usize {
+        let width = self.bits as usize / 8;
+        let start = self.offset + index * width;
+        if self.big_endian { start + width - 1 } else { start }
+    }
+
+    /// Reads sample `index` of the whole file `bytes` as an unsigned integer of `bits` bits (two's
+    /// complement for signed samples).
+    pub fn read(&self, bytes: &[u8], index: usize) -> u32 {
+        let width = self.bits as usize / 8;
+        let sample = &bytes[self.offset + index * width..self.offset + (index + 1) * width];
+        match self.big_endian {
+            true => sample.iter().fold(0, |value, byte| (value << 8) | *byte as u32),
+            false => sample.iter().rev().fold(0, |value, byte| (value << 8) | *byte as u32),
+        }
+    }
+
+    /// Writes `value` (as read by [`read`](Samples::read)) to sample `index` of the whole file `bytes`.
+    pub fn write(&self, bytes: &mut [u8], index: usize, value: u32) {
+        let width = self.bits as usize / 8;
+        let sample = &mut bytes[self.offset + index * width..self.offset + (index + 1) * width];
+        for (position, byte) in sample.iter_mut().enumerate() {
+            let shift = if self.big_endian { width - 1 - position } else { position };
+            *byte = (value >> (8 * shift)) as u8;
+        }
+    }
+}
+
+impl Cover for Samples {
+    fn carrier(&self) -> Carrier {
+        Carrier::PcmLsb
+    }
+
+    fn format(&self) -> &'static str {
+        "wav or aiff"
+    }
+
+    fn count(&self) -> usize {
+        self.count
+    }
+
+    fn lsb(&self, bytes: &[u8], index: usize) -> u8 {
+        bytes[self.lsb_offset(index)] & 1
+    }
+
+    fn change(&self, bytes: &mut [u8], index: usize, matching: bool) {
+        match matching {
+            true => self.write(bytes, index, lsb::step(self.read(bytes, index), self.bits, self.signed)),
+            false => bytes[self.lsb_offset(index)] ^= 1,
+        }
+    }
+}
+
+/// The root chunk of a wav or aiff file.
+struct Root<'a> {
+    format: &'static str,
+    big_endian: bool,
+    /// Root chunk id and form type (the size is rewritten).
+    id: &'a [u8],
+    form_type: &'a [u8],
+    chunks: Vec<Chunk<'a>>,
+    /// Bytes after the root chunk (copied unchanged).
+    trailing: &'a [u8],
+}
+
+/// Reads every record stored by id3stego in the chunks of the wav or aiff file `bytes`.
+pub(crate) fn read_records(bytes: &[u8]) -> Result<Vec<Record>> {
+    let root = parse(bytes)?;
+    let mut records = Vec::new();
+    for chunk in &root.chunks {
+        if is_info_list(chunk, &root) {
+            for sub_chunk in parse_chunks(&chunk.data[INFO_LIST_TYPE.len()..], chunk.offset + INFO_LIST_TYPE.len(), &root)? {
+                if let Some(record) = info_record(&sub_chunk, &root)? {
+                    records.push(record);
+                }
+            }
+        }
+        else if let Some(record) = chunk_record(chunk, &root)? {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+/// Returns a copy of the wav or aiff file `bytes` with one new chunk (or sub-chunk) per record
+/// (text encoded with `encoding` where the chunk holds text).
+pub(crate) fn add_records(bytes: &[u8], records: &[Record], encoding: TextEncoding) -> Result<Vec<u8>> {
+    let root = parse(bytes)?;
+    let mut info_entries = Vec::new();
+    let mut new_chunks = Vec::new();
+    for record in records {
+        match record.carrier {
+            Carrier::WavInfo => {
+                let text = format!("{}\0", encoding.encode(&record.to_native()));
+                info_entries.extend(chunk(ID3STEGO_CHUNK_ID, text.as_bytes(), &root)?);
+            }
+            Carrier::AiffAppl => {
+                let data = [ID3STEGO_CHUNK_ID.as_slice(), &record.to_native()].concat();
+                new_chunks.extend(chunk(APPLICATION_CHUNK_ID, &data, &root)?);
+            }
+            Carrier::AiffAnno => {
+                let text = encoding.encode(&record.to_native());
+                new_chunks.extend(chunk(ANNOTATION_CHUNK_ID, text.as_bytes(), &root)?);
+            }
+            _ => new_chunks.extend(chunk(ID3STEGO_CHUNK_ID, &record.to_native(), &root)?),
+        }
+    }
+
+    // info entries go into the first LIST/INFO chunk, or into a new one
+    let mut body = Vec::with_capacity(bytes.len() + info_entries.len() + new_chunks.len());
+    let mut info_list_found = false;
+    for existing in &root.chunks {
+        if is_info_list(existing, &root) && !info_list_found && !info_entries.is_empty() {
+            info_list_found = true;
+            body.extend(chunk(LIST_CHUNK_ID, &[existing.data, &info_entries].concat(), &root)?);
+        }
+        else {
+            body.extend(chunk(&existing.id, existing.data, &root)?);
+        }
+    }
+    if !info_list_found && !info_entries.is_empty() {
+        body.extend(chunk(LIST_CHUNK_ID, &[INFO_LIST_TYPE.as_slice(), &info_entries].concat(), &root)?);
+    }
+    body.extend(new_chunks);
+    write(&root, &body)
+}
+
+/// Returns a copy of the wav or aiff file `bytes` without the chunks (and sub-chunks) holding
+/// id3stego records, with the removed records (`None` for damaged ones, which are removed too).
+pub(crate) fn remove_records(bytes: &[u8]) -> Result<(Vec<u8>, Vec<Option<Record>>)> {
+    let root = parse(bytes)?;
+    let mut removed = Vec::new();
+    let mut body = Vec::with_capacity(bytes.len());
+    for existing in &root.chunks {
+        if is_info_list(existing, &root) {
+            let mut entries = INFO_LIST_TYPE.to_vec();
+            let mut entries_removed = false;
+            for sub_chunk in parse_chunks(&existing.data[INFO_LIST_TYPE.len()..], existing.offset + INFO_LIST_TYPE.len(), &root)? {
+                if keep(info_record(&sub_chunk, &root), &mut removed) {
+                    entries.extend(chunk(&sub_chunk.id, sub_chunk.data, &root)?);
+                }
+                else {
+                    entries_removed = true;
+                }
+            }
+            // a LIST/INFO chunk left empty is removed with its entries
+            match entries_removed {
+                true if entries.len() == INFO_LIST_TYPE.len() => {}
+                true => body.extend(chunk(LIST_CHUNK_ID, &entries, &root)?),
+                false => body.extend(chunk(&existing.id, existing.data, &root)?),
+            }
+        }
+        else if keep(chunk_record(existing, &root), &mut removed) {
+            body.extend(chunk(&existing.id, existing.data, &root)?);
+        }
+    }
+    if removed.is_empty() {
+        return Ok((bytes.to_vec(), removed));
+    }
+    Ok((write(&root, &body)?, removed))
+}
+
+/// Finds the integer PCM samples of the wav or aiff file `bytes` (8, 16, or 24 bit, any number of
+/// channels); other sample formats and compressed aiff-c files fail with
+/// [`Error::UnsupportedSamples`].
+pub(crate) fn pcm_samples(bytes: &[u8]) -> Result<Samples> {
+    let root = parse(bytes)?;
+    let invalid = |reason| Error::InvalidContainer { format: root.format, reason };
+    let unsupported = |reason| Error::UnsupportedSamples { format: root.format, reason };
+    let find = |id: &[u8; 4]| root.chunks.iter().find(|chunk| &chunk.id == id);
+
+    // wav: format tag, channels, sample rate, byte rate, block align, bits per sample ('fmt ')
+    // aiff: channels, sample frames, bits per sample, sample rate, aiff-c compression ('COMM')
+    let (channels, bits, frame_size, big_endian, sound) = match root.big_endian {
+        false => {
+            let format = find(WAV_FORMAT_CHUNK_ID).ok_or_else(|| invalid("missing fmt chunk"))?.data;
+            if format.len() < 16 {
+                return Err(invalid("fmt chunk is truncated"));
+            }
+            let format_tag = u16::from_le_bytes([format[0], format[1]]);
+            let pcm_sub_format = format.get(24..26) == Some(WAV_FORMAT_PCM.to_le_bytes().as_slice());
+            if format_tag != WAV_FORMAT_PCM && !(format_tag == WAV_FORMAT_EXTENSIBLE && pcm_sub_format) {
+                return Err(unsupported("samples are not integer pcm"));
+            }
+            let channels = u16::from_le_bytes([format[2], format[3]]) as usize;
+            let block_align = u16::from_le_bytes([format[12], format[13]]) as usize;
+            let bits = u16::from_le_bytes([format[14], format[15]]);
+            let data = find(WAV_DATA_CHUNK_ID).ok_or_else(|| invalid("missing data chunk"))?;
+            (channels, bits, block_align, false, (data.offset, data.data.len()))
+        }
+        true => {
+            let common = find(AIFF_COMMON_CHUNK_ID).ok_or_else(|| invalid("missing COMM chunk"))?.data;
+            if common.len() < 18 {
+                return Err(invalid("COMM chunk is truncated"));
+            }
+            let big_endian = match common.get(18..22) {
+                _ if root.form_type != b"AIFC" => true,
+                Some(b"NONE") | Some(b"twos") => true,
+                Some(b"sowt") => false,
+                _ => return Err(unsupported("sound data is compressed")),
+            };
+            let channels = u16::from_be_bytes([common[0], common[1]]) as usize;
+            let frames = read_u32(&common[2..6], true) as usize;
+            let bits = u16::from_be_bytes([common[6], common[7]]);
+            let sound = find(AIFF_SOUND_CHUNK_ID).ok_or_else(|| invalid("missing SSND chunk"))?;
+            // sound data starts after its offset field, block size field, and offset bytes
+            if sound.data.len() < 8 {
+                return Err(invalid("SSND chunk is truncated"));
+            }
+            let start = (8 + read_u32(&sound.data[..4], true) as usize).min(sound.data.len());
+            let frame_size = channels * (bits as usize).div_ceil(8);
+            let len = (sound.data.len() - start).min(frames.saturating_mul(frame_size));
+            (channels, bits, frame_size, big_endian, (sound.offset + start, len))
+        }
+    };
+    if !matches!(bits, 8 | 16 | 24) {
+        return Err(unsupported("only 8, 16, and 24 bit samples are supported"));
+    }
+    if channels == 0 || frame_size != channels * bits as usize / 8 {
+        return Err(unsupported("sample frames are padded or empty"));
+    }
+    let (offset, len) = sound;
+    // 8 bit wav samples are the only unsigned ones
+    let signed = root.big_endian || bits != 8;
+    Ok(Samples { offset, count: len / frame_size * channels, bits, big_endian, signed })
+}
+
+/// Duration of the wav or aiff file `bytes` in seconds: sound data over byte rate ('fmt ') for
+/// wav, sample frames over sample rate ('COMM') for aiff.
+pub(crate) fn duration(bytes: &[u8]) -> Result<f64> {
+    let root = parse(bytes)?;
+    let invalid = |reason| Error::InvalidContainer { format: root.format, reason };
+    let find = |id: &[u8; 4]| root.chunks.iter().find(|chunk| &chunk.id == id);
+
+    match root.big_endian {
+        false => {
+            let format = find(WAV_FORMAT_CHUNK_ID).ok_or_else(|| invalid("missing fmt chunk"))?.data;
+            if format.len() < 16 {
+                return Err(invalid("fmt chunk is truncated"));
+            }
+            let byte_rate = read_u32(&format[8..12], false);
+            let data = find(WAV_DATA_CHUNK_ID).ok_or_else(|| invalid("missing data chunk"))?;
+            match byte_rate {
+                0 => Err(invalid("byte rate is zero")),
+                _ => Ok(data.data.len() as f64 / byte_rate as f64),
+            }
+        }
+        true => {
+            let common = find(AIFF_COMMON_CHUNK_ID).ok_or_else(|| invalid("missing COMM chunk"))?.data;
+            if common.len() < 18 {
+                return Err(invalid("COMM chunk is truncated"));
+            }
+            let frames = read_u32(&common[2..6], true);
+            // 80 bit ieee 754 extended float: sign and 15 bit exponent, then 64 bit mantissa with
+            // an explicit integer bit
+            let exponent = (u16::from_be_bytes([common[8], common[9]]) & 0x7fff) as i32;
+            let mut mantissa = [0; 8];
+            mantissa.copy_from_slice(&common[10..18]);
+            let sample_rate = u64::from_be_bytes(mantissa) as f64 * 2f64.powi(exponent - 16383 - 63);
+            match sample_rate {
+                rate if rate > 0.0 && rate.is_finite() => Ok(frames as f64 / sample_rate),
+                _ => Err(invalid("sample rate is zero")),
+            }
+        }
+    }
+}
+
+/// Data of the 'ID3 ' chunk (or 'id3 ', as some programs write it) of the wav or aiff file
+/// `bytes`: the raw id3v2 tag, if there is one.
+pub(crate) fn id3_chunk(bytes: &[u8]) -> Option<&[u8]> {
+    let root = parse(bytes).ok()?;
+    root.chunks.iter().find(|chunk| chunk.id.eq_ignore_ascii_case(ID3_CHUNK_ID)).map(|chunk| chunk.data)
+}
+
+/// Returns a copy of the wav or aiff file `bytes` with its 'ID3 ' chunk holding `encoded_tag`:
+/// an existing chunk is replaced in place, otherwise a new one is appended after the last chunk
+/// (no encoded tag removes the existing chunk instead).
+pub(crate) fn write_id3_chunk(bytes: &[u8], encoded_tag: Option<&[u8]>) -> Result<Vec<u8>> {
+    let root = parse(bytes)?;
+    let mut body = Vec::with_capacity(bytes.len() + encoded_tag.map_or(0, |tag| CHUNK_HEADER_LEN + tag.len() + 1));
+    let mut id3_chunk_found = false;
+    for existing in &root.chunks {
+        if existing.id.eq_ignore_ascii_case(ID3_CHUNK_ID) && !id3_chunk_found {
+            id3_chunk_found = true;
+            if let Some(encoded_tag) = encoded_tag {
+                body.extend(chunk(ID3_CHUNK_ID, encoded_tag, &root)?);
+            }
+        }
+        else {
+            body.extend(chunk(&existing.id, existing.data, &root)?);
+        }
+    }
+    match (id3_chunk_found, encoded_tag) {
+        (false, Some(encoded_tag)) => body.extend(chunk(ID3_CHUNK_ID, encoded_tag, &root)?),
+        (false, None) => return Ok(bytes.to_vec()),
+        _ => {}
+    }
+    write(&root, &body)
+}
+
+fn keep(record: Result<Option<Record>>, removed: &mut Vec<Option<Record>>) -> bool {
+    // keeps chunks not written by id3stego, remembers the others (none if damaged)
+    match record {
+        Ok(Some(record)) => removed.push(Some(record)),
+        Err(_) => removed.push(None),
+        Ok(None) => return true,
+    }
+    false
+}
+
+fn read_u32(bytes: &[u8], big_endian: bool) -> u32 {
+    let array = [bytes[0], bytes[1], bytes[2], bytes[3]];
+    if big_endian { u32::from_be_bytes(array) } else { u32::from_le_bytes(array) }
+}
+
+fn parse(bytes: &[u8]) -> Result<Root<'_>> {
+    let (format, big_endian) = match bytes.get(..4) {
+        Some(b"RIFF") => ("wav", false),
+        Some(b"FORM") => ("aiff", true),
+        _ => return Err(Error::InvalidContainer { format: "wav or aiff", reason: "missing RIFF or FORM chunk" }),
+    };
+    if bytes.len() < ROOT_HEADER_LEN {
+        return Err(Error::InvalidContainer { format, reason: "root chunk header is truncated" });
+    }
+    // root chunk size covers everything after the root chunk header, excluding trailing data
+    let root_end = (CHUNK_HEADER_LEN + read_u32(&bytes[4..8], big_endian) as usize).clamp(ROOT_HEADER_LEN, bytes.len());
+    let mut root = Root {
+        format,
+        big_endian,
+        id: &bytes[..4],
+        form_type: &bytes[8..12],
+        chunks: Vec::new(),
+        trailing: &bytes[root_end..],
+    };
+    root.chunks = parse_chunks(&bytes[ROOT_HEADER_LEN..root_end], ROOT_HEADER_LEN, &root)?;
+    Ok(root)
+}
+
+/// Parses the chunks of `data`, found at offset `base` of the whole file.
+fn parse_chunks<'a>(data: &'a [u8], base: usize, root: &Root) -> Result<Vec<Chunk<'a>>> {
+    let mut chunks = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        if data.len() - offset < CHUNK_HEADER_LEN {
+            return Err(Error::InvalidContainer { format: root.format, reason: "chunk header is truncated" });
+        }
+        let size = read_u32(&data[offset + 4..offset + 8], root.big_endian) as usize;
+        let data_start = offset + CHUNK_HEADER_LEN;
+        if data.len() - data_start < size {
+            return Err(Error::InvalidContainer { format: root.format, reason: "chunk is truncated" });
+        }
+        let mut id = [0; 4];
+        id.copy_from_slice(&data[offset..offset + 4]);
+        chunks.push(Chunk { id, data: &data[data_start..data_start + size], offset: base + data_start });
+        // the padding byte of a last chunk is sometimes missing
+        offset = (data_start + size + size % 2).min(data.len());
+    }
+    Ok(chunks)
+}
+
+fn chunk(id: &[u8; 4], data: &[u8], root: &Root) -> Result<Vec<u8>> {
+    let size = u32::try_from(data.len()).map_err(|_| Error::PayloadTooLarge { size: data.len(), max: u32::MAX as usize })?;
+    let mut chunk = Vec::with_capacity(CHUNK_HEADER_LEN + data.len() + 1);
+    chunk.extend_from_slice(id);
+    chunk.extend_from_slice(&if root.big_endian { size.to_be_bytes() } else { size.to_le_bytes() });
+    chunk.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        chunk.push(0);
+    }
+    Ok(chunk)
+}
+
+fn write(root: &Root, body: &[u8]) -> Result<Vec<u8>> {
+    let size = root.form_type.len() + body.len();
+    let size = u32::try_from(size).map_err(|_| Error::PayloadTooLarge { size, max: u32::MAX as usize })?;
+    let mut output = Vec::with_capacity(ROOT_HEADER_LEN + body.len() + root.trailing.len());
+    output.extend_from_slice(root.id);
+    output.extend_from_slice(&if root.big_endian { size.to_be_bytes() } else { size.to_le_bytes() });
+    output.extend_from_slice(root.form_type);
+    output.extend_from_slice(body);
+    output.extend_from_slice(root.trailing);
+    Ok(output)
+}
+
+fn is_info_list(chunk: &Chunk, root: &Root) -> bool {
+    // only wav files have LIST chunks
+    !root.big_endian && &chunk.id == LIST_CHUNK_ID && chunk.data.starts_with(INFO_LIST_TYPE)
+}
+
+fn chunk_record(chunk: &Chunk, root: &Root) -> Result<Option<Record>> {
+    // success: return the record held by an id3stego chunk, or none for any other chunk
+    // failure: an I3SG chunk (or APPL chunk with id3stego's signature) that cannot be read
+    let (carrier, packed) = match (root.big_endian, &chunk.id) {
+        (false, ID3STEGO_CHUNK_ID) => (Carrier::WavChunk, chunk.data),
+        (true, APPLICATION_CHUNK_ID) if chunk.data.starts_with(ID3STEGO_CHUNK_ID) => {
+            (Carrier::AiffAppl, &chunk.data[ID3STEGO_CHUNK_ID.len()..])
+        }
+        (true, ANNOTATION_CHUNK_ID) => {
+            let packed = std::str::from_utf8(chunk.data).ok().and_then(TextEncoding::decode);
+            let record = packed.and_then(|packed| Record::from_native(Carrier::AiffAnno, &packed));
+            return Ok(record.filter(is_id3stego_record));
+        }
+        _ => return Ok(None),
+    };
+    match Record::from_native(carrier, packed) {
+        Some(record) => Ok(Some(record)),
+        None => Err(Error::InvalidContainer { format: root.format, reason: "I3SG chunk is missing key, filename, or mime-type" }),
+    }
+}
+
+fn info_record(sub_chunk: &Chunk, root: &Root) -> Result<Option<Record>> {
+    // same as chunk_record, for LIST/INFO sub-chunks (null terminated text)
+    if &sub_chunk.id != ID3STEGO_CHUNK_ID {
+        return Ok(None);
+    }
+    let text = std::str::from_utf8(sub_chunk.data).ok().map(|text| text.trim_end_matches('\0'));
+    let packed = text.and_then(TextEncoding::decode);
+    match packed.and_then(|packed| Record::from_native(Carrier::WavInfo, &packed)) {
+        Some(record) => Ok(Some(record)),
+        None => Err(Error::InvalidContainer { format: root.format, reason: "I3SG info entry is not a base64 or base85 encoded record" }),
+    }
+}
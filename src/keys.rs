@@ -0,0 +1,110 @@
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD_NO_PAD as BASE64;
+use rand::rngs::OsRng;
+use x25519_dalek::{PublicKey, StaticSecret};
+use crate::error::{Error, Result};
+
+// Key files hold one key per line, as a type prefix followed by the base64 encoded key:
+//  - public key (recipient): "id3stego-public-" + base64
+//  - private key (identity): "ID3STEGO-PRIVATE-" + base64
+// Lines starting with '#' are comments. Private key files written by keygen also include the
+// matching public key as a comment.
+
+const RECIPIENT_PREFIX: &str = "id3stego-public-";
+const IDENTITY_PREFIX: &str = "ID3STEGO-PRIVATE-";
+
+/// An x25519 public key that embedded files can be encrypted to.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Recipient(pub(crate) PublicKey);
+
+/// An x25519 private key that can decrypt files encrypted to its [`Recipient`].
+#[derive(Clone)]
+pub struct Identity(pub(crate) StaticSecret);
+
+impl Identity {
+    /// Generates a new random private key.
+    pub fn generate() -> Identity {
+        Identity(StaticSecret::random_from_rng(OsRng))
+    }
+
+    /// Public key matching this private key.
+    pub fn recipient(&self) -> Recipient {
+        Recipient(PublicKey::from(&self.0))
+    }
+
+    /// Reads every private key in the key file at `path`.
+    pub fn read_from_path(path: impl AsRef<Path>) -> Result<Vec<Identity>> {
+        parse_key_file(&std::fs::read_to_string(path)?)
+    }
+
+    /// Text form of this private key (as written to key files).
+    pub fn to_secret_string(&self) -> String {
+        format!("{}{}", IDENTITY_PREFIX, BASE64.encode(self.0.as_bytes()))
+    }
+}
+
+impl Recipient {
+    /// Reads every public key in the key file at `path`.
+    pub fn read_from_path(path: impl AsRef<Path>) -> Result<Vec<Recipient>> {
+        parse_key_file(&std::fs::read_to_string(path)?)
+    }
+}
+
+impl fmt::Display for Recipient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", RECIPIENT_PREFIX, BASE64.encode(self.0.as_bytes()))
+    }
+}
+
+impl fmt::Debug for Recipient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Recipient({})", self)
+    }
+}
+
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Identity({})", self.recipient())
+    }
+}
+
+impl FromStr for Recipient {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Recipient> {
+        let bytes = decode_key(text, RECIPIENT_PREFIX)?;
+        Ok(Recipient(PublicKey::from(bytes)))
+    }
+}
+
+impl FromStr for Identity {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Identity> {
+        let bytes = decode_key(text, IDENTITY_PREFIX)?;
+        Ok(Identity(StaticSecret::from(bytes)))
+    }
+}
+
+fn decode_key(text: &str, prefix: &'static str) -> Result<[u8; 32]> {
+    let invalid = || Error::InvalidKey(format!("expected a key starting with '{}'", prefix));
+    let encoded = text.trim().strip_prefix(prefix).ok_or_else(invalid)?;
+    let bytes = BASE64.decode(encoded).map_err(|_| invalid())?;
+    bytes.try_into().map_err(|_| invalid())
+}
+
+fn parse_key_file<K: FromStr<Err = Error>>(contents: &str) -> Result<Vec<K>> {
+    let keys = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(K::from_str)
+        .collect::<Result<Vec<K>>>()?;
+    if keys.is_empty() {
+        return Err(Error::InvalidKey("no keys found in key file".to_string()));
+    }
+    Ok(keys)
+}
@@ -0,0 +1,60 @@
+//! Audio file (mp3, wav, aiff, flac, ogg, m4a) metadata steganography (for educational use only).
+//!
+//! [`Embedder`] stores other files in the id3v2 tag of mp3, wav, and aiff files, in the native
+//! metadata of flac, ogg, and m4a files, or in the audio data itself (see [`Format`] and
+//! [`Carrier`]). [`Extractor`] gets them back, [`Stripper`] removes them, and [`Detector`] looks
+//! for traces of hidden data. Every embedded file is wrapped in a versioned envelope (size,
+//! SHA-256 digest, filename, and modification time) verified when extracting, and can be
+//! compressed ([`Compression`]) and encrypted with a passphrase or to x25519 [`Recipient`]s.
+//!
+//! ```no_run
+//! use id3stego::{Embedder, Extractor};
+//!
+//! fn main() -> Result<(), id3stego::Error> {
+//!     let embedded = Embedder::new().embed_path("test.mp3", "test.jpg", "output-test.mp3")?;
+//!     println!("embedded {} as {}", embedded.filename, embedded.key);
+//!
+//!     for extracted in Extractor::new().extract_path("output-test.mp3")? {
+//!         match extracted {
+//!             Ok(extracted) => println!("found {} ({} bytes)", extracted.filename, extracted.size()),
+//!             Err(err) => println!("skipped: {}", err),
+//!         }
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+mod archive;
+mod carrier;
+mod chunk;
+mod compress;
+mod container;
+mod crypto;
+mod detect;
+mod embed;
+mod envelope;
+mod error;
+mod extract;
+mod flac;
+mod iff;
+mod keys;
+mod lsb;
+mod mp4;
+mod mpeg;
+mod ogg;
+pub mod paths;
+mod storage;
+mod strip;
+
+pub use crate::carrier::{Carrier, TextEncoding};
+pub use crate::compress::{Compression, DEFAULT_MAX_DECOMPRESSED_SIZE, MAX_DECOMPRESSED_SIZE};
+pub use crate::container::{Format, TagVersion, MAX_FRAME_SIZE, MAX_TAG_SIZE};
+pub use crate::crypto::EncryptionMethod;
+pub use crate::detect::{Check, Detection, Detector, Finding};
+pub use crate::embed::{Embedded, Embedder, KEY_PREFIX, MAX_KEY_LEN};
+pub use crate::error::{Error, Result};
+pub use crate::extract::{Extracted, Extractor, Listed};
+pub use crate::keys::{Identity, Recipient};
+pub use crate::lsb::{Capacity, EmbeddingStats, MAX_MATRIX_K};
+pub use crate::storage::{remove_temp_files, write_atomic, Storage};
+pub use crate::strip::{Stripped, Stripper};
@@ -0,0 +1,327 @@
+use hkdf::Hkdf;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use sha2::Sha256;
+use crate::carrier::{Carrier, Record};
+use crate::error::{Error, Result};
+
+// Wav and aiff files can hide embedded files in the least significant bit (LSB) of their integer
+// PCM samples (8, 16, or 24 bits, every channel), leaving the rest of the file unchanged (mp3
+// files in the LSB of the global gains of their frames, see mpeg.rs; both are a `Cover` of
+// numbered values, called samples below). The hidden bits are:
+//
+// | Field   | Size    | Value                                                                 |
+// |---------+---------+-----------------------------------------------------------------------|
+// | magic   | 4 bytes | b"I3SL"                                                               |
+// | code    | 1 byte  | k of the hamming code hiding the records (1 = one bit per sample)     |
+// | size    | 4 bytes | size of the records (big endian)                                      |
+// | records | size    | for each record: its size (4 bytes, big endian), then the record      |
+// |         |         | packed as key \0 filename \0 mime-type \0 data (see carrier.rs)       |
+//
+// Each byte is read and written most significant bit first. Samples are not used in order, but in
+// the order of a permutation of all samples chosen by the stego key: a Fisher-Yates shuffle
+// driven by chacha20, seeded with hkdf-sha256(ikm = stego key, info = POSITIONS_INFO). Without
+// the stego key there is no telling which samples hold the magic, so get mode with a wrong key
+// finds nothing.
+//
+// The header (magic, code, and size) is one bit per sample. The records use matrix embedding
+// with the (1, 2^k - 1, k) hamming code: every k bits are hidden in the next 2^k - 1 samples of
+// the permutation as the syndrome of their LSBs (the xor of the 1-based numbers of the samples
+// whose LSB is 1), which takes at most one changed sample; the last group is padded with zero
+// bits. k = 1 is plain LSB embedding (one bit per sample).
+//
+// A sample is changed either by flipping its LSB (LSB replacement), or by adding or subtracting 1
+// at random (LSB matching, within the range of the samples), which leaves no pairs of values
+// for statistical attacks to compare. Both read back the same way.
+//
+// Every put rewrites all LSB records at once: records found with the same stego key are kept,
+// records hidden with another stego key are (partly) overwritten. Strip mode leaves the samples
+// alone, since their original LSBs are lost.
+
+const LSB_MAGIC: &[u8; 4] = b"I3SL";
+const HEADER_LEN: usize = 9;
+const RECORD_SIZE_LEN: usize = 4;
+
+/// Largest k of the (1, 2^k - 1, k) hamming codes used for matrix embedding (groups of 4095
+/// samples), see [`Embedder::matrix_embedding`](crate::Embedder::matrix_embedding).
+pub const MAX_MATRIX_K: u8 = 12;
+
+/// hkdf info of the seed of the sample permutation.
+const POSITIONS_INFO: &[u8] = b"id3stego lsb positions";
+
+/// Room for embedded files in the audio data of an audio file, see
+/// [`Embedder::capacity_path`](crate::Embedder::capacity_path).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capacity {
+    /// Carrier the capacity applies to.
+    pub carrier: Carrier,
+    /// Number of places in the audio data that can hold one hidden bit (samples for
+    /// [`Carrier::PcmLsb`], global gains of every granule and channel for
+    /// [`Carrier::Mp3GlobalGain`]), or number of free ancillary bytes for
+    /// [`Carrier::Mp3Ancillary`].
+    pub positions: usize,
+    /// Number of bytes that can be hidden (with the chosen matrix embedding, if any), after
+    /// compression and encryption and with the envelope, key, filename, and mime-type of every
+    /// embedded file (plus 4 bytes per file).
+    pub bytes: usize,
+}
+
+/// How much the audio data was changed to hide embedded files, see
+/// [`Embedded::embedding`](crate::Embedded::embedding).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmbeddingStats {
+    /// Number of bits hidden (headers and every file hidden with the stego key).
+    pub bits: usize,
+    /// Number of samples (or global gains) changed.
+    pub changes: usize,
+}
+
+impl EmbeddingStats {
+    /// Embedding efficiency: bits hidden per changed sample (about 2 for plain LSB embedding,
+    /// k / (1 - 2^-k) with matrix embedding), or `None` if no sample had to change.
+    pub fn bits_per_change(&self) -> Option<f64> {
+        match self.changes {
+            0 => None,
+            changes => Some(self.bits as f64 / changes as f64),
+        }
+    }
+}
+
+/// How bits are hidden in the sample LSBs (see
+/// [`Embedder::lsb_matching`](crate::Embedder::lsb_matching) and
+/// [`Embedder::matrix_embedding`](crate::Embedder::matrix_embedding)).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LsbCoding {
+    /// Change samples by +1 or -1 rather than by flipping their LSB.
+    pub matching: bool,
+    /// k of the hamming code (1 to MAX_MATRIX_K).
+    pub matrix_k: u8,
+}
+
+impl Default for LsbCoding {
+    fn default() -> LsbCoding {
+        LsbCoding { matching: false, matrix_k: 1 }
+    }
+}
+
+impl LsbCoding {
+    fn validate(&self) -> Result<()> {
+        match self.matrix_k {
+            1..=MAX_MATRIX_K => Ok(()),
+            k => Err(Error::InvalidMatrixCode { k }),
+        }
+    }
+}
+
+/// Numbered values of an audio file (its samples) whose LSBs can hide bits.
+pub(crate) trait Cover {
+    /// Carrier of the records hidden in these samples.
+    fn carrier(&self) -> Carrier;
+    /// Container format name, for errors.
+    fn format(&self) -> &'static str;
+    /// Number of samples.
+    fn count(&self) -> usize;
+    /// LSB of sample `index` of the whole file `bytes`.
+    fn lsb(&self, bytes: &[u8], index: usize) -> u8;
+    /// Changes sample `index` of the whole file `bytes` so that its LSB flips, by flipping the LSB
+    /// or (`matching`) by adding or subtracting 1 (see [`step`]).
+    fn change(&self, bytes: &mut [u8], index: usize, matching: bool);
+}
+
+/// Computes how much can be hidden with `coding` in the LSBs of `cover`.
+pub(crate) fn capacity(cover: &impl Cover, coding: LsbCoding) -> Result<Capacity> {
+    coding.validate()?;
+    let groups = cover.count().saturating_sub(HEADER_LEN * 8) / group_len(coding.matrix_k);
+    Ok(Capacity {
+        carrier: cover.carrier(),
+        positions: cover.count(),
+        bytes: groups * coding.matrix_k as usize / 8,
+    })
+}
+
+/// Reads every record hidden with `stego_key` in the LSBs of `cover` of the audio file `bytes`
+/// (none if nothing was hidden with this stego key).
+pub(crate) fn read_records(bytes: &[u8], cover: &impl Cover, stego_key: &str) -> Result<Vec<Record>> {
+    if cover.count() < HEADER_LEN * 8 {
+        return Ok(Vec::new());
+    }
+    let mut positions = Positions::new(cover.count(), stego_key);
+    let header = read_bits(bytes, cover, &mut positions, HEADER_LEN, 1);
+    if &header[..LSB_MAGIC.len()] != LSB_MAGIC {
+        return Ok(Vec::new());
+    }
+    let matrix_k = header[4];
+    if !(1..=MAX_MATRIX_K).contains(&matrix_k) {
+        return Err(invalid(cover, "unknown hamming code"));
+    }
+    let size = u32::from_be_bytes([header[5], header[6], header[7], header[8]]) as usize;
+    if HEADER_LEN * 8 + groups_needed(size, matrix_k) * group_len(matrix_k) > cover.count() {
+        return Err(invalid(cover, "hidden records are larger than the samples"));
+    }
+
+    let packed = read_bits(bytes, cover, &mut positions, size, matrix_k);
+    let mut records = Vec::new();
+    let mut offset = 0;
+    while offset < packed.len() {
+        if packed.len() - offset < RECORD_SIZE_LEN {
+            return Err(invalid(cover, "hidden record size is truncated"));
+        }
+        let record_size = u32::from_be_bytes([packed[offset], packed[offset + 1], packed[offset + 2], packed[offset + 3]]) as usize;
+        let record_start = offset + RECORD_SIZE_LEN;
+        if packed.len() - record_start < record_size {
+            return Err(invalid(cover, "hidden record is truncated"));
+        }
+        match Record::from_native(cover.carrier(), &packed[record_start..record_start + record_size]) {
+            Some(record) => records.push(record),
+            None => return Err(invalid(cover, "hidden record is missing key, filename, or mime-type")),
+        }
+        offset = record_start + record_size;
+    }
+    Ok(records)
+}
+
+/// Returns a copy of the audio file `bytes` with `records` hidden in the LSBs of `cover` with
+/// `stego_key` and `coding`, next to the records already hidden there with the same stego key,
+/// and how much the samples changed.
+pub(crate) fn add_records(bytes: &[u8], cover: &impl Cover, records: &[Record], stego_key: &str, coding: LsbCoding)
+    -> Result<(Vec<u8>, EmbeddingStats)> {
+    let capacity = capacity(cover, coding)?;
+    let mut packed = Vec::new();
+    for record in read_records(bytes, cover, stego_key)?.iter().chain(records) {
+        let record = record.to_native();
+        packed.extend_from_slice(&(record.len() as u32).to_be_bytes());
+        packed.extend_from_slice(&record);
+    }
+    if packed.len() > capacity.bytes {
+        return Err(Error::CapacityExceeded { size: packed.len(), capacity: capacity.bytes });
+    }
+
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(LSB_MAGIC);
+    header.push(coding.matrix_k);
+    header.extend_from_slice(&(packed.len() as u32).to_be_bytes());
+
+    let mut output = bytes.to_vec();
+    let mut positions = Positions::new(cover.count(), stego_key);
+    let changes = write_bits(&mut output, cover, &mut positions, &header, 1, coding.matching)
+        + write_bits(&mut output, cover, &mut positions, &packed, coding.matrix_k, coding.matching);
+    let stats = EmbeddingStats { bits: (header.len() + packed.len()) * 8, changes };
+    Ok((output, stats))
+}
+
+/// Number of samples hiding k bits.
+fn group_len(matrix_k: u8) -> usize {
+    (1 << matrix_k) - 1
+}
+
+/// Number of groups of samples needed to hide `len` bytes.
+fn groups_needed(len: usize, matrix_k: u8) -> usize {
+    (len * 8).div_ceil(matrix_k as usize)
+}
+
+/// Syndrome of the next group of samples of the permutation: the xor of the (1-based) numbers of
+/// the samples whose LSB is 1. Also returns the samples of the group.
+fn syndrome(bytes: &[u8], cover: &impl Cover, positions: &mut Positions, matrix_k: u8) -> (usize, Vec<usize>) {
+    let group: Vec<usize> = positions.by_ref().take(group_len(matrix_k)).collect();
+    let syndrome = group
+        .iter()
+        .enumerate()
+        .filter(|(_, index)| cover.lsb(bytes, **index) == 1)
+        .fold(0, |syndrome, (number, _)| syndrome ^ (number + 1));
+    (syndrome, group)
+}
+
+fn read_bits(bytes: &[u8], cover: &impl Cover, positions: &mut Positions, len: usize, matrix_k: u8) -> Vec<u8> {
+    let mut bits = Vec::with_capacity(len * 8 + matrix_k as usize);
+    for _ in 0..groups_needed(len, matrix_k) {
+        let (syndrome, _) = syndrome(bytes, cover, positions, matrix_k);
+        bits.extend((0..matrix_k).rev().map(|shift| (syndrome >> shift) as u8 & 1));
+    }
+    bits.chunks(8).take(len).map(|byte| byte.iter().fold(0, |byte, bit| (byte << 1) | bit)).collect()
+}
+
+/// Hides `data` in the next samples of the permutation, returning the number of samples changed.
+fn write_bits(output: &mut [u8], cover: &impl Cover, positions: &mut Positions, data: &[u8], matrix_k: u8, matching: bool)
+    -> usize {
+    let mut bits: Vec<u8> = data.iter().flat_map(|byte| (0..8).rev().map(move |shift| (byte >> shift) & 1)).collect();
+    bits.resize(groups_needed(data.len(), matrix_k) * matrix_k as usize, 0);
+
+    let mut changes = 0;
+    for message in bits.chunks(matrix_k as usize) {
+        let message = message.iter().fold(0, |message, bit| (message << 1) | *bit as usize);
+        // changing the LSB of sample number d turns the syndrome s into s xor d
+        let (syndrome, group) = syndrome(output, cover, positions, matrix_k);
+        if syndrome != message {
+            cover.change(output, group[(syndrome ^ message) - 1], matching);
+            changes += 1;
+        }
+    }
+    changes
+}
+
+/// Adds or subtracts 1 at random to `value`, an integer of `bits` bits (two's complement if
+/// `signed`), except at the smallest and largest values (which cannot wrap).
+pub(crate) fn step(value: u32, bits: u16, signed: bool) -> u32 {
+    let mask = (1u32 << bits) - 1;
+    let (min, max) = match signed {
+        true => (1 << (bits - 1), (1 << (bits - 1)) - 1),
+        false => (0, mask),
+    };
+    match value {
+        _ if value == max => value - 1,
+        _ if value == min => value + 1,
+        _ if rand::random() => value.wrapping_add(1) & mask,
+        _ => value.wrapping_sub(1) & mask,
+    }
+}
+
+fn invalid(cover: &impl Cover, reason: &'static str) -> Error {
+    Error::InvalidContainer { format: cover.format(), reason }
+}
+
+/// Sample indices in the order chosen by a stego key, shuffled one step at a time.
+struct Positions {
+    order: Vec<u32>,
+    next: usize,
+    rng: ChaCha20Rng,
+}
+
+impl Positions {
+    fn new(count: usize, stego_key: &str) -> Positions {
+        let mut seed = [0; 32];
+        // expanding 32 bytes from hkdf-sha256 cannot fail (limit is 255 * 32 bytes)
+        let _ = Hkdf::<Sha256>::new(None, stego_key.as_bytes()).expand(POSITIONS_INFO, &mut seed);
+        Positions {
+            order: (0..count as u32).collect(),
+            next: 0,
+            rng: ChaCha20Rng::from_seed(seed),
+        }
+    }
+
+    /// Uniformly random number below `bound` (rejection sampling, so that the permutation only
+    /// depends on the chacha20 stream).
+    fn below(&mut self, bound: u64) -> u64 {
+        let zone = u64::MAX - u64::MAX % bound;
+        loop {
+            let value = self.rng.next_u64();
+            if value < zone {
+                return value % bound;
+            }
+        }
+    }
+}
+
+impl Iterator for Positions {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let remaining = self.order.len() - self.next;
+        if remaining == 0 {
+            return None;
+        }
+        let chosen = self.next + self.below(remaining as u64) as usize;
+        self.order.swap(self.next, chosen);
+        self.next += 1;
+        Some(self.order[self.next - 1] as usize)
+    }
+}
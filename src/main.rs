@@ -1,12 +1,9 @@
-use std::env;
-use std::io::Read;
 use std::path::Path;
 use clap::{command, Arg, ArgAction};
-use id3::{Tag, Version, Error, ErrorKind, TagLike};
-use id3::frame::EncapsulatedObject;
-use infer;
-use rand::{thread_rng, Rng};
-use rand::distributions::Alphanumeric;
+use id3stego::{Carrier, Compression, Detection, Detector, Embedder, EncryptionMethod, Error, Extracted, Extractor, Format, Identity,
+    Listed, Recipient, Stripper, TagVersion, TextEncoding, DEFAULT_MAX_DECOMPRESSED_SIZE, MAX_MATRIX_K};
+use id3stego::paths::{add_filename_prefix_preserve_path, expand_name_template, get_filename_drop_path, resolve_conflict,
+    split_stem_ext, ConflictPolicy};
 
 // See https://stackoverflow.com/questions/63302814/is-there-a-way-to-disable-enable-the-println-macro
 // See also https://veykril.github.io/tlborm/decl-macros/patterns/tt-muncher.html re tt munching
@@ -24,7 +21,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             Arg::new("mode")
                 .short('m')
                 .long("mode")
-                .help("\'put\' (or \'insert\') OR \'get\' (or \'extract\')")
+                .help("\'put\' (or \'insert\') OR \'get\' (or \'extract\') OR \'list\' OR \'strip\' OR \'detect\' OR \'keygen\'")
                 .required(false)
                 .action(ArgAction::Set)
         )
@@ -32,7 +29,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             Arg::new("audio_file")
                 .short('a')
                 .long("audiofile")
-                .help("Path to audio file of type mp3, wav, or aiff (will not be modified)")
+                .help("Path to audio file of type mp3, wav, aiff, flac, ogg, or m4a (will not be modified, unless --in-place)")
                 .required(false)
                 .action(ArgAction::Set)
             )
@@ -40,8 +37,199 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             Arg::new("other_file")
                 .short('o')
                 .long("otherfile")
-                .help("Path to other file (any type, size < 16mb) to embed in audio file (will not be modified)")
+                .help("Path to other file (any type, size < 256mb) to embed in audio file (will not be modified); repeat or use a glob (ex: -o \"*.txt\") to embed several files at once")
                 .required(false)
+                .action(ArgAction::Append)
+        )
+        .arg(
+            Arg::new("dir")
+                .long("dir")
+                .help("Put mode: path to directory to embed as one archive, keeping relative paths, permissions, and mtimes (repeatable); get mode unpacks it into an \'extracted-\' directory")
+                .required(false)
+                .action(ArgAction::Append)
+        )
+        .arg(
+            Arg::new("key")
+                .short('k')
+                .long("key")
+                .help("Put mode: frame description key for other file (default id3stego + 10 random chars); get and list modes: only files whose key matches exactly or by glob (ex: -k \"backup-*\")")
+                .required(false)
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("carrier")
+                .short('c')
+                .long("carrier")
+                .help("Put mode: id3v2 frame type (geob, priv, txxx, or comm; default geob), flac metadata block type (application or picture; default application), ogg comment type (comment or picture; default comment), m4a atom type (freeform or uuid; default freeform), or instead of an id3v2 frame a wav chunk type (chunk or info) or aiff chunk type (appl or anno) used to store other file; get mode detects all types")
+                .required(false)
+                .value_parser(["geob", "priv", "txxx", "comm", "application", "comment", "picture", "freeform", "uuid", "chunk", "info", "appl", "anno"])
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("method")
+                .long("method")
+                .help("Put mode: hide other file in metadata (id3v2 frame or native chunk, block, comment, or atom chosen with -c; default) or in the least significant bits of wav or aiff pcm samples (lsb) or of mp3 frame global gains (gain) (both require --stego-key), or in the unused ancillary bytes of mp3 frames (ancillary)")
+                .required(false)
+                .value_parser(["metadata", "lsb", "gain", "ancillary"])
+                .default_value("metadata")
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("stego_key")
+                .long("stego-key")
+                .help("Put mode: secret key choosing the audio samples or global gains that hide other file (--method lsb or gain); get and list modes: also search audio samples and global gains with this key")
+                .required(false)
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("lsb_matching")
+                .long("lsb-matching")
+                .help("Put mode (--method lsb or gain): change samples or global gains by adding or subtracting 1 at random (±1 lsb matching) instead of overwriting their least significant bit (harder to detect)")
+                .required(false)
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("matrix")
+                .long("matrix")
+                .help("Put mode (--method lsb or gain): matrix embedding with a (1, 2^k-1, k) hamming code, hiding k bits in every 2^k-1 samples or global gains with at most one change (k from 1 to 12; default 1, one bit per sample)")
+                .required(false)
+                .value_parser(clap::value_parser!(u8).range(1..=MAX_MATRIX_K as i64))
+                .default_value("1")
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("text_encoding")
+                .long("text-encoding")
+                .help("Put mode: encoding of other file stored in txxx or comm frames, ogg comments, or wav info and aiff anno chunks (base64 or base85)")
+                .required(false)
+                .value_parser(["base64", "base85"])
+                .default_value("base64")
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("compress")
+                .short('z')
+                .long("compress")
+                .help("Put mode: compress other file before embedding (and before encrypting) it (zstd or deflate)")
+                .required(false)
+                .value_parser(["zstd", "deflate"])
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("max_decompressed_size")
+                .long("max-decompressed-size")
+                .help("Get mode: refuse to decompress embedded files larger than this many mb (from 1 to 1000; default 64), guarding against decompression bombs")
+                .required(false)
+                .value_parser(clap::value_parser!(u16).range(1..=1000))
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("encrypt")
+                .short('e')
+                .long("encrypt")
+                .help("Put mode: encrypt other file with a passphrase (prompted for)")
+                .required(false)
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("passphrase_file")
+                .long("passphrase-file")
+                .help("Path to file containing passphrase (put mode: encrypt other file; get mode: decrypt embedded files)")
+                .required(false)
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("recipient")
+                .short('r')
+                .long("recipient")
+                .help("Put mode: encrypt other file to a public key, or to every public key in a key file (repeatable)")
+                .required(false)
+                .action(ArgAction::Append)
+        )
+        .arg(
+            Arg::new("identity")
+                .short('i')
+                .long("identity")
+                .help("Get mode: path to private key file used to decrypt embedded files (repeatable)")
+                .required(false)
+                .action(ArgAction::Append)
+        )
+        .arg(
+            Arg::new("key_file")
+                .long("keyfile")
+                .help("Keygen mode: path to new private key file (public key saved to same path + \'.pub\')")
+                .required(false)
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .help("List and detect modes: print embedded files (or findings) as a table or as json")
+                .required(false)
+                .value_parser(["table", "json"])
+                .default_value("table")
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("min_score")
+                .long("min-score")
+                .help("Detect mode: only report findings scoring at least this much (from 1 to 100; default 1, every finding)")
+                .required(false)
+                .value_parser(clap::value_parser!(u8).range(1..=100))
+                .default_value("1")
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("in_place")
+                .long("in-place")
+                .help("Put and strip modes: modify audio file in place instead of saving a new \'output-\' file (keeps its id3v2 tag version)")
+                .required(false)
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("id3_version")
+                .long("id3-version")
+                .help("Put mode: write id3v2 tag as version 2.3 or 2.4 (default: same version as audio file\'s tag, or 2.4 for a new tag)")
+                .required(false)
+                .value_parser(["2.3", "2.4"])
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("keep_empty_tag")
+                .long("keep-empty-tag")
+                .help("Strip mode: keep id3v2 tag even if nothing is left in it (removed by default)")
+                .required(false)
+                .action(ArgAction::SetTrue)
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .help("Put and strip modes: path of output audio file instead of \'output-\' file next to audio file; may use {stem} and {ext} of audio file (ex: --output \"out/{stem}-hidden{ext}\")")
+                .required(false)
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("out_dir")
+                .long("out-dir")
+                .help("Get mode: directory to save extracted files to (created if needed) instead of audio file\'s directory")
+                .required(false)
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("name")
+                .long("name")
+                .help("Get mode: naming template for extracted files, using {stem} and {ext} of embedded filename, {key}, and {index} (1, 2, ...)")
+                .required(false)
+                .default_value("extracted-{stem}{ext}")
+                .action(ArgAction::Set)
+        )
+        .arg(
+            Arg::new("on_conflict")
+                .long("on-conflict")
+                .help("Put, get, and strip modes: if an output file already exists, skip it, overwrite it, or rename new file with a counter (ex: extracted-test-1.jpg)")
+                .required(false)
+                .value_parser(["skip", "overwrite", "rename"])
+                .default_value("rename")
                 .action(ArgAction::Set)
         )
         .arg(
@@ -55,12 +243,127 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let help_msg = "For usage information, type .\\id3stego -h".to_string();
 
+    // on ctrl-c (or termination), remove temp files still being written so no partial output is left behind
+    // output files are only renamed into place once completely written
+    if ctrlc::set_handler(|| {
+        id3stego::remove_temp_files();
+        eprintln!("Error: Interrupted, no partial output file(s) left behind.");
+        std::process::exit(130);
+    }).is_err() {
+        eprintln!("Error: Unable to install interrupt (ctrl-c) handler.");
+    }
+
     // if quiet cl arg flag used (-q), set env variable QUIET (process) checked by println! macro
     // existence checked in println! macro (not value)
     if argument_matches.get_flag("quiet") {
         std::env::set_var("QUIET", "ON");
     }
     
+    // put mode options select the frame type (and text encoding) used to store the other file
+    // without a carrier, the default for the audio file's format is used
+    let carrier = match argument_matches.get_one::<String>("carrier").map(String::as_str) {
+        Some("geob") => Some(Carrier::Geob),
+        Some("priv") => Some(Carrier::Priv),
+        Some("txxx") => Some(Carrier::Txxx),
+        Some("comm") => Some(Carrier::Comm),
+        Some("application") => Some(Carrier::FlacApplication),
+        Some("comment") => Some(Carrier::OggComment),
+        Some("freeform") => Some(Carrier::Mp4Freeform),
+        Some("uuid") => Some(Carrier::Mp4Uuid),
+        Some("chunk") => Some(Carrier::WavChunk),
+        Some("info") => Some(Carrier::WavInfo),
+        Some("appl") => Some(Carrier::AiffAppl),
+        Some("anno") => Some(Carrier::AiffAnno),
+        // flac and ogg both store pictures, each in its own way
+        Some("picture") => match argument_matches.get_one::<String>("audio_file").map(Format::detect_path) {
+            Some(Ok(Format::Ogg)) => Some(Carrier::OggPicture),
+            _ => Some(Carrier::FlacPicture),
+        },
+        _ => None,
+    };
+    // put mode method: metadata carriers, or the audio data itself (any -c is rejected)
+    let method = match argument_matches.get_one::<String>("method").map(String::as_str) {
+        Some("lsb") => Method::Lsb,
+        Some("gain") => Method::Gain,
+        Some("ancillary") => Method::Ancillary,
+        _ => Method::Metadata,
+    };
+    let text_encoding = match argument_matches.get_one::<String>("text_encoding").map(String::as_str) {
+        Some("base85") => TextEncoding::Base85,
+        _ => TextEncoding::Base64,
+    };
+    let embedder = Embedder::new()
+        .text_encoding(text_encoding);
+    let embedder = match (&method, carrier) {
+        (Method::Lsb, _) => embedder.carrier(Carrier::PcmLsb),
+        (Method::Gain, _) => embedder.carrier(Carrier::Mp3GlobalGain),
+        (Method::Ancillary, _) => embedder.carrier(Carrier::Mp3Ancillary),
+        (Method::Metadata, Some(carrier)) => embedder.carrier(carrier),
+        (Method::Metadata, None) => embedder,
+    };
+    let embedder = match argument_matches.get_one::<String>("id3_version").map(String::as_str) {
+        Some("2.3") => embedder.tag_version(TagVersion::Id3v23),
+        Some("2.4") => embedder.tag_version(TagVersion::Id3v24),
+        _ => embedder,
+    };
+    let embedder = embedder
+        .lsb_matching(argument_matches.get_flag("lsb_matching"))
+        .matrix_embedding(argument_matches.get_one::<u8>("matrix").copied().unwrap_or(1));
+    let embedder = match argument_matches.get_one::<String>("compress").map(String::as_str) {
+        Some("zstd") => embedder.compression(Compression::Zstd),
+        Some("deflate") => embedder.compression(Compression::Deflate),
+        _ => embedder,
+    };
+
+    // frame description key of other file (put mode), or key pattern of embedded files (get and list modes)
+    let (embedder, extractor) = match argument_matches.get_one::<String>("key") {
+        Some(key) => (embedder.key(key), Extractor::new().key(key)),
+        None => (embedder, Extractor::new()),
+    };
+
+    // stego key choosing the audio samples that hide other file (put mode), or searched (get and list modes)
+    let stego_key = argument_matches.get_one::<String>("stego_key");
+    let (embedder, extractor) = match stego_key {
+        Some(stego_key) => (embedder.stego_key(stego_key), extractor.stego_key(stego_key)),
+        None => (embedder, extractor),
+    };
+
+    // most bytes decompressed per embedded file (get mode)
+    let extractor = match argument_matches.get_one::<u16>("max_decompressed_size") {
+        Some(max_mb) => extractor.max_decompressed_size(*max_mb as usize * 1000000),
+        None => extractor,
+    };
+
+    // passphrase used to encrypt (put mode) or decrypt (get mode) embedded files
+    let passphrase_source = match argument_matches.get_one::<String>("passphrase_file") {
+        Some(passphrase_filename) => PassphraseSource::File(passphrase_filename.to_string()),
+        None if argument_matches.get_flag("encrypt") => PassphraseSource::Prompt,
+        None => PassphraseSource::None,
+    };
+
+    // public keys (put mode) and private key files (get mode) for recipient encryption
+    let recipient_args: Vec<String> = match argument_matches.get_many::<String>("recipient") {
+        Some(recipients) => recipients.cloned().collect(),
+        None => Vec::new(),
+    };
+    let identity_filenames: Vec<String> = match argument_matches.get_many::<String>("identity") {
+        Some(identities) => identities.cloned().collect(),
+        None => Vec::new(),
+    };
+
+    // output file names (put, get, and strip modes) and what to do if an output file already exists
+    let naming = OutputNaming {
+        in_place: argument_matches.get_flag("in_place"),
+        output: argument_matches.get_one::<String>("output").cloned(),
+        out_dir: argument_matches.get_one::<String>("out_dir").cloned(),
+        name_template: argument_matches.get_one::<String>("name").cloned().unwrap_or_default(),
+        on_conflict: match argument_matches.get_one::<String>("on_conflict").map(String::as_str) {
+            Some("skip") => ConflictPolicy::Skip,
+            Some("overwrite") => ConflictPolicy::Overwrite,
+            _ => ConflictPolicy::Rename,
+        },
+    };
+
     let mode = argument_matches.get_one::<String>("mode");
     match mode {
         None => {
@@ -69,7 +372,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
         Some(mode) => {
             let audio_filename = argument_matches.get_one::<String>("audio_file");
-            let other_filename = argument_matches.get_one::<String>("other_file");
+            let other_args: Vec<String> = match argument_matches.get_many::<String>("other_file") {
+                Some(other_args) => other_args.cloned().collect(),
+                None => Vec::new(),
+            };
+            let dir_args: Vec<String> = match argument_matches.get_many::<String>("dir") {
+                Some(dir_args) => dir_args.cloned().collect(),
+                None => Vec::new(),
+            };
             if mode.to_lowercase() == "put" || mode.to_lowercase() == "insert" {
                 match audio_filename {
                     None => {
@@ -79,25 +389,50 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     Some(audio_filename) => {
                         if Path::exists(Path::new(audio_filename)) {
                             println!("Checkpoint (Put Mode): Audio file {} exists.", &audio_filename);
-                            match other_filename {
-                                None => {
-                                    eprintln!("Error (Put Mode): No other file (-o filename) specified.");
-                                    eprintln!("{}", &help_msg);
-                                }                                
-                                Some(other_filename) => {
-                                    if Path::exists(Path::new(other_filename)) {
-                                        println!("Checkpoint (Put Mode): Other file {} exists.", &other_filename);
-                                        match put(audio_filename.to_string(), other_filename.to_string()) {
-                                            Ok(output_filename) => {
+                            if other_args.is_empty() && dir_args.is_empty() {
+                                eprintln!("Error (Put Mode): No other file (-o filename) or directory (--dir path) specified.");
+                                eprintln!("{}", &help_msg);
+                            }
+                            else if !matches!(method, Method::Metadata) && carrier.is_some() {
+                                eprintln!("Error (Put Mode): Use either a carrier (-c) or --method {}, not both.", method.name());
+                                eprintln!("{}", &help_msg);
+                            }
+                            else if matches!(method, Method::Lsb | Method::Gain) && stego_key.is_none() {
+                                eprintln!("Error (Put Mode): No stego key (--stego-key key) specified for --method {}.", method.name());
+                                eprintln!("{}", &help_msg);
+                            }
+                            else if let Some(dir_arg) = dir_args.iter().find(|dir_arg| !Path::is_dir(Path::new(dir_arg))) {
+                                eprintln!("Error (Put Mode): Directory (--dir path) not found at {}.", &dir_arg);
+                                eprintln!("{}", &help_msg);
+                            }
+                            else {
+                                match expand_other_filenames(&other_args) {
+                                    Ok(other_filenames) => {
+                                        for other_filename in &other_filenames {
+                                            println!("Checkpoint (Put Mode): Other file {} exists.", &other_filename);
+                                        }
+                                        for dir_arg in &dir_args {
+                                            println!("Checkpoint (Put Mode): Directory {} exists.", &dir_arg);
+                                        }
+                                        // directories are embedded after other files, each packed into one archive
+                                        let mut other_filenames = other_filenames;
+                                        other_filenames.extend(dir_args.iter().cloned());
+                                        match put(audio_filename, &other_filenames, &embedder, &method, &passphrase_source, &recipient_args, &naming) {
+                                            Ok((output_filename, keys)) => {
                                                 println!("Checkpoint (Put Mode): Success! {} is {} + {}. All done!", 
-                                                    output_filename, &audio_filename, &other_filename);
+                                                    output_filename, &audio_filename, other_filenames.join(" + "));
+                                                println!("Checkpoint (Put Mode): Created the following {} frame description key(s):", 
+                                                    keys.len().to_string());
+                                                for (other_filename, key) in other_filenames.iter().zip(keys) {
+                                                    println!("\t- {} ({})", key, other_filename);
+                                                }
                                             }
                                             Err(_) => {
                                                 eprintln!("{}", &help_msg);
                                             }
                                         }
                                     }
-                                    else {
+                                    Err(other_filename) => {
                                         eprintln!("Error (Put Mode): Other file (-o filename) not found at {}.", &other_filename);
                                         eprintln!("{}", &help_msg);
                                     }
@@ -120,7 +455,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     Some(audio_filename) => {
                         if Path::exists(Path::new(audio_filename)) {
                             println!("Checkpoint (Get Mode): Audio file exists at {}.", &audio_filename);                     
-                            match get(audio_filename.to_string()) {
+                            match get(audio_filename, &extractor, &passphrase_source, &identity_filenames, &naming) {
                                 Ok(extracted_filenames_ok) => {
                                     match extracted_filenames_ok {
                                         Some(extracted_filenames) => {
@@ -149,6 +484,100 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     }
                 }
             }
+            else if mode.to_lowercase() == "list" {
+                match audio_filename {
+                    None => {
+                        eprintln!("Error (List Mode): No audio file (-a filename) specified.");
+                        eprintln!("{}", &help_msg);
+                    }
+                    Some(audio_filename) => {
+                        if Path::exists(Path::new(audio_filename)) {
+                            let json = argument_matches.get_one::<String>("format").is_some_and(|format| format == "json");
+                            if list(audio_filename, &extractor, json).is_err() {
+                                eprintln!("{}", &help_msg);
+                            }
+                        }
+                        else {
+                            eprintln!("Error (List Mode): Audio file (-a filename) not found at {}.", &audio_filename);
+                            eprintln!("{}", &help_msg);
+                        }
+                    }
+                }
+            }
+            else if mode.to_lowercase() == "strip" {
+                match audio_filename {
+                    None => {
+                        eprintln!("Error (Strip Mode): No audio file (-a filename) specified.");
+                        eprintln!("{}", &help_msg);
+                    }
+                    Some(audio_filename) => {
+                        if Path::exists(Path::new(audio_filename)) {
+                            println!("Checkpoint (Strip Mode): Audio file {} exists.", &audio_filename);
+                            let stripper = Stripper::new()
+                                .remove_empty_tag(!argument_matches.get_flag("keep_empty_tag"));
+                            match strip(audio_filename, &stripper, &naming) {
+                                Ok(Some(output_filename)) => {
+                                    println!("Checkpoint (Strip Mode): Success! {} is {} without embedded files. All done!", 
+                                        output_filename, &audio_filename);
+                                }
+                                Ok(None) => {
+                                    println!("Checkpoint (Strip Mode): No id3stego embedded file(s) found in {}, nothing written.", 
+                                        &audio_filename);
+                                }
+                                Err(_) => {
+                                    eprintln!("{}", &help_msg);
+                                }
+                            }
+                        }
+                        else {
+                            eprintln!("Error (Strip Mode): Audio file (-a filename) not found at {}.", &audio_filename);
+                            eprintln!("{}", &help_msg);
+                        }
+                    }
+                }
+            }
+            else if mode.to_lowercase() == "detect" {
+                match audio_filename {
+                    None => {
+                        eprintln!("Error (Detect Mode): No audio file (-a filename) specified.");
+                        eprintln!("{}", &help_msg);
+                    }
+                    Some(audio_filename) => {
+                        if Path::exists(Path::new(audio_filename)) {
+                            let detector = Detector::new()
+                                .min_score(argument_matches.get_one::<u8>("min_score").copied().unwrap_or(1));
+                            let json = argument_matches.get_one::<String>("format").is_some_and(|format| format == "json");
+                            if detect(audio_filename, &detector, json).is_err() {
+                                eprintln!("{}", &help_msg);
+                            }
+                        }
+                        else {
+                            eprintln!("Error (Detect Mode): Audio file (-a filename) not found at {}.", &audio_filename);
+                            eprintln!("{}", &help_msg);
+                        }
+                    }
+                }
+            }
+            else if mode.to_lowercase() == "keygen" {
+                match argument_matches.get_one::<String>("key_file") {
+                    None => {
+                        eprintln!("Error (Keygen Mode): No key file (--keyfile filename) specified.");
+                        eprintln!("{}", &help_msg);
+                    }
+                    Some(key_filename) => {
+                        match keygen(key_filename) {
+                            Ok(recipient) => {
+                                println!("Checkpoint (Keygen Mode): Public key is {}", recipient);
+                                println!("Checkpoint (Keygen Mode): Success! Share {}.pub, keep {} private. All done!", 
+                                    &key_filename, &key_filename);
+                            }
+                            Err(_) => {
+                                eprintln!("{}", &help_msg);
+                            }
+                        }
+                    }
+                }
+            }
             else {
                 eprintln!("Error: Invalid mode type (-m mode) specified.");
                 eprintln!("{}", &help_msg);
@@ -165,386 +594,815 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
 }
 
-fn put(audio_filename: String, other_filename: String) -> Result<String, Box<dyn std::error::Error>> {
-    // success: return output_filename as string
-    // failure: prints error message, returns err
-    // to do:  test new output filename code, add to get also for -extracted
+// where output files are saved (put, get, and strip modes)
+struct OutputNaming {
+    // put and strip modes: write to audio file itself
+    in_place: bool,
+    // put and strip modes: output audio file path template ({stem}, {ext}), or none for 'output-' prefix
+    output: Option<String>,
+    // get mode: directory for extracted files, or none for audio file's directory
+    out_dir: Option<String>,
+    // get mode: extracted file name template ({stem}, {ext}, {key}, {index})
+    name_template: String,
+    on_conflict: ConflictPolicy,
+}
 
-    let mut output_file_prefix = "output-".to_string();
-    let output_filename = add_filename_prefix_preserve_path(&audio_filename, &audio_filename, &mut output_file_prefix);
+// where other file is hidden (put mode)
+enum Method {
+    // id3v2 frames, or native metadata of the audio file's format (-c)
+    Metadata,
+    // least significant bits of wav or aiff pcm samples, at positions chosen by the stego key
+    Lsb,
+    // least significant bits of the global gains of mp3 frames, at positions chosen by the stego key
+    Gain,
+    // unused ancillary bytes after the main data of mp3 frames
+    Ancillary,
+}
 
-    match is_supported_filetype(&audio_filename) {
-        Ok(supported_ok) => {
-            match supported_ok {
-                Some(supported_filetype) => {
-                    println!("Checkpoint (Put Mode): Mime-type of {} is \'{}\'.", 
-                        &audio_filename, supported_filetype);
-                }
-                None => {
-                    eprintln!("Error (Put Mode): Mime-type of {} must be mp3, wav, or aiff.", 
-                        &audio_filename);
-                    return Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, 
-                        "Unsupported audio file (-a audio_file) type.")));
-                }
-            }
+impl Method {
+    fn name(&self) -> &'static str {
+        match self {
+            Method::Metadata => "metadata",
+            Method::Lsb => "lsb",
+            Method::Gain => "gain",
+            Method::Ancillary => "ancillary",
         }
-        Err(err) => {
-            eprintln!("Error (Put Mode): Unable to determine mime-type of {} (mp3, wav, or aiff required).", 
-                &audio_filename);
-            return Err(err)
+    }
+
+    // what one hidden bit changes, in messages
+    fn positions(&self) -> &'static str {
+        match self {
+            Method::Gain => "global gains",
+            Method::Ancillary => "free ancillary bytes",
+            _ => "samples",
         }
     }
+}
 
-    // open other_filename
-    let mut other_file = match std::fs::File::open(&other_filename) {
-        Ok(other_file) => {
-            println!("Checkpoint (Put Mode): Opening {}.", &other_filename);
-            other_file
+// where the passphrase for encrypting (put mode) or decrypting (get mode) comes from
+enum PassphraseSource {
+    None,
+    Prompt,
+    File(String),
+}
+
+fn put(audio_filename: &str, other_filenames: &[String], embedder: &Embedder, method: &Method,
+    passphrase_source: &PassphraseSource, recipient_args: &[String], naming: &OutputNaming) -> Result<(String, Vec<String>), Box<dyn std::error::Error>> {
+    // success: return output_filename as string, and frame description key of each other file
+    // failure: prints error message, returns err
+    // all other files are added to the same id3v2 tag, written once
+
+    let output_filename = match output_filename(audio_filename, naming) {
+        Ok(Some(output_filename)) => output_filename,
+        Ok(None) => {
+            eprintln!("Error (Put Mode): Output file already exists, skipped (--on-conflict skip).");
+            return Err("output file already exists".into())
         }
         Err(err) => {
-            eprintln!("Error (Put Mode): Unable to open {}.", &other_filename);
-            return Err(Box::new(err))
+            eprintln!("Error (Put Mode): {}.", err);
+            return Err(err.into())
         }
     };
 
-    // read bytes from other_filename into buffer
-    // confirm other_file size < 16mb (maximum id3v2 frame size)
-    let mut other_file_buffer = Vec::new();
-    let max_frame_size = 16 * 1000000; // 10^6 used instead of 2^20; 1,000,000 vs 1,048,576.
-    match other_file.read_to_end(&mut other_file_buffer) {
-        Ok(bytes_read) => {
-            if bytes_read <= max_frame_size {
-                println!("Checkpoint (Put Mode): Reading {} bytes from {} into buffer.", 
-                    bytes_read.to_string(), &other_filename);
-            }
-            else {
-                eprintln!("Error (Put Mode): Other file {} exceeds 16mb (id3v2 max frame size).",
-                    &other_filename);
-                return Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, 
-                    "Max id3v2 frame size (16mb) exceeded.")));
-            }
+    match Format::detect_path(audio_filename) {
+        Ok(format) => {
+            println!("Checkpoint (Put Mode): Mime-type of {} is \'{}\'.", 
+                &audio_filename, format.mime_type());
         }
         Err(err) => {
-            eprintln!("Error (Put Mode): Unable to read bytes from {} into buffer.", &other_filename);
+            eprintln!("Error (Put Mode): Mime-type of {} must be mp3, wav, aiff, flac, ogg, or m4a.", 
+                &audio_filename);
             return Err(Box::new(err))
         }
-    };
+    }
 
-    // if possible, infer mimetype of other_file from buffer
-    let other_file_mimetype = match infer::get(&other_file_buffer) {
-        Some(kind) => {
-            println!("Checkpoint (Put Mode): Inferring mime-type of \'{}\' from buffer as \'{}\'.", 
-                &other_filename, kind.mime_type());
-            kind.mime_type().to_owned()
-        }
-        None => {
-            println!("Checkpoint (Put Mode): Unable to infer mime-type of {} from buffer.", 
-                &other_filename);
-            println!("Checkpoint (Put Mode): Using mime-type of \'application/octet-stream\' for {}.",
-                &other_filename);
-            "application/octet-stream".to_string()
+    // report up front how much the audio data (samples, global gains, or ancillary bytes) can hide
+    if !matches!(method, Method::Metadata) {
+        match embedder.capacity_path(audio_filename) {
+            Ok(capacity) => {
+                println!("Checkpoint (Put Mode): Audio data of {} can hide {} bytes ({} {}).", 
+                    &audio_filename, capacity.bytes.to_string(), capacity.positions.to_string(), method.positions());
+            }
+            Err(err) => {
+                eprintln!("Error (Put Mode): {}.", err);
+                return Err(Box::new(err))
+            }
         }
+    }
+
+    let embedder = match passphrase_source {
+        PassphraseSource::None => embedder.clone(),
+        PassphraseSource::Prompt => match prompt_passphrase(true) {
+            Ok(passphrase) => embedder.clone().passphrase(passphrase),
+            Err(err) => {
+                eprintln!("Error (Put Mode): {}.", err);
+                return Err(err)
+            }
+        },
+        PassphraseSource::File(passphrase_filename) => match read_passphrase_file(passphrase_filename) {
+            Ok(passphrase) => embedder.clone().passphrase(passphrase),
+            Err(err) => {
+                eprintln!("Error (Put Mode): Unable to read passphrase from {}.", &passphrase_filename);
+                return Err(err)
+            }
+        },
     };
 
-    // copy audio_filename to output_filename
-    // todo, multiple other files: move above previous block; add error cleanup to err of previous block
-    // todo, multiple other files: change return value to vector of strings with output filenames
-    match std::fs::copy(&audio_filename, &output_filename) {
-        Ok(bytes_copied) => {
-            println!("Checkpoint (Put Mode): Copying {} to {} ({} bytes).", 
-                    &audio_filename, &output_filename, bytes_copied.to_string());
+    if !recipient_args.is_empty() && !matches!(passphrase_source, PassphraseSource::None) {
+        eprintln!("Error (Put Mode): Encrypt with a passphrase or to recipients (-r), not both.");
+        return Err("passphrase and recipients both specified".into())
+    }
+    let mut embedder = embedder;
+    let mut recipient_count = 0;
+    for recipient_arg in recipient_args {
+        match read_recipients(recipient_arg) {
+            Ok(recipients) => {
+                recipient_count += recipients.len();
+                embedder = embedder.recipients(recipients);
+            }
+            Err(err) => {
+                eprintln!("Error (Put Mode): Unable to read public key(s) from {}: {}.", &recipient_arg, err);
+                return Err(Box::new(err))
+            }
         }
+    }
+
+    for other_filename in other_filenames {
+        match method {
+            Method::Metadata => {
+                println!("Checkpoint (Put Mode): Injecting data from {} into new id3v2 frame (or native metadata chunk, block, comment, or atom).", &other_filename);
+            }
+            Method::Lsb => {
+                println!("Checkpoint (Put Mode): Hiding data from {} in the least significant bits of audio samples.", &other_filename);
+            }
+            Method::Gain => {
+                println!("Checkpoint (Put Mode): Hiding data from {} in the least significant bits of mp3 frame global gains.", &other_filename);
+            }
+            Method::Ancillary => {
+                println!("Checkpoint (Put Mode): Hiding data from {} in the ancillary data of mp3 frames.", &other_filename);
+            }
+        }
+    }
+    let embedded_files = match embedder.embed_paths(audio_filename, other_filenames, &output_filename) {
+        Ok(embedded_files) => embedded_files,
         Err(err) => {
-            eprintln!("Error (Put Mode): Unable to copy {}.", &audio_filename);
+            eprintln!("Error (Put Mode): {}.", err);
             return Err(Box::new(err))
         }
-    }
-    
-    // search for id3 tag in output_filename, create if none found
-    let mut tag = match Tag::read_from_path(&output_filename) {
-        Ok(tag) => {
-            println!("Checkpoint (Put Mode): Extracting existing id3v2 tag from {}.", &output_filename);
-            tag
+    };
+    let tag_version = match embedded_files.first().map(|embedded| embedded.tag_version) {
+        Some(Some(tag_version)) => format!("{} tag", tag_version),
+        Some(None) if matches!(method, Method::Lsb) => "sample LSBs".to_string(),
+        Some(None) if matches!(method, Method::Gain) => "global gain LSBs".to_string(),
+        Some(None) if matches!(method, Method::Ancillary) => "ancillary data".to_string(),
+        Some(None) => "metadata".to_string(),
+        None => "id3v2 tag".to_string(),
+    };
+    let embedding = embedded_files.first().and_then(|embedded| embedded.embedding);
+    let mut keys = Vec::new();
+    for (other_filename, embedded) in other_filenames.iter().zip(embedded_files) {
+        if embedded.archive {
+            println!("Checkpoint (Put Mode): Packed directory {} into a tar archive.", &other_filename);
         }
-        Err(Error{kind: ErrorKind::NoTag, ..}) => {
-            println!("Checkpoint (Put Mode): No id3v2 tag in {}.", &output_filename);
-            println!("Checkpoint (Put Mode): Creating new id3v2 tag for {}.", &output_filename);            
-            Tag::new()
+        println!("Checkpoint (Put Mode): Embedded {} bytes from {} (\'{}\').", 
+            embedded.size.to_string(), &other_filename, &embedded.mime_type);
+        println!("Checkpoint (Put Mode): SHA-256 digest of {} is {}.", &other_filename, to_hex(&embedded.sha256));
+        if let Some(compression) = embedded.compression {
+            println!("Checkpoint (Put Mode): Compressed {} with {}.", &other_filename, compression);
+            if embedded.size > DEFAULT_MAX_DECOMPRESSED_SIZE {
+                println!("Checkpoint (Put Mode): {} is larger than 64mb, get mode needs \'--max-decompressed-size {}\' to extract it.",
+                    &other_filename, embedded.size.div_ceil(1000000).to_string());
+            }
         }
-        Err(err) => {
-            eprintln!("Error (Put Mode): Unable to find or create id3v2 tag in {}.", &output_filename);
-            error_cleanup(&output_filename);
-            return Err(Box::new(err))
+        match embedded.encryption {
+            Some(EncryptionMethod::Passphrase) => {
+                println!("Checkpoint (Put Mode): Encrypted {} with passphrase.", &other_filename);
+            }
+            Some(EncryptionMethod::Recipients) => {
+                println!("Checkpoint (Put Mode): Encrypted {} to {} recipient(s).", 
+                    &other_filename, recipient_count.to_string());
+            }
+            None => {}
         }
-    };  
-
-    // prepare new frame data
-    // do not embed full file path for other file (only filename)
-    let frame_filename = get_filename_drop_path(Path::new(&other_filename), &"".to_string());
-    let frame_mime_type = other_file_mimetype;
-    let frame_data: Vec<u8> = other_file_buffer;
-    let mut frame_description_key = "id3stego".to_string();
-
-    // set frame description key to 'id3stego' + random 10 character string 
-    // prevent collisions if tag already contains another file previously embedded by id3stego (multi file embedding)
-    let rand_string: String = thread_rng()
-        .sample_iter(&Alphanumeric)
-        .take(10)
-        .map(char::from)
-        .collect();
-    frame_description_key.push_str(&rand_string);
-
-    // embed buffered data read from other_file into new frame in id3 tag
-    println!("Checkpoint (Put Mode): Injecting buffer (data from {}) into new id3v2 frame.", &other_filename);
-    println!("Checkpoint (Put Mode): Using frame description key \'{}\'.", &frame_description_key);
-    //let frame = match tag.add_frame(
-    match tag.add_frame(
-        EncapsulatedObject {
-            mime_type: frame_mime_type,
-            filename: frame_filename,
-            description: frame_description_key,
-            data: frame_data,
-        }) {
-            Some(_) => {
-                println!("Checkpoint (Put Mode): Existing id3v2 frame found with same frame description key (collision)!");
-                println!("Checkpoint (Put Mode): Overwriting existing id3v2 frame with same frame description key.");                
-                //frame
+        if embedded.frames > 1 {
+            println!("Checkpoint (Put Mode): Split {} into {} chunks (id3v2 max frame size is 16mb).", 
+                &other_filename, embedded.frames.to_string());
+        }
+        println!("Checkpoint (Put Mode): Using {} frame(s) with frame description key \'{}\'.", 
+            embedded.carrier.frame_id(), &embedded.key);
+        keys.push(embedded.key);
+    }
+    // all files are hidden in the samples together, so efficiency is reported once per put
+    if let Some(embedding) = embedding {
+        match embedding.bits_per_change() {
+            Some(bits_per_change) => {
+                println!("Checkpoint (Put Mode): Embedding efficiency is {:.2} bits per change ({} bits hidden, {} {} changed).", 
+                    bits_per_change, embedding.bits.to_string(), embedding.changes.to_string(), method.positions());
             }
             None => {
-                println!("Checkpoint (Put Mode): Adding new frame to id3v2 tag.");
+                println!("Checkpoint (Put Mode): Embedding efficiency is unbounded ({} bits hidden, no {} changed).", 
+                    embedding.bits.to_string(), method.positions());
             }
-        };
-
-    // write tag back to output_file
-    match tag.write_to_path(&output_filename, Version::Id3v24) {
-        Ok(_) => {
-            println!("Checkpoint (Put Mode): Writing id3v2 tag with new frame to {}.", &output_filename);
-        }
-        Err(err) => {
-            eprintln!("Error (Put Mode): Unable to write finalized id3v2 tag to {}.", &output_filename);
-            error_cleanup(&output_filename);
-            return Err(Box::new(err))
         }
     }
+    println!("Checkpoint (Put Mode): Wrote {} with {} new file(s) to {}.", tag_version, keys.len().to_string(), &output_filename);
 
-    Ok(output_filename)
+    Ok((output_filename, keys))
 
 } 
  
-fn get(audio_filename: String) -> Result<Option<Vec<(String, String)>>, Box<dyn std::error::Error>> {
+// (filename stored in frame, path of extracted file)
+type ExtractedFilenames = Vec<(String, String)>;
+
+fn get(audio_filename: &str, extractor: &Extractor, passphrase_source: &PassphraseSource, identity_filenames: &[String],
+    naming: &OutputNaming) -> Result<Option<ExtractedFilenames>, Box<dyn std::error::Error>> {
     // success: return vector of extracted filenames or none
     // failure: prints error message, returns err
 
-    let mut extracted_filenames: Vec<(String, String)> = Vec::new();
-    let mut extracted_file_prefix = "extracted-".to_string();
+    let mut extracted_filenames: ExtractedFilenames = Vec::new();
+
+    // check naming template before searching (placeholder values do not matter here)
+    if let Err(placeholder) = expand_name_template(&naming.name_template,
+        &[("stem", ""), ("ext", ""), ("key", ""), ("index", "")]) {
+        eprintln!("Error (Get Mode): Unknown placeholder \'{}\' in naming template (--name), use {{stem}}, {{ext}}, {{key}}, or {{index}}.", 
+            placeholder);
+        return Err("invalid naming template".into())
+    }
 
     // check file-type of audio_filename
-    match is_supported_filetype(&audio_filename) {
-        Ok(supported_ok) => {
-            match supported_ok {
-                Some(supported_filetype) => {
-                        println!("Checkpoint (Get Mode): Mime-type of {} is \'{}\'.", 
-                            &audio_filename, supported_filetype); 
-                }
-                None => {
-                    eprintln!("Error (Get Mode): Mime-type of {} must be mp3, wav, or aiff.", 
-                        &audio_filename);
-                    return Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, 
-                        "Unsupported audio file (-a audio_file) type.")));
-                }
-            }
+    match Format::detect_path(audio_filename) {
+        Ok(format) => {
+            println!("Checkpoint (Get Mode): Mime-type of {} is \'{}\'.", 
+                &audio_filename, format.mime_type());
         }
         Err(err) => {
-            eprintln!("Error (Get Mode): Unable to determine mime-type of {} (mp3, wav, or aiff required).", 
+            eprintln!("Error (Get Mode): Mime-type of {} must be mp3, wav, aiff, flac, ogg, or m4a.", 
                 &audio_filename);
-            return Err(err)
+            return Err(Box::new(err))
         }
     }
-    
-    // search for id3 tag in output_filename, ret if none found
-    let tag = match Tag::read_from_path(&audio_filename) {
-        Ok(tag) => {
-            println!("Checkpoint (Get Mode): Extracting existing id3v2 tag from {}.", &audio_filename);
-            tag
-        }
-        Err(err) => match err.kind { 
-            ErrorKind::NoTag => {
-                println!("Checkpoint (Get Mode): No id3v2 tag in {}. No data found to extract.", &audio_filename);
-                return Err(Box::new(err))
+
+    let extractor = match passphrase_source {
+        PassphraseSource::File(passphrase_filename) => match read_passphrase_file(passphrase_filename) {
+            Ok(passphrase) => extractor.clone().passphrase(passphrase),
+            Err(err) => {
+                eprintln!("Error (Get Mode): Unable to read passphrase from {}.", &passphrase_filename);
+                return Err(err)
             }
-            _ => {
-                eprintln!("Error (Get Mode): Unable to find id3v2 tag in {}. No data found to extract.", &audio_filename);
+        },
+        _ => extractor.clone(),
+    };
+
+    let mut extractor = extractor;
+    for identity_filename in identity_filenames {
+        match Identity::read_from_path(identity_filename) {
+            Ok(identities) => {
+                for identity in identities {
+                    extractor = extractor.identity(identity);
+                }
+            }
+            Err(err) => {
+                eprintln!("Error (Get Mode): Unable to read private key(s) from {}: {}.", &identity_filename, err);
                 return Err(Box::new(err))
             }
         }
-    };  
+    }
+
+    println!("Checkpoint (Get Mode): Searching id3v2 tag and native metadata for frames containing files previously embedded by id3stego.");
+    let mut extracted_result = extractor.extract_path(audio_filename);
+    let passphrase_required = match &extracted_result {
+        Ok(extracted_files) => extracted_files.iter().find_map(|extracted| match extracted {
+            Err(Error::PassphraseRequired { key }) => Some(key.clone()),
+            _ => None,
+        }),
+        Err(_) => None,
+    };
+    if let Some(key) = passphrase_required {
+        // ask for passphrase only once an encrypted file is found, then search again
+        println!("Checkpoint (Get Mode): Embedded file \'{}\' is encrypted.", key);
+        match prompt_passphrase(false) {
+            Ok(passphrase) => {
+                extracted_result = extractor.clone().passphrase(passphrase).extract_path(audio_filename);
+            }
+            Err(err) => {
+                eprintln!("Error (Get Mode): {}.", err);
+                return Err(err)
+            }
+        }
+    }
+    let extracted_files = match extracted_result {
+        Ok(extracted_files) => {
+            println!("Checkpoint (Get Mode): Finished searching id3v2 tag and native metadata.");
+            extracted_files
+        }
+        Err(err) => {
+            eprintln!("Error (Get Mode): Unable to extract embedded file(s) from {}: {}.", &audio_filename, err);
+            return Err(Box::new(err))
+        }
+    };
+
+    // extract to same directory as audio file (or --out-dir)
+    if let Some(out_dir) = &naming.out_dir {
+        if let Err(err) = std::fs::create_dir_all(out_dir) {
+            eprintln!("Error (Get Mode): Unable to create output directory {}: {}.", &out_dir, err);
+            return Err(Box::new(err))
+        }
+    }
+    let mut failed = 0;
+    for (index, extracted) in extracted_files.iter().enumerate() {
+        // files that cannot be extracted are reported, the others are still written
+        let extracted = match extracted {
+            Ok(extracted) => extracted,
+            Err(err) => {
+                eprintln!("Error (Get Mode): Unable to extract embedded file from {}: {}.", &audio_filename, err);
+                failed += 1;
+                continue;
+            }
+        };
+        let extracted_filename_with_prefix = match extracted_filename(audio_filename, extracted, index, naming) {
+            Ok(Some(extracted_filename)) => extracted_filename,
+            Ok(None) => {
+                println!("Checkpoint (Get Mode): Skipping {}, output file already exists (--on-conflict skip).", 
+                    &extracted.filename);
+                continue;
+            }
+            Err(err) => {
+                eprintln!("Error (Get Mode): Unable to extract {}: {}.", &extracted.filename, err);
+                continue;
+            }
+        };
 
-    // iterate all encapsulated object frames contained in discovered id3v2 tag
-    let mut id3stego_frame_count = 0;
-    println!("Checkpoint (Get Mode): Searching id3v2 tag for frames containing files previously embedded by id3stego.");
-    let mut encapsulated_objects = tag.encapsulated_objects();
-    loop {
-        match encapsulated_objects.next() {
-            Some(frame) => {
-                // extract to same directory as audio file if frame placed by id3stego (description is 'id3stego')
-                if frame.description.contains("id3stego") {
-                    let extracted_filename_with_prefix = add_filename_prefix_preserve_path(&audio_filename, &frame.filename, &mut extracted_file_prefix);
-                    extracted_filenames.push( (frame.filename.to_owned(), extracted_filename_with_prefix.to_owned()) );
+        println!("Checkpoint (Get Mode): Found embedded file {} (\'{}\' of size {} bytes) in {} frame(s).",
+            &extracted.filename, &extracted.mime_type, extracted.size().to_string(), extracted.carrier.frame_id());  
+        if let Some(compression) = extracted.compression {
+            println!("Checkpoint (Get Mode): Decompressed {} ({}).", &extracted.filename, compression);
+        }
+        match extracted.encryption {
+            Some(EncryptionMethod::Passphrase) => {
+                println!("Checkpoint (Get Mode): Decrypted {} with passphrase.", &extracted.filename);
+            }
+            Some(EncryptionMethod::Recipients) => {
+                println!("Checkpoint (Get Mode): Decrypted {} with private key.", &extracted.filename);
+            }
+            None => {}
+        }
 
-                    println!("Checkpoint (Get Mode): Found embedded file {} (\'{}\' of size {} bytes).",
-                        &frame.filename, &frame.mime_type, &frame.data.len().to_string());  
+        match extracted.sha256 {
+            Some(sha256) => {
+                println!("Checkpoint (Get Mode): Verified SHA-256 digest of {} ({}).", &extracted.filename, to_hex(&sha256));
+            }
+            None => {
+                println!("Checkpoint (Get Mode): No digest stored for {} (embedded by id3stego v0.2), not verified.", 
+                    &extracted.filename);
+            }
+        }
 
-                    match std::fs::write(&extracted_filename_with_prefix, &frame.data) {
-                        Ok(_) => {
-                            println!("Checkpoint (Get Mode): Extracting {} to {}.",
-                                &frame.filename, extracted_filename_with_prefix);
-                        }
-                        Err(_) => {
-                            eprintln!("Error (Get Mode): Unable to extract {} from {}",
-                                &frame.filename, &audio_filename);
-                            // do not propagate error, continue iter to next embedded file
-                        }
-                    };
-                    id3stego_frame_count += 1;
+        if extracted.archive {
+            // unpack embedded directory into a new directory (replacing an existing one with --on-conflict overwrite)
+            match extracted.unpack(&extracted_filename_with_prefix) {
+                Ok(unpacked_paths) => {
+                    println!("Checkpoint (Get Mode): Unpacking directory {} ({} entries) to {}.",
+                        &extracted.filename, unpacked_paths.len().to_string(), extracted_filename_with_prefix);
+                    extracted_filenames.push( (extracted.filename.to_owned(), extracted_filename_with_prefix) );
+                }
+                Err(err) => {
+                    eprintln!("Error (Get Mode): Unable to unpack directory {} from {}: {}.",
+                        &extracted.filename, &audio_filename, err);
+                    // do not propagate error, continue iter to next embedded file
                 }
             }
-            None => {
-                println!("Checkpoint (Get Mode): Finished searching id3v2 tag data.");
-                break;
+            continue;
+        }
+
+        match write_extracted_file(&extracted_filename_with_prefix, &extracted.data, extracted.modified) {
+            Ok(_) => {
+                println!("Checkpoint (Get Mode): Extracting {} to {}.",
+                    &extracted.filename, extracted_filename_with_prefix);
+                extracted_filenames.push( (extracted.filename.to_owned(), extracted_filename_with_prefix) );
+            }
+            Err(_) => {
+                eprintln!("Error (Get Mode): Unable to extract {} from {}",
+                    &extracted.filename, &audio_filename);
+                // do not propagate error, continue iter to next embedded file
             }
         };
     }
-    if id3stego_frame_count == 0 {
+
+    if extracted_files.is_empty() {
         Ok(None)
     }
+    else if failed == extracted_files.len() {
+        Err("no embedded file could be extracted".into())
+    }
     else {
         Ok(Some(extracted_filenames))
     }
 }
 
-fn is_supported_filetype(filename: &String) -> Result<Option<String>, Box<dyn std::error::Error>> {
-    // returns mime-type if filename is of type mp3, wav, or aiff
-    // otherwise, returns none or error
-
-    match infer::get_from_path(filename) {
-        Ok(kind_ok) => { 
-            match kind_ok { 
-                Some(kind) => {
-                    if kind.mime_type() == "audio/mpeg" || 
-                       kind.mime_type() == "audio/x-wav" || 
-                       kind.mime_type() == "audio/x-aiff" {
-                        return Ok(Some(kind.mime_type().to_string()))
-                    }
-                    else {
-                        // not of type mp3, wav, or aiff
-                        return Ok(None)
-                    }
-                }
-                None => {
-                    // no mimetype found
-                    return Ok(None)
-                }
-            }
+fn list(audio_filename: &str, extractor: &Extractor, json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    // success: prints table or json of embedded files (even in quiet mode), writes nothing
+    // failure: prints error message, returns err
+
+    let listed_files = match extractor.list_path(audio_filename) {
+        Ok(listed_files) => listed_files,
+        Err(err) => {
+            eprintln!("Error (List Mode): Unable to list embedded file(s) in {}: {}.", &audio_filename, err);
+            return Err(Box::new(err))
         }
+    };
+
+    // damaged files are reported (and kept in json), the others are still listed
+    for err in listed_files.iter().filter_map(|listed| listed.as_ref().err()) {
+        eprintln!("Error (List Mode): Unable to list embedded file in {}: {}.", &audio_filename, err);
+    }
+    if json {
+        std::println!("{}", serde_json::to_string_pretty(&listed_files_json(&listed_files))?);
+    }
+    else if listed_files.is_empty() {
+        std::println!("No id3stego embedded file(s) found in {}.", &audio_filename);
+    }
+    else {
+        let listed_files: Vec<&Listed> = listed_files.iter().filter_map(|listed| listed.as_ref().ok()).collect();
+        if !listed_files.is_empty() {
+            std::print!("{}", listed_files_table(&listed_files));
+        }
+    }
+    Ok(())
+}
+
+fn encryption_name(encryption: Option<EncryptionMethod>) -> &'static str {
+    match encryption {
+        Some(EncryptionMethod::Passphrase) => "passphrase",
+        Some(EncryptionMethod::Recipients) => "recipients",
+        None => "none",
+    }
+}
+
+fn listed_files_table(listed_files: &[&Listed]) -> String {
+    // one row per embedded file
+
+    let header = ["KEY", "FILENAME", "MIME-TYPE", "SIZE", "CARRIER", "FRAMES", "COMPRESSED", "ENCRYPTED"];
+    let mut rows = Vec::with_capacity(listed_files.len());
+    for listed in listed_files {
+        rows.push(vec![
+            listed.key.clone(),
+            // embedded directories are listed with a trailing slash, encrypted files have no filename
+            match listed.filename.as_str() {
+                "" => "-".to_string(),
+                filename if listed.archive => format!("{}/", filename),
+                filename => filename.to_string(),
+            },
+            listed.mime_type.clone(),
+            // encrypted files hide their size and compression along with everything else
+            listed.size.map_or("-".to_string(), |size| size.to_string()),
+            listed.carrier.frame_id().to_string(),
+            listed.frames.to_string(),
+            (match listed.compressed { Some(true) => "yes", Some(false) => "no", None => "-" }).to_string(),
+            encryption_name(listed.encryption).to_string(),
+        ]);
+    }
+    table(&header, rows)
+}
+
+fn table(header: &[&str], rows: Vec<Vec<String>>) -> String {
+    // header then one line per row, columns padded to widest value and separated by two spaces
+    // (trailing spaces trimmed)
+
+    let rows: Vec<Vec<String>> = std::iter::once(header.iter().map(|cell| cell.to_string()).collect()).chain(rows).collect();
+    let widths: Vec<usize> = (0..header.len())
+        .map(|column| rows.iter().map(|row| row[column].chars().count()).max().unwrap_or(0))
+        .collect();
+
+    let mut table = String::new();
+    for row in rows {
+        let cells: Vec<String> = row.iter().zip(&widths).map(|(cell, width)| format!("{:<width$}", cell, width = width)).collect();
+        table.push_str(cells.join("  ").trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+fn listed_files_json(listed_files: &[Result<Listed, Error>]) -> serde_json::Value {
+    let files: Vec<serde_json::Value> = listed_files
+        .iter()
+        .map(|listed| match listed {
+            Ok(listed) => serde_json::json!({
+                "key": listed.key,
+                "filename": listed.filename,
+                "mime_type": listed.mime_type,
+                "size": listed.size,
+                "stored_size": listed.stored_size,
+                "carrier": listed.carrier.frame_id(),
+                "frames": listed.frames,
+                "compressed": listed.compressed,
+                "archive": listed.archive,
+                "encryption": listed.encryption.map(|encryption| encryption_name(Some(encryption))),
+                "sha256": listed.sha256.map(|sha256| to_hex(&sha256)),
+                "modified": listed.modified
+                    .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map(|since_epoch| since_epoch.as_secs()),
+            }),
+            // damaged files only carry the reason they could not be listed
+            Err(err) => serde_json::json!({ "error": err.to_string() }),
+        })
+        .collect();
+    serde_json::Value::Array(files)
+}
+
+fn detect(audio_filename: &str, detector: &Detector, json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    // success: prints table or json of findings (even in quiet mode), writes nothing
+    // failure: prints error message, returns err
+
+    let detection = match detector.detect_path(audio_filename) {
+        Ok(detection) => detection,
         Err(err) => {
-            // error reading mimetype
+            eprintln!("Error (Detect Mode): Unable to scan {}: {}.", &audio_filename, err);
             return Err(Box::new(err))
         }
     };
+
+    if json {
+        std::println!("{}", serde_json::to_string_pretty(&detection_json(&detection))?);
+        return Ok(());
+    }
+    match (detection.tag_size, detection.duration) {
+        (None, _) => println!("Checkpoint (Detect Mode): No id3v2 tag found in {}.", &audio_filename),
+        (Some(tag_size), Some(duration)) => println!("Checkpoint (Detect Mode): {} has a {} byte id3v2 tag for {:.1} seconds of audio.", 
+            &audio_filename, tag_size, duration),
+        (Some(tag_size), None) => println!("Checkpoint (Detect Mode): {} has a {} byte id3v2 tag.", &audio_filename, tag_size),
+    }
+    if detection.findings.is_empty() {
+        std::println!("No suspicious frames, sizes, or padding found in {}.", &audio_filename);
+    }
+    else {
+        std::print!("{}", findings_table(&detection));
+    }
+    Ok(())
 }
 
-fn add_filename_prefix_preserve_path(file_path_str: &String, file_name_str: &String, prefix: &mut String) -> String {
-    // constructs output path and filename in form of file_path_str\prefix-file_name_str
-    //
-    // ex as used in put mode:  
-    //  file_path_str = "c:\id3stego\test.mp3", file_name_str = "test.mp3", prefix = "output" 
-    //      -> "c:\id3stego\test.mp3" -> "c:\id3stego\prefix-test.mp3"
-    //
-    // ex as used in get mode:  
-    //  file_path_str = "c:\id3stego\test.mp3", file_name_str = "test.jpg", prefix = "extracted" 
-    //      -> "c:\id3stego\extracted-test.jpg"
+fn findings_table(detection: &Detection) -> String {
+    // one row per finding (explanations last, so they are never padded)
 
+    let header = ["SCORE", "CHECK", "FRAME", "EXPLANATION"];
+    let mut rows = Vec::with_capacity(detection.findings.len());
+    for finding in &detection.findings {
+        rows.push(vec![
+            finding.score.to_string(),
+            finding.check.to_string(),
+            finding.frame.clone().unwrap_or_else(|| "-".to_string()),
+            finding.explanation.clone(),
+        ]);
+    }
+    table(&header, rows)
+}
 
-    // extract filename from full file path
-    let filename_only = get_filename_drop_path(Path::new(&file_name_str), &prefix);
-    
-    // add prefix to filename (require &mut string argument)
-    prefix.push_str(&filename_only);
+fn detection_json(detection: &Detection) -> serde_json::Value {
+    let findings: Vec<serde_json::Value> = detection
+        .findings
+        .iter()
+        .map(|finding| {
+            serde_json::json!({
+                "check": finding.check.to_string(),
+                "frame": finding.frame,
+                "score": finding.score,
+                "explanation": finding.explanation,
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "format": detection.format.mime_type(),
+        "tag_size": detection.tag_size,
+        "duration": detection.duration,
+        "findings": findings,
+    })
+}
 
-    // reconstruct full file path by combining path contained in file_path_str with filename_only
-    let file_path = Path::new(&file_path_str);
-    let new_file_path = file_path.with_file_name(&prefix);
-    let new_file_path_str = match new_file_path.to_str() {
-        Some(new_file_path_str) => {
-            new_file_path_str.to_string()
+fn expand_other_filenames(other_args: &[String]) -> Result<Vec<String>, String> {
+    // success: return every other file, with glob patterns (ex: *.txt) replaced by matching files
+    // failure: return first other file (or pattern) not found
+
+    let mut other_filenames = Vec::new();
+    for other_arg in other_args {
+        if Path::exists(Path::new(other_arg)) {
+            other_filenames.push(other_arg.to_string());
+            continue;
         }
-        None => {
-            // If unable to reconstrct new path, use filename_only
-            filename_only.to_owned()
+        let matches: Vec<String> = match glob::glob(other_arg) {
+            Ok(paths) => paths
+                .filter_map(|path| path.ok())
+                .filter(|path| path.is_file())
+                .map(|path| path.to_string_lossy().to_string())
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        if matches.is_empty() {
+            return Err(other_arg.to_string())
+        }
+        other_filenames.extend(matches);
+    }
+    Ok(other_filenames)
+}
+
+fn strip(audio_filename: &str, stripper: &Stripper, naming: &OutputNaming) 
+    -> Result<Option<String>, Box<dyn std::error::Error>> {
+    // success: return output filename, or none if nothing was embedded (no file written)
+    // failure: prints error message, returns err
+
+    let output_filename = match output_filename(audio_filename, naming) {
+        Ok(Some(output_filename)) => output_filename,
+        Ok(None) => {
+            eprintln!("Error (Strip Mode): Output file already exists, skipped (--on-conflict skip).");
+            return Err("output file already exists".into())
+        }
+        Err(err) => {
+            eprintln!("Error (Strip Mode): {}.", err);
+            return Err(err.into())
         }
     };
 
-    // return new file path as String
-    new_file_path_str
+    match Format::detect_path(audio_filename) {
+        Ok(format) => {
+            println!("Checkpoint (Strip Mode): Mime-type of {} is \'{}\'.", 
+                &audio_filename, format.mime_type());
+        }
+        Err(err) => {
+            eprintln!("Error (Strip Mode): Mime-type of {} must be mp3, wav, aiff, flac, ogg, or m4a.", 
+                &audio_filename);
+            return Err(Box::new(err))
+        }
+    }
 
+    println!("Checkpoint (Strip Mode): Searching id3v2 tag and native metadata for frames containing files previously embedded by id3stego.");
+    match stripper.strip_path(audio_filename, &output_filename) {
+        Ok(stripped) => {
+            if stripped.frames == 0 {
+                return Ok(None)
+            }
+            for key in &stripped.keys {
+                println!("\t- removed embedded file with frame description key \'{}\'", key);
+            }
+            println!("Checkpoint (Strip Mode): Removed {} frame(s) containing {} embedded file(s).", 
+                stripped.frames.to_string(), stripped.keys.len().to_string());
+            if stripped.tag_removed {
+                println!("Checkpoint (Strip Mode): Removed id3v2 tag (nothing else was left in it).");
+            }
+            println!("Checkpoint (Strip Mode): Writing audio file to {}.", &output_filename);
+        }
+        Err(err) => {
+            eprintln!("Error (Strip Mode): Unable to strip embedded file(s) from {}: {}.", &audio_filename, err);
+            return Err(Box::new(err))
+        }
+    }
+
+    Ok(Some(output_filename))
 }
 
-fn get_filename_drop_path(file_path: &Path, prefix: &String) -> String {
-    // extracts filename from path string
-    // ex: "c:\id3stego\test.jpg" --> "test.jpg"
+fn output_filename(audio_filename: &str, naming: &OutputNaming) -> Result<Option<String>, String> {
+    // success: return output audio file path (put and strip modes), or none if it exists and should be skipped
+    // failure: return reason (unknown placeholder in --output)
 
-    let filename_only = match file_path.file_name() {
-        Some(filename_os_str) => {
-            match filename_os_str.to_str() {
-                Some(filename_str) => {
-                    filename_str.to_string()
-                }
-                None => {
-                    // If unable to extract filename from full path, use default filename string
-                    generate_random_filename_with_prefix(prefix.to_owned())
+    if naming.in_place {
+        if naming.output.is_some() {
+            return Err("Use either --in-place or --output, not both".to_string())
+        }
+        return Ok(Some(audio_filename.to_string()))
+    }
+    let output_filename = match &naming.output {
+        Some(output_template) => {
+            let (stem, ext) = split_stem_ext(&get_filename_drop_path(Path::new(audio_filename), "output-"));
+            match expand_name_template(output_template, &[("stem", &stem), ("ext", &ext)]) {
+                Ok(output_filename) => output_filename,
+                Err(placeholder) => {
+                    return Err(format!("Unknown placeholder \'{}\' in output path (--output), use {{stem}} or {{ext}}", placeholder))
                 }
             }
         }
-        None => {
-            // If unable to extract filename from full path, use default filename string
-            generate_random_filename_with_prefix(prefix.to_owned())
-        }
+        None => add_filename_prefix_preserve_path(audio_filename, audio_filename, "output-"),
     };
-
-    // return only filename part of file path
-    filename_only
-
+    if Path::new(&output_filename) == Path::new(audio_filename) {
+        return Err("Output path (--output) is the audio file itself".to_string())
+    }
+    Ok(resolve_conflict(Path::new(&output_filename), naming.on_conflict)
+        .map(|output_path| output_path.to_string_lossy().to_string()))
 }
 
-fn generate_random_filename_with_prefix(mut prefix: String) -> String {
-    // generate default output filename (prefix + 5 random chars) 
-    // used if None encountered when extracting filename from full path (unlikely)
-    // or if None encountered when reconstructing full file path with new filename (also unlikely)
-    
-    prefix.push_str( &thread_rng()
-        .sample_iter(&Alphanumeric)
-        .take(5)
-        .map(char::from)
-        .collect::<String>()
-    );
+fn extracted_filename(audio_filename: &str, extracted: &Extracted, index: usize, naming: &OutputNaming) 
+    -> Result<Option<String>, String> {
+    // success: return path to save extracted file to (get mode), or none if it exists and should be skipped
+    // failure: return reason (naming template does not make a plain file name)
+    // ex: --name "{key}-{index}{ext}" -> "id3stegoAbCdE12345-1.jpg"
 
-    // return prefix + 5 random chars
-    prefix
+    // embedded filename is never trusted as a path, and keys may contain '/' or ':'
+    let (stem, ext) = split_stem_ext(&get_filename_drop_path(Path::new(&extracted.filename), "extracted-"));
+    let key = extracted.key.replace(['/', ':', '\\'], "_");
+    let index = (index + 1).to_string();
+    let name = expand_name_template(&naming.name_template, &[("stem", &stem), ("ext", &ext), ("key", &key), ("index", &index)])?;
+    if !matches!(Path::new(&name).components().collect::<Vec<_>>().as_slice(), [std::path::Component::Normal(_)]) {
+        return Err(format!("naming template (--name) makes \'{}\', which is not a plain file name", name))
+    }
 
+    let extracted_path = match &naming.out_dir {
+        Some(out_dir) => Path::new(out_dir).join(&name),
+        None => Path::new(audio_filename).with_file_name(&name),
+    };
+    Ok(resolve_conflict(&extracted_path, naming.on_conflict)
+        .map(|extracted_path| extracted_path.to_string_lossy().to_string()))
 }
 
-fn error_cleanup(filename: &String) /* -> Result<(), Box<dyn std::error::Error>> */ {
-    // deletes copied output file if error occurs after making copy.
-    // to do:  consider adding directly in put method (only called twice in put function)
-    match std::fs::remove_file(filename) {
+fn keygen(key_filename: &str) -> Result<Recipient, Box<dyn std::error::Error>> {
+    // success: return new public key (recipient)
+    // failure: prints error message, returns err
+    // private key saved to key_filename, public key saved to key_filename + '.pub'
+
+    let public_key_filename = format!("{}.pub", key_filename);
+    for filename in [key_filename, public_key_filename.as_str()] {
+        if Path::exists(Path::new(filename)) {
+            eprintln!("Error (Keygen Mode): {} already exists (will not overwrite keys).", filename);
+            return Err("key file already exists".into())
+        }
+    }
+
+    let identity = Identity::generate();
+    let recipient = identity.recipient();
+    let private_contents = format!("# public key: {}\n{}\n", recipient, identity.to_secret_string());
+    match write_private_file(key_filename, &private_contents) {
         Ok(_) => {
-            eprintln!("Error (Put Mode): Cleaning up, removing {}.", filename);
-            //Ok(())
+            println!("Checkpoint (Keygen Mode): Saved private key to {}.", &key_filename);
         }
-        Err(_) => {
-            eprintln!("Error (Put Mode): Unable to delete (clean up) {}.", filename);
-            //return Err(Box::new(err))
-            //does not propagate errors (errors handled by put function)
+        Err(err) => {
+            eprintln!("Error (Keygen Mode): Unable to save private key to {}: {}.", &key_filename, err);
+            return Err(Box::new(err))
+        }
+    }
+    match std::fs::write(&public_key_filename, format!("{}\n", recipient)) {
+        Ok(_) => {
+            println!("Checkpoint (Keygen Mode): Saved public key to {}.", &public_key_filename);
+        }
+        Err(err) => {
+            eprintln!("Error (Keygen Mode): Unable to save public key to {}: {}.", &public_key_filename, err);
+            return Err(Box::new(err))
         }
     }
-}
\ No newline at end of file
+
+    Ok(recipient)
+}
+
+fn write_private_file(filename: &str, contents: &str) -> std::io::Result<()> {
+    // private key files are only readable by their owner (where supported)
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(filename)?.write_all(contents.as_bytes())
+}
+
+fn read_recipients(recipient_arg: &str) -> Result<Vec<Recipient>, Error> {
+    // recipient is either a public key (id3stego-public-...) or a path to a file of public keys
+
+    match recipient_arg.parse::<Recipient>() {
+        Ok(recipient) => Ok(vec![recipient]),
+        Err(_) => Recipient::read_from_path(recipient_arg),
+    }
+}
+
+fn write_extracted_file(filename: &str, data: &[u8], modified: Option<std::time::SystemTime>) -> std::io::Result<()> {
+    // restores original modification time of extracted file, if it was stored
+    // written to a temp file first, then renamed into place (never a partially written file)
+
+    id3stego::write_atomic(filename, data)?;
+    if let Some(modified) = modified {
+        std::fs::File::options().write(true).open(filename)?.set_modified(modified)?;
+    }
+    Ok(())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn prompt_passphrase(confirm: bool) -> Result<String, Box<dyn std::error::Error>> {
+    // reads passphrase from terminal without echo
+    // put mode (confirm = true) asks twice to catch typos
+
+    let passphrase = rpassword::prompt_password("Passphrase: ")?;
+    if passphrase.is_empty() {
+        return Err("Passphrase must not be empty".into())
+    }
+    if confirm && rpassword::prompt_password("Confirm passphrase: ")? != passphrase {
+        return Err("Passphrases do not match".into())
+    }
+    Ok(passphrase)
+}
+
+fn read_passphrase_file(passphrase_filename: &str) -> Result<String, Box<dyn std::error::Error>> {
+    // passphrase is first line of file (trailing newline not part of passphrase)
+
+    let contents = std::fs::read_to_string(passphrase_filename)?;
+    let passphrase = contents.lines().next().unwrap_or("").to_string();
+    if passphrase.is_empty() {
+        return Err("Passphrase must not be empty".into())
+    }
+    Ok(passphrase)
+}
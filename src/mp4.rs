@@ -0,0 +1,380 @@
+use crate::carrier::{self, Carrier, Record};
+use crate::error::{Error, Result};
+
+// MP4 (m4a) files are a tree of atoms (boxes). Every atom starts with a header:
+//
+// | Field     | Size    | Value                                                          |
+// |-----------+---------+----------------------------------------------------------------|
+// | size      | 4 bytes | size of the atom, header included (1 = 64 bit size follows,   |
+// |           |         | 0 = atom extends to the end of the file)                       |
+// | type      | 4 bytes | ex: b"moov"                                                    |
+// | largesize | 8 bytes | only if size is 1                                              |
+//
+// All integers are big endian. Embedded files are stored under the movie atom, which holds the
+// track tables, while the audio itself stays in 'mdat':
+//  - freeform ("----") atom in moov/udta/meta/ilst, as iTunes stores custom tags: a 'mean' atom
+//    (version/flags, then b"com.id3stego"), a 'name' atom (version/flags, then the key), and a
+//    'data' atom (type 0 = binary, locale 0, then filename \0 mime-type \0 data)
+//  - 'uuid' atom in moov/udta: id3stego's 16 byte uuid, then the record packed as key \0
+//    filename \0 mime-type \0 data (see carrier.rs)
+// A missing udta atom is created, and missing meta (with an 'mdir' handler named "id3stego") and
+// ilst atoms only for freeform atoms. Stripping drops an ilst atom it empties, then a meta atom
+// left with nothing but id3stego's handler (a meta atom of the file's own is kept), then an udta
+// atom it empties.
+//
+// The chunk offset tables ('stco' or 'co64' in moov/trak/mdia/minf/stbl) hold absolute file
+// positions of the audio, so when moov grows or shrinks every offset past the end of the old
+// moov atom (mdat after moov) is moved by the same amount. Offsets before it are left alone.
+
+const ATOM_HEADER_LEN: usize = 8;
+const LARGE_ATOM_HEADER_LEN: usize = 16;
+/// Version and flags in front of the contents of "full" atoms (meta, mean, name, stco, ...).
+const FULL_ATOM_HEADER_LEN: usize = 4;
+/// Name (null terminated) of the 'mdir' handler of meta atoms created by id3stego.
+const HANDLER_NAME: &[u8] = b"id3stego\0";
+
+/// User type of 'uuid' atoms written by id3stego.
+const ID3STEGO_UUID: [u8; 16] = [
+    0x49, 0x33, 0x53, 0x47, 0x8a, 0x41, 0x4e, 0x2b, 0x9d, 0x07, 0x6c, 0x35, 0xe1, 0xf0, 0x52, 0xa7,
+];
+/// 'mean' (domain) of freeform atoms written by id3stego.
+const FREEFORM_MEAN: &str = "com.id3stego";
+const DATA_TYPE_BINARY: u32 = 0;
+const DATA_HEADER_LEN: usize = 8;
+
+/// Path from moov to the atoms holding chunk offset tables.
+const SAMPLE_TABLE_PATH: [&[u8; 4]; 4] = [b"trak", b"mdia", b"minf", b"stbl"];
+
+/// One atom of a list of sibling atoms.
+struct Atom<'a> {
+    kind: [u8; 4],
+    /// Contents after the header.
+    body: &'a [u8],
+    /// Offset of the atom within the parsed bytes.
+    start: usize,
+    /// Offset of the contents within the parsed bytes.
+    body_start: usize,
+    /// The whole atom, header included.
+    bytes: &'a [u8],
+}
+
+/// Reads every record stored by id3stego in the mp4 file `bytes`.
+pub(crate) fn read_records(bytes: &[u8]) -> Result<Vec<Record>> {
+    let atoms = parse_atoms(bytes)?;
+    let moov_children = parse_atoms(moov(&atoms)?.body)?;
+    let mut records = Vec::new();
+    let udta = match find(&moov_children, b"udta") {
+        Some(udta) => udta,
+        None => return Ok(records),
+    };
+    let udta_children = parse_atoms(udta.body)?;
+    for atom in &udta_children {
+        if let Some(record) = uuid_record(atom)? {
+            records.push(record);
+        }
+    }
+    if let Some(meta) = find(&udta_children, b"meta") {
+        let meta_children = parse_atoms(&meta.body[meta_children_start(meta.body)..])?;
+        if let Some(ilst) = find(&meta_children, b"ilst") {
+            for atom in &parse_atoms(ilst.body)? {
+                if let Some(record) = freeform_record(atom)? {
+                    records.push(record);
+                }
+            }
+        }
+    }
+    Ok(records)
+}
+
+/// Returns a copy of the mp4 file `bytes` with one new atom per record.
+pub(crate) fn add_records(bytes: &[u8], records: &[Record]) -> Result<Vec<u8>> {
+    let (output, _) = rewrite(bytes, records, false)?;
+    Ok(output)
+}
+
+/// Returns a copy of the mp4 file `bytes` without the atoms holding id3stego records, with the
+/// removed records (`None` for damaged atoms, which are removed too).
+pub(crate) fn remove_records(bytes: &[u8]) -> Result<(Vec<u8>, Vec<Option<Record>>)> {
+    rewrite(bytes, &[], true)
+}
+
+fn invalid(reason: &'static str) -> Error {
+    Error::InvalidContainer { format: "mp4", reason }
+}
+
+fn parse_atoms(data: &[u8]) -> Result<Vec<Atom<'_>>> {
+    let mut atoms = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        if data.len() - offset < ATOM_HEADER_LEN {
+            return Err(invalid("atom header is truncated"));
+        }
+        let size = u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap_or_default()) as u64;
+        let (header_len, size) = match size {
+            0 => (ATOM_HEADER_LEN, (data.len() - offset) as u64),
+            1 => match data.get(offset + 8..offset + LARGE_ATOM_HEADER_LEN) {
+                Some(largesize) => (LARGE_ATOM_HEADER_LEN, u64::from_be_bytes(largesize.try_into().unwrap_or_default())),
+                None => return Err(invalid("atom header is truncated")),
+            },
+            size => (ATOM_HEADER_LEN, size),
+        };
+        if size < header_len as u64 || size > (data.len() - offset) as u64 {
+            return Err(invalid("atom size is out of range"));
+        }
+        let end = offset + size as usize;
+        let mut kind = [0; 4];
+        kind.copy_from_slice(&data[offset + 4..offset + 8]);
+        atoms.push(Atom {
+            kind,
+            body: &data[offset + header_len..end],
+            start: offset,
+            body_start: offset + header_len,
+            bytes: &data[offset..end],
+        });
+        offset = end;
+    }
+    Ok(atoms)
+}
+
+fn atom(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    // 64 bit size only if the 32 bit size field is too small
+    let mut atom = Vec::with_capacity(LARGE_ATOM_HEADER_LEN + body.len());
+    match u32::try_from(ATOM_HEADER_LEN + body.len()) {
+        Ok(size) => {
+            atom.extend_from_slice(&size.to_be_bytes());
+            atom.extend_from_slice(kind);
+        }
+        Err(_) => {
+            atom.extend_from_slice(&1u32.to_be_bytes());
+            atom.extend_from_slice(kind);
+            atom.extend_from_slice(&((LARGE_ATOM_HEADER_LEN + body.len()) as u64).to_be_bytes());
+        }
+    }
+    atom.extend_from_slice(body);
+    atom
+}
+
+fn find<'a, 'b>(atoms: &'b [Atom<'a>], kind: &[u8; 4]) -> Option<&'b Atom<'a>> {
+    atoms.iter().find(|atom| &atom.kind == kind)
+}
+
+fn moov<'a, 'b>(atoms: &'b [Atom<'a>]) -> Result<&'b Atom<'a>> {
+    find(atoms, b"moov").ok_or_else(|| invalid("missing moov atom"))
+}
+
+fn meta_children_start(body: &[u8]) -> usize {
+    // iso meta atoms have version and flags before their children, older quicktime ones do not
+    if body.get(4..8) == Some(b"hdlr") { 0 } else { FULL_ATOM_HEADER_LEN }
+}
+
+fn rewrite(bytes: &[u8], added: &[Record], strip: bool) -> Result<(Vec<u8>, Vec<Option<Record>>)> {
+    // rebuilds moov/udta/meta/ilst without stripped atoms and with added ones, then moves the
+    // chunk offsets by as much as moov changed in size
+    let atoms = parse_atoms(bytes)?;
+    let moov = moov(&atoms)?;
+    let moov_end = moov.start + moov.bytes.len();
+    let moov_children = parse_atoms(moov.body)?;
+    let udta_children = match find(&moov_children, b"udta") {
+        Some(udta) => parse_atoms(udta.body)?,
+        None => Vec::new(),
+    };
+    let (meta_header, meta_children) = match find(&udta_children, b"meta") {
+        Some(meta) => {
+            let children_start = meta_children_start(meta.body);
+            (&meta.body[..children_start], parse_atoms(&meta.body[children_start..])?)
+        }
+        None => (&[0; FULL_ATOM_HEADER_LEN][..], Vec::new()),
+    };
+    let ilst_children = match find(&meta_children, b"ilst") {
+        Some(ilst) => parse_atoms(ilst.body)?,
+        None => Vec::new(),
+    };
+    let mut removed = Vec::new();
+
+    let freeform_added = added.iter().any(|record| record.carrier != Carrier::Mp4Uuid);
+    let mut ilst = Vec::new();
+    for child in &ilst_children {
+        if !strip || keep(freeform_record(child), &mut removed) {
+            ilst.extend_from_slice(child.bytes);
+        }
+    }
+    for record in added.iter().filter(|record| record.carrier != Carrier::Mp4Uuid) {
+        ilst.extend_from_slice(&freeform_atom(record));
+    }
+    // so that stripping restores the file as it was, only containers created by id3stego (told
+    // apart by its metadata handler) are dropped once empty
+    let own_meta = find(&meta_children, b"hdlr").is_some_and(|hdlr| hdlr.bytes == metadata_handler().as_slice());
+    let drop_ilst = strip && own_meta && !ilst_children.is_empty() && ilst.is_empty();
+    let drop_meta = drop_ilst && meta_children.iter().all(|child| matches!(&child.kind, b"hdlr" | b"ilst"));
+    let mut meta = meta_header.to_vec();
+    if find(&meta_children, b"hdlr").is_none() && freeform_added {
+        meta.extend_from_slice(&metadata_handler());
+    }
+    for child in &meta_children {
+        match &child.kind {
+            b"ilst" if drop_ilst => {}
+            b"ilst" => meta.extend_from_slice(&atom(b"ilst", &ilst)),
+            _ => meta.extend_from_slice(child.bytes),
+        }
+    }
+    if find(&meta_children, b"ilst").is_none() && freeform_added {
+        meta.extend_from_slice(&atom(b"ilst", &ilst));
+    }
+    let mut udta = Vec::new();
+    for child in &udta_children {
+        match &child.kind {
+            b"meta" if drop_meta => {}
+            b"meta" => udta.extend_from_slice(&atom(b"meta", &meta)),
+            _ if !strip || keep(uuid_record(child), &mut removed) => udta.extend_from_slice(child.bytes),
+            _ => {}
+        }
+    }
+    if find(&udta_children, b"meta").is_none() && freeform_added {
+        udta.extend_from_slice(&atom(b"meta", &meta));
+    }
+    for record in added.iter().filter(|record| record.carrier == Carrier::Mp4Uuid) {
+        udta.extend_from_slice(&atom(b"uuid", &[ID3STEGO_UUID.as_slice(), &record.to_native()].concat()));
+    }
+    if strip && removed.is_empty() {
+        return Ok((bytes.to_vec(), removed));
+    }
+
+    let mut moov_body = Vec::with_capacity(moov.body.len() + udta.len());
+    for child in &moov_children {
+        match &child.kind {
+            b"udta" if udta.is_empty() => {}
+            b"udta" => moov_body.extend_from_slice(&atom(b"udta", &udta)),
+            _ => moov_body.extend_from_slice(child.bytes),
+        }
+    }
+    if find(&moov_children, b"udta").is_none() && !udta.is_empty() {
+        moov_body.extend_from_slice(&atom(b"udta", &udta));
+    }
+    let mut new_moov = atom(b"moov", &moov_body);
+    let shift = new_moov.len() as i64 - moov.bytes.len() as i64;
+    if shift != 0 {
+        move_chunk_offsets(&mut new_moov, moov_end as u64, shift)?;
+    }
+
+    let mut output = Vec::with_capacity(bytes.len() + new_moov.len() - moov.bytes.len());
+    output.extend_from_slice(&bytes[..moov.start]);
+    output.extend_from_slice(&new_moov);
+    output.extend_from_slice(&bytes[moov_end..]);
+    Ok((output, removed))
+}
+
+fn keep(record: Result<Option<Record>>, removed: &mut Vec<Option<Record>>) -> bool {
+    // keeps atoms not written by id3stego, remembers the others (none if damaged)
+    match record {
+        Ok(Some(record)) => removed.push(Some(record)),
+        Err(_) => removed.push(None),
+        Ok(None) => return true,
+    }
+    false
+}
+
+fn move_chunk_offsets(moov: &mut [u8], moov_end: u64, shift: i64) -> Result<()> {
+    // every chunk stored after the old moov atom moves with it
+    let mut tables = Vec::new();
+    let moov_body_start = parse_atoms(moov)?[0].body_start;
+    find_chunk_offsets(&moov[moov_body_start..], moov_body_start, &SAMPLE_TABLE_PATH, &mut tables)?;
+    for (start, len, large) in tables {
+        let entry_len = if large { 8 } else { 4 };
+        let table = &mut moov[start..start + len];
+        if table.len() < FULL_ATOM_HEADER_LEN + 4 {
+            return Err(invalid("chunk offset table is truncated"));
+        }
+        let count = u32::from_be_bytes(table[4..8].try_into().unwrap_or_default()) as usize;
+        let entries = &mut table[FULL_ATOM_HEADER_LEN + 4..];
+        if entries.len() / entry_len < count {
+            return Err(invalid("chunk offset table is truncated"));
+        }
+        for entry in entries.chunks_exact_mut(entry_len).take(count) {
+            let offset = match large {
+                true => u64::from_be_bytes(entry.try_into().unwrap_or_default()),
+                false => u32::from_be_bytes(entry.try_into().unwrap_or_default()) as u64,
+            };
+            if offset < moov_end {
+                continue;
+            }
+            let moved = offset.checked_add_signed(shift).ok_or_else(|| invalid("chunk offset is out of range"))?;
+            match large {
+                true => entry.copy_from_slice(&moved.to_be_bytes()),
+                false => {
+                    let moved = u32::try_from(moved).map_err(|_| invalid("chunk offset does not fit in stco table"))?;
+                    entry.copy_from_slice(&moved.to_be_bytes());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn find_chunk_offsets(data: &[u8], base: usize, path: &[&[u8; 4]], tables: &mut Vec<(usize, usize, bool)>) -> Result<()> {
+    // collects (start, length, 64 bit) of the stco and co64 atom contents below path
+    for atom in &parse_atoms(data)? {
+        let body_start = base + atom.body_start;
+        match path.split_first() {
+            Some((kind, rest)) if &atom.kind == *kind => find_chunk_offsets(atom.body, body_start, rest, tables)?,
+            None if &atom.kind == b"stco" => tables.push((body_start, atom.body.len(), false)),
+            None if &atom.kind == b"co64" => tables.push((body_start, atom.body.len(), true)),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn metadata_handler() -> Vec<u8> {
+    // version/flags, pre-defined, handler type 'mdir', reserved ('appl' and zeros), then a name
+    // telling the meta atoms created by id3stego apart from the file's own
+    let mut handler = vec![0; FULL_ATOM_HEADER_LEN + 4];
+    handler.extend_from_slice(b"mdirappl");
+    handler.extend_from_slice(&[0; 8]);
+    handler.extend_from_slice(HANDLER_NAME);
+    atom(b"hdlr", &handler)
+}
+
+fn freeform_atom(record: &Record) -> Vec<u8> {
+    let mut mean = vec![0; FULL_ATOM_HEADER_LEN];
+    mean.extend_from_slice(FREEFORM_MEAN.as_bytes());
+    let mut name = vec![0; FULL_ATOM_HEADER_LEN];
+    name.extend_from_slice(record.key.as_bytes());
+    let mut data = DATA_TYPE_BINARY.to_be_bytes().to_vec();
+    // locale
+    data.extend_from_slice(&[0; 4]);
+    data.extend_from_slice(&carrier::pack(&record.filename, &record.mime_type, &record.data));
+    atom(b"----", &[atom(b"mean", &mean), atom(b"name", &name), atom(b"data", &data)].concat())
+}
+
+fn freeform_record(atom: &Atom) -> Result<Option<Record>> {
+    // success: return the record held by an id3stego freeform atom, or none for any other atom
+    // failure: a freeform atom with id3stego's mean that cannot be read
+    if &atom.kind != b"----" {
+        return Ok(None);
+    }
+    let children = parse_atoms(atom.body)?;
+    let field = |kind, header_len| find(&children, kind).and_then(|child| child.body.get(header_len..));
+    if field(b"mean", FULL_ATOM_HEADER_LEN) != Some(FREEFORM_MEAN.as_bytes()) {
+        return Ok(None);
+    }
+    let key = field(b"name", FULL_ATOM_HEADER_LEN).and_then(|key| String::from_utf8(key.to_vec()).ok());
+    let packed = field(b"data", DATA_HEADER_LEN).and_then(carrier::unpack);
+    match (key, packed) {
+        (Some(key), Some((filename, mime_type, data))) => {
+            Ok(Some(Record { key, filename, mime_type, carrier: Carrier::Mp4Freeform, data }))
+        }
+        _ => Err(invalid("freeform atom is missing key, filename, or mime-type")),
+    }
+}
+
+fn uuid_record(atom: &Atom) -> Result<Option<Record>> {
+    // success: return the record held by an id3stego uuid atom, or none for any other atom
+    // failure: a uuid atom with id3stego's uuid that cannot be read
+    if &atom.kind != b"uuid" || !atom.body.starts_with(&ID3STEGO_UUID) {
+        return Ok(None);
+    }
+    match Record::from_native(Carrier::Mp4Uuid, &atom.body[ID3STEGO_UUID.len()..]) {
+        Some(record) => Ok(Some(record)),
+        None => Err(invalid("uuid atom is missing key, filename, or mime-type")),
+    }
+}
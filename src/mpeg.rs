@@ -0,0 +1,587 @@
+use std::ops::Range;
+use crate::carrier::{Carrier, Record};
+use crate::error::{Error, Result};
+use crate::lsb::{self, Capacity, Cover};
+
+// Mp3 files are a sequence of MPEG audio frames (after an optional id3v2 tag, and before an
+// optional id3v1 or APE tag). Every frame starts with a 4 byte header:
+//
+// | Field       | Size    | Value                                                         |
+// |-------------+---------+---------------------------------------------------------------|
+// | sync        | 11 bits | all ones                                                      |
+// | version     | 2 bits  | 3 = MPEG-1, 2 = MPEG-2, 0 = MPEG-2.5 (MPEG-2 and 2.5 are      |
+// |             |         | "LSF", low sampling frequency)                                |
+// | layer       | 2 bits  | 1 = layer III                                                 |
+// | protection  | 1 bit   | 0 = a 16 bit CRC follows the header                           |
+// | bitrate     | 4 bits  | index into the bitrate table (0 = free format, 15 = invalid)  |
+// | sample rate | 2 bits  | index into the sample rate table (3 = invalid)                |
+// | padding     | 1 bit   | 1 = the frame is one byte longer                              |
+// | private     | 1 bit   |                                                               |
+// | mode        | 2 bits  | 3 = mono, anything else is two channels                       |
+// | ...         | 6 bits  | mode extension, copyright, original, emphasis                 |
+//
+// followed by the CRC (if protected), the side info (17 or 32 bytes for MPEG-1 mono or stereo,
+// 9 or 17 bytes for LSF), and the frame's share of the main data. The side info starts with
+// main_data_begin (9 bits, 8 for LSF), the number of bytes the frame's main data starts *before*
+// its own share (in the shares of earlier frames: the bit reservoir), the private bits, and the
+// scfsi bits (MPEG-1 only). Then for each granule (2 for MPEG-1, 1 for LSF) and channel:
+//
+// | Field             | Size                | Value                                        |
+// |-------------------+---------------------+----------------------------------------------|
+// | part2_3_length    | 12 bits             | bits of scale factors and huffman data       |
+// | big_values        | 9 bits              | number of big value pairs                    |
+// | global_gain       | 8 bits              | quantizer step size of the granule           |
+// | ...               | 30 bits (LSF 34)    | scale factor compression, window switching,  |
+// |                   |                     | huffman tables, and flags                    |
+//
+// The CRC covers the last two header bytes and the side info (CRC-16, polynomial 0x8005, initial
+// value 0xffff).
+//
+// Embedded files can be hidden in the LSB of every global_gain field (its parity): changing a
+// global gain by 1 scales the granule by 2^(1/4) (1.5 dB), but moves no bits, so every frame
+// keeps its length and its main data, and decodes as before (the CRC of protected frames is
+// recomputed). part2_3_length and big_values cannot change without re-encoding the huffman
+// data, so they are left alone. Hidden bits are laid out as for pcm samples (see lsb.rs), with
+// one global gain per sample.
+//
+// Frames are found by following the frame lengths from the first frame header, resynchronizing
+// on the next header with the same version and sample rate after any damaged data. The first
+// frame is skipped if it holds a Xing, Info, or VBRI header (encoder info read by players).
+//
+// Embedded files can also be stored in the ancillary data: the bytes of the main data shares
+// that no frame's main data uses (from main_data_begin bytes before the frame's share, for the
+// part2_3_length bits of all its granules, rounded up to whole bytes). Decoders skip them, and
+// encoders often leave some unused after frames that needed fewer bits than their bitrate gives.
+// Only these bytes change, so every frame keeps its length, its side info, and the reservoir
+// contents every later frame points back to. The padding bits are left alone, since toggling one
+// changes the frame length (and moves the main data of every frame reaching back past it). The
+// stream ends at damaged data, and the reservoir the next frame points back to stays as is. All
+// free bytes, in file order, hold:
+//
+// | Field   | Size    | Value                                                                 |
+// |---------+---------+-----------------------------------------------------------------------|
+// | magic   | 4 bytes | b"I3SA"                                                               |
+// | size    | 4 bytes | size of the records (big endian)                                      |
+// | records | size    | for each record: its size (4 bytes, big endian), then the record      |
+// |         |         | packed as key \0 filename \0 mime-type \0 data (see carrier.rs)       |
+//
+// Strip mode zeroes the magic, size, and records.
+
+const FRAME_HEADER_LEN: usize = 4;
+const CRC_LEN: usize = 2;
+const ID3V2_HEADER_LEN: usize = 10;
+
+const ANCILLARY_MAGIC: &[u8; 4] = b"I3SA";
+const ANCILLARY_HEADER_LEN: usize = 8;
+const RECORD_SIZE_LEN: usize = 4;
+
+/// MPEG-1 layer III bitrates (kbit/s) by bitrate index.
+const MPEG1_BITRATES: [u32; 15] = [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320];
+/// MPEG-2 and MPEG-2.5 layer III bitrates (kbit/s) by bitrate index.
+const LSF_BITRATES: [u32; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+/// MPEG-1 sample rates by sample rate index (halved for MPEG-2, quartered for MPEG-2.5).
+const MPEG1_SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+
+/// Bits of one granule and channel of the side info, and offset of global_gain in them.
+const MPEG1_GRANULE_BITS: usize = 59;
+const LSF_GRANULE_BITS: usize = 63;
+const GLOBAL_GAIN_OFFSET: usize = 21;
+const GLOBAL_GAIN_BITS: u16 = 8;
+
+const CRC_POLYNOMIAL: u16 = 0x8005;
+
+/// MPEG audio version of a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Version {
+    Mpeg1,
+    Mpeg2,
+    Mpeg25,
+}
+
+/// The fields of a layer III frame header needed to find the frame's parts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Header {
+    pub version: Version,
+    pub protected: bool,
+    pub bitrate: u32,
+    pub sample_rate: u32,
+    pub padding: bool,
+    pub channels: usize,
+}
+
+impl Header {
+    /// Parses the 4 header bytes of a layer III frame (none for other layers, free format
+    /// bitrates, and invalid headers).
+    pub fn parse(bytes: &[u8]) -> Option<Header> {
+        if bytes.len() < FRAME_HEADER_LEN || bytes[0] != 0xff || bytes[1] & 0xe0 != 0xe0 {
+            return None;
+        }
+        let version = match (bytes[1] >> 3) & 0x03 {
+            3 => Version::Mpeg1,
+            2 => Version::Mpeg2,
+            0 => Version::Mpeg25,
+            _ => return None,
+        };
+        if (bytes[1] >> 1) & 0x03 != 1 {
+            return None;
+        }
+        let bitrate_index = (bytes[2] >> 4) as usize;
+        let sample_rate_index = ((bytes[2] >> 2) & 0x03) as usize;
+        if bitrate_index == 0 || bitrate_index == 15 || sample_rate_index == 3 {
+            return None;
+        }
+        let (bitrate, sample_rate) = match version {
+            Version::Mpeg1 => (MPEG1_BITRATES[bitrate_index], MPEG1_SAMPLE_RATES[sample_rate_index]),
+            Version::Mpeg2 => (LSF_BITRATES[bitrate_index], MPEG1_SAMPLE_RATES[sample_rate_index] / 2),
+            Version::Mpeg25 => (LSF_BITRATES[bitrate_index], MPEG1_SAMPLE_RATES[sample_rate_index] / 4),
+        };
+        Some(Header {
+            version,
+            protected: bytes[1] & 0x01 == 0,
+            bitrate,
+            sample_rate,
+            padding: (bytes[2] >> 1) & 0x01 == 1,
+            channels: if bytes[3] >> 6 == 3 { 1 } else { 2 },
+        })
+    }
+
+    /// Length of the whole frame, header included.
+    pub fn frame_len(&self) -> usize {
+        let coefficient = if self.version == Version::Mpeg1 { 144000 } else { 72000 };
+        (coefficient * self.bitrate / self.sample_rate) as usize + self.padding as usize
+    }
+
+    /// Number of granules per frame (2 for MPEG-1, 1 for LSF).
+    pub fn granules(&self) -> usize {
+        if self.version == Version::Mpeg1 { 2 } else { 1 }
+    }
+
+    /// Number of samples (per channel) decoded from one frame.
+    pub fn samples(&self) -> usize {
+        576 * self.granules()
+    }
+
+    /// Length of the side info in bytes.
+    pub fn side_info_len(&self) -> usize {
+        match (self.version, self.channels) {
+            (Version::Mpeg1, 1) => 17,
+            (Version::Mpeg1, _) => 32,
+            (_, 1) => 9,
+            (_, _) => 17,
+        }
+    }
+
+    /// Bits of the side info before the first granule (main_data_begin, private bits, scfsi).
+    fn side_info_prefix_bits(&self) -> usize {
+        match (self.version, self.channels) {
+            (Version::Mpeg1, 1) => 9 + 5 + 4,
+            (Version::Mpeg1, _) => 9 + 3 + 8,
+            (_, 1) => 8 + 1,
+            (_, _) => 8 + 2,
+        }
+    }
+
+    fn granule_bits(&self) -> usize {
+        if self.version == Version::Mpeg1 { MPEG1_GRANULE_BITS } else { LSF_GRANULE_BITS }
+    }
+
+    fn same_stream(&self, other: &Header) -> bool {
+        self.version == other.version && self.sample_rate == other.sample_rate
+    }
+}
+
+/// One granule of one channel, from the side info.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Granule {
+    /// Bits of scale factors and huffman data of this granule and channel.
+    pub part2_3_length: usize,
+    pub big_values: usize,
+    pub global_gain: u8,
+    /// Offset of the global_gain field in the whole file, in bits.
+    pub global_gain_bit: usize,
+}
+
+/// One layer III frame of an mp3 file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Frame {
+    /// Offset of the frame header in the whole file.
+    pub offset: usize,
+    pub header: Header,
+    /// Bytes the frame's main data starts before its own share of the main data.
+    pub main_data_begin: usize,
+    /// Granules of every channel, in side info order (granule 0 channel 0, granule 0 channel 1,
+    /// granule 1 channel 0, ...).
+    pub granules: Vec<Granule>,
+    /// Whether the frame holds a Xing, Info, or VBRI header instead of audio.
+    pub info: bool,
+}
+
+impl Frame {
+    /// Offset of the side info in the whole file.
+    pub fn side_info_offset(&self) -> usize {
+        self.offset + FRAME_HEADER_LEN + if self.header.protected { CRC_LEN } else { 0 }
+    }
+
+    /// Offset of the frame's share of the main data in the whole file (after the side info).
+    pub fn main_data_offset(&self) -> usize {
+        self.side_info_offset() + self.header.side_info_len()
+    }
+
+    /// Offset of the end of the frame (and of its share of the main data) in the whole file.
+    pub fn end_offset(&self) -> usize {
+        self.offset + self.header.frame_len()
+    }
+
+    /// Length of the frame's main data (scale factors and huffman data of every granule), in
+    /// bytes rounded up.
+    pub fn main_data_len(&self) -> usize {
+        self.granules.iter().map(|granule| granule.part2_3_length).sum::<usize>().div_ceil(8)
+    }
+}
+
+/// Finds every layer III frame of the mp3 file `bytes`.
+pub(crate) fn frames(bytes: &[u8]) -> Result<Vec<Frame>> {
+    let mut frames = Vec::new();
+    let mut offset = id3v2_len(bytes);
+    let mut first: Option<Header> = None;
+    while offset + FRAME_HEADER_LEN <= bytes.len() {
+        let header = match Header::parse(&bytes[offset..]) {
+            Some(header) if first.is_none_or(|first| first.same_stream(&header)) => header,
+            // damaged data, an id3v1 or APE tag, or a false sync: look for the next frame
+            _ => {
+                offset += 1;
+                continue;
+            }
+        };
+        if offset + header.frame_len() > bytes.len() {
+            break;
+        }
+        // a lone sync before the first frame must be followed by another frame of the stream
+        if first.is_none() {
+            let next = offset + header.frame_len();
+            let followed = Header::parse(&bytes[next..]).is_some_and(|next| next.same_stream(&header));
+            if next + FRAME_HEADER_LEN <= bytes.len() && !followed {
+                offset += 1;
+                continue;
+            }
+            first = Some(header);
+        }
+        frames.push(parse_frame(bytes, offset, header, frames.is_empty()));
+        offset += header.frame_len();
+    }
+    if frames.is_empty() {
+        return Err(Error::UnsupportedSamples { format: "mp3", reason: "no mpeg layer iii frames found" });
+    }
+    Ok(frames)
+}
+
+fn parse_frame(bytes: &[u8], offset: usize, header: Header, first: bool) -> Frame {
+    let mut frame = Frame { offset, header, main_data_begin: 0, granules: Vec::new(), info: false };
+    let side_info = frame.side_info_offset();
+    let main_data_begin_bits = if header.version == Version::Mpeg1 { 9 } else { 8 };
+    frame.main_data_begin = read_bits(bytes, side_info * 8, main_data_begin_bits) as usize;
+
+    let mut bit = side_info * 8 + header.side_info_prefix_bits();
+    for _ in 0..header.granules() * header.channels {
+        frame.granules.push(Granule {
+            part2_3_length: read_bits(bytes, bit, 12) as usize,
+            big_values: read_bits(bytes, bit + 12, 9) as usize,
+            global_gain: read_bits(bytes, bit + GLOBAL_GAIN_OFFSET, GLOBAL_GAIN_BITS as usize) as u8,
+            global_gain_bit: bit + GLOBAL_GAIN_OFFSET,
+        });
+        bit += header.granule_bits();
+    }
+
+    // encoders write their info header in place of the main data of the first frame (Xing and
+    // Info headers right after the side info, VBRI headers 32 bytes after the frame header)
+    let main_data = frame.main_data_offset();
+    let encoder_info = matches!(bytes.get(main_data..main_data + 4), Some(b"Xing") | Some(b"Info"))
+        || bytes.get(offset + 36..offset + 40) == Some(b"VBRI");
+    frame.info = first && encoder_info;
+    frame
+}
+
+/// Length of the id3v2 tag at the start of `bytes` (0 if there is none).
+pub(crate) fn id3v2_len(bytes: &[u8]) -> usize {
+    if bytes.len() < ID3V2_HEADER_LEN || &bytes[..3] != b"ID3" {
+        return 0;
+    }
+    // syncsafe size (7 bits per byte), plus the header and the footer if flagged
+    let size = bytes[6..10].iter().fold(0, |size, byte| (size << 7) | (*byte & 0x7f) as usize);
+    let footer = if bytes[5] & 0x10 != 0 { ID3V2_HEADER_LEN } else { 0 };
+    (ID3V2_HEADER_LEN + size + footer).min(bytes.len())
+}
+
+/// Duration of the mp3 file `bytes` in seconds, from the samples of its audio frames.
+pub(crate) fn duration(bytes: &[u8]) -> Result<f64> {
+    let frames = frames(bytes)?;
+    Ok(frames
+        .iter()
+        .filter(|frame| !frame.info)
+        .map(|frame| frame.header.samples() as f64 / frame.header.sample_rate as f64)
+        .sum())
+}
+
+/// Global gains of every audio frame of an mp3 file, each hiding one bit in its LSB.
+pub(crate) struct GlobalGains {
+    /// Bit offset of each global_gain field, with the index of its frame.
+    gains: Vec<(usize, usize)>,
+    frames: Vec<Frame>,
+}
+
+/// Finds the global gains of the mp3 file `bytes` (see [`GlobalGains`]).
+pub(crate) fn global_gains(bytes: &[u8]) -> Result<GlobalGains> {
+    let frames = frames(bytes)?;
+    let gains = frames
+        .iter()
+        .enumerate()
+        .filter(|(_, frame)| !frame.info)
+        .flat_map(|(index, frame)| frame.granules.iter().map(move |granule| (granule.global_gain_bit, index)))
+        .collect();
+    Ok(GlobalGains { gains, frames })
+}
+
+impl Cover for GlobalGains {
+    fn carrier(&self) -> Carrier {
+        Carrier::Mp3GlobalGain
+    }
+
+    fn format(&self) -> &'static str {
+        "mp3"
+    }
+
+    fn count(&self) -> usize {
+        self.gains.len()
+    }
+
+    fn lsb(&self, bytes: &[u8], index: usize) -> u8 {
+        let (bit, _) = self.gains[index];
+        read_bits(bytes, bit + GLOBAL_GAIN_BITS as usize - 1, 1) as u8
+    }
+
+    fn change(&self, bytes: &mut [u8], index: usize, matching: bool) {
+        let (bit, frame) = self.gains[index];
+        let gain = read_bits(bytes, bit, GLOBAL_GAIN_BITS as usize);
+        let gain = match matching {
+            true => lsb::step(gain, GLOBAL_GAIN_BITS, false),
+            false => gain ^ 1,
+        };
+        write_bits(bytes, bit, GLOBAL_GAIN_BITS as usize, gain);
+        update_crc(bytes, &self.frames[frame]);
+    }
+}
+
+/// Finds the ancillary data of the mp3 file `bytes`: the ranges of the whole file (in file order)
+/// that belong to a frame's share of the main data, but to no frame's main data.
+pub(crate) fn ancillary_ranges(bytes: &[u8]) -> Result<Vec<Range<usize>>> {
+    let frames = frames(bytes)?;
+    let frames: Vec<&Frame> = frames.iter().filter(|frame| !frame.info).collect();
+
+    // the shares of all frames make up one main data stream, where each frame's main data starts
+    // main_data_begin bytes before its own share (the bit reservoir); the stream only runs through
+    // frames that follow each other, so after a gap (damaged data skipped by resynchronizing) no
+    // frame's main data starts before the gap, and the reservoir it points back to is left alone
+    // (whether decoders keep it across the gap or not)
+    let mut shares = Vec::with_capacity(frames.len());
+    let mut used = Vec::with_capacity(frames.len());
+    let mut stream_len: usize = 0;
+    let mut run_start: usize = 0;
+    let mut previous_end = None;
+    for frame in &frames {
+        if previous_end.is_some_and(|end| end != frame.offset) {
+            run_start = stream_len;
+        }
+        let share = frame.main_data_offset().min(frame.end_offset())..frame.end_offset();
+        let start = stream_len.saturating_sub(frame.main_data_begin);
+        used.push(start..start.max(run_start) + frame.main_data_len());
+        stream_len += share.len();
+        shares.push(share);
+        previous_end = Some(frame.end_offset());
+    }
+    used.sort_by_key(|range| range.start);
+
+    // free parts of the stream, between the main data of the frames
+    let mut free = Vec::new();
+    let mut position = 0;
+    for range in used {
+        if range.start > position && position < stream_len {
+            free.push(position..range.start.min(stream_len));
+        }
+        position = position.max(range.end);
+    }
+    if position < stream_len {
+        free.push(position..stream_len);
+    }
+
+    // back to offsets in the whole file, one range per frame share at most
+    let mut ranges = Vec::new();
+    let mut share_start = 0;
+    for share in shares {
+        let share_end = share_start + share.len();
+        for range in free.iter().filter(|range| range.start < share_end && range.end > share_start) {
+            let start = range.start.max(share_start) - share_start;
+            let end = range.end.min(share_end) - share_start;
+            ranges.push(share.start + start..share.start + end);
+        }
+        share_start = share_end;
+    }
+    Ok(ranges)
+}
+
+/// Computes how much fits in the ancillary data of the mp3 file `bytes`.
+pub(crate) fn ancillary_capacity(bytes: &[u8]) -> Result<Capacity> {
+    let free: usize = ancillary_ranges(bytes)?.iter().map(Range::len).sum();
+    Ok(Capacity {
+        carrier: Carrier::Mp3Ancillary,
+        positions: free,
+        bytes: free.saturating_sub(ANCILLARY_HEADER_LEN),
+    })
+}
+
+/// Reads the records stored by id3stego in the ancillary data of the mp3 file `bytes` (none if
+/// it has no mpeg frames).
+pub(crate) fn read_records(bytes: &[u8]) -> Result<Vec<Record>> {
+    let ranges = match ancillary_ranges(bytes) {
+        Ok(ranges) => ranges,
+        Err(Error::UnsupportedSamples { .. }) => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    match ancillary_payload(bytes, &ranges) {
+        Some(packed) => parse_records(&packed),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Returns a copy of the mp3 file `bytes` with `records` stored in its ancillary data, next to
+/// the records already stored there.
+pub(crate) fn add_records(bytes: &[u8], records: &[Record]) -> Result<Vec<u8>> {
+    let ranges = ancillary_ranges(bytes)?;
+    let mut packed = Vec::new();
+    for record in read_records(bytes)?.iter().chain(records) {
+        let record = record.to_native();
+        packed.extend_from_slice(&(record.len() as u32).to_be_bytes());
+        packed.extend_from_slice(&record);
+    }
+    let capacity = ancillary_capacity(bytes)?;
+    if packed.len() > capacity.bytes {
+        return Err(Error::CapacityExceeded { size: packed.len(), capacity: capacity.bytes });
+    }
+
+    let mut data = Vec::with_capacity(ANCILLARY_HEADER_LEN + packed.len());
+    data.extend_from_slice(ANCILLARY_MAGIC);
+    data.extend_from_slice(&(packed.len() as u32).to_be_bytes());
+    data.extend_from_slice(&packed);
+    let mut output = bytes.to_vec();
+    write_ancillary(&mut output, &ranges, &data);
+    Ok(output)
+}
+
+/// Returns a copy of the mp3 file `bytes` without the records stored in its ancillary data, and
+/// the removed records (`None` if they were damaged).
+pub(crate) fn remove_records(bytes: &[u8]) -> Result<(Vec<u8>, Vec<Option<Record>>)> {
+    let ranges = match ancillary_ranges(bytes) {
+        Ok(ranges) => ranges,
+        Err(Error::UnsupportedSamples { .. }) => return Ok((bytes.to_vec(), Vec::new())),
+        Err(err) => return Err(err),
+    };
+    let packed = match ancillary_payload(bytes, &ranges) {
+        Some(packed) => packed,
+        None => return Ok((bytes.to_vec(), Vec::new())),
+    };
+    let removed = match parse_records(&packed) {
+        Ok(records) => records.into_iter().map(Some).collect(),
+        Err(_) => vec![None],
+    };
+    let mut output = bytes.to_vec();
+    write_ancillary(&mut output, &ranges, &vec![0; ANCILLARY_HEADER_LEN + packed.len()]);
+    Ok((output, removed))
+}
+
+/// Reads the records (after the magic and size) from the ancillary data, if they start with the
+/// magic; the size is cut to the free bytes.
+fn ancillary_payload(bytes: &[u8], ranges: &[Range<usize>]) -> Option<Vec<u8>> {
+    let mut data = ranges.iter().flat_map(|range| bytes[range.clone()].iter().copied());
+    let header: Vec<u8> = data.by_ref().take(ANCILLARY_HEADER_LEN).collect();
+    if header.len() < ANCILLARY_HEADER_LEN || &header[..ANCILLARY_MAGIC.len()] != ANCILLARY_MAGIC {
+        return None;
+    }
+    let size = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    Some(data.take(size).collect())
+}
+
+fn parse_records(packed: &[u8]) -> Result<Vec<Record>> {
+    let mut records = Vec::new();
+    let mut offset = 0;
+    while offset < packed.len() {
+        if packed.len() - offset < RECORD_SIZE_LEN {
+            return Err(invalid("ancillary record size is truncated"));
+        }
+        let record_size = u32::from_be_bytes([packed[offset], packed[offset + 1], packed[offset + 2], packed[offset + 3]]) as usize;
+        let record_start = offset + RECORD_SIZE_LEN;
+        if packed.len() - record_start < record_size {
+            return Err(invalid("ancillary record is truncated"));
+        }
+        match Record::from_native(Carrier::Mp3Ancillary, &packed[record_start..record_start + record_size]) {
+            Some(record) => records.push(record),
+            None => return Err(invalid("ancillary record is missing key, filename, or mime-type")),
+        }
+        offset = record_start + record_size;
+    }
+    Ok(records)
+}
+
+/// Writes `data` to the start of the ancillary data, across as many ranges as it takes.
+fn write_ancillary(output: &mut [u8], ranges: &[Range<usize>], data: &[u8]) {
+    let mut data = data.iter();
+    for offset in ranges.iter().flat_map(Range::clone) {
+        match data.next() {
+            Some(byte) => output[offset] = *byte,
+            None => return,
+        }
+    }
+}
+
+fn invalid(reason: &'static str) -> Error {
+    Error::InvalidContainer { format: "mp3", reason }
+}
+
+/// Rewrites the CRC of a protected frame after its side info changed.
+pub(crate) fn update_crc(bytes: &mut [u8], frame: &Frame) {
+    if !frame.header.protected {
+        return;
+    }
+    let side_info = &bytes[frame.side_info_offset()..frame.main_data_offset()];
+    let crc = bytes[frame.offset + 2..frame.offset + FRAME_HEADER_LEN]
+        .iter()
+        .chain(side_info)
+        .fold(0xffff, |crc, byte| crc16(crc, *byte));
+    let crc_offset = frame.offset + FRAME_HEADER_LEN;
+    bytes[crc_offset..crc_offset + CRC_LEN].copy_from_slice(&crc.to_be_bytes());
+}
+
+fn crc16(mut crc: u16, byte: u8) -> u16 {
+    for shift in (0..8).rev() {
+        let carry = (crc >> 15) ^ ((byte >> shift) as u16 & 1);
+        crc <<= 1;
+        if carry == 1 {
+            crc ^= CRC_POLYNOMIAL;
+        }
+    }
+    crc
+}
+
+/// Reads `len` (at most 32) bits starting at bit `bit` of `bytes`, most significant bit first.
+fn read_bits(bytes: &[u8], bit: usize, len: usize) -> u32 {
+    (bit..bit + len).fold(0, |value, bit| (value << 1) | ((bytes[bit / 8] >> (7 - bit % 8)) & 1) as u32)
+}
+
+/// Writes the `len` low bits of `value` starting at bit `bit` of `bytes`, most significant bit first.
+fn write_bits(bytes: &mut [u8], bit: usize, len: usize, value: u32) {
+    for (position, bit) in (bit..bit + len).enumerate() {
+        let mask = 1 << (7 - bit % 8);
+        match (value >> (len - 1 - position)) & 1 {
+            1 => bytes[bit / 8] |= mask,
+            _ => bytes[bit / 8] &= !mask,
+        }
+    }
+}
@@ -0,0 +1,401 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use crate::carrier::{Carrier, Record, TextEncoding};
+use crate::error::{Error, Result};
+use crate::extract::is_id3stego_record;
+use crate::flac;
+
+// Ogg files are a sequence of pages, each holding segments of one logical stream's packets:
+//
+// | Field        | Size     | Value                                                        |
+// |--------------+----------+--------------------------------------------------------------|
+// | capture      | 4 bytes  | b"OggS"                                                      |
+// | version      | 1 byte   | 0                                                            |
+// | header type  | 1 byte   | 0x01 = continues a packet, 0x02 = first page, 0x04 = last    |
+// | granule      | 8 bytes  | position after the last packet ending on the page (or -1)    |
+// | serial       | 4 bytes  | logical stream the page belongs to                           |
+// | sequence     | 4 bytes  | page number within the logical stream                        |
+// | crc          | 4 bytes  | crc-32 (poly 0x04c11db7) of the page, with this field zeroed |
+// | segments     | 1 byte   | number of lacing values                                      |
+// | lacing       | n bytes  | segment sizes (a packet ends with the first value < 255)     |
+//
+// All integers are little endian. The first page of a Vorbis or Opus stream holds the
+// identification header, the next pages the comment header (Vorbis also has a setup header),
+// and audio starts on a fresh page. The comment header is the codec's magic bytes, the vendor
+// string, and a list of "NAME=value" comments (u32 length, then text).
+//
+// Embedded files are added as comments of their own, so the comment header pages are rebuilt
+// (with new crc) and later pages of the stream are renumbered; the audio packets are not touched:
+//  - ID3STEGO: the record packed as key \0 filename \0 mime-type \0 data (see carrier.rs), text
+//    encoded with base64 or base85 like TXXX and COMM frames
+//  - METADATA_BLOCK_PICTURE: a base64 encoded flac PICTURE block (see flac.rs)
+//
+// ID3STEGO comments always belong to id3stego; pictures only if they look like it (see
+// is_id3stego_record). Only the first logical stream is changed, other streams are copied.
+
+const CAPTURE_PATTERN: &[u8; 4] = b"OggS";
+const PAGE_HEADER_LEN: usize = 27;
+const MAX_SEGMENTS: usize = 255;
+const CRC_OFFSET: usize = 22;
+
+const CONTINUED_PACKET: u8 = 0x01;
+const BEGIN_OF_STREAM: u8 = 0x02;
+const NO_GRANULE: u64 = u64::MAX;
+
+const VORBIS_IDENTIFICATION: &[u8] = b"\x01vorbis";
+const VORBIS_COMMENT: &[u8] = b"\x03vorbis";
+const OPUS_IDENTIFICATION: &[u8] = b"OpusHead";
+const OPUS_TAGS: &[u8] = b"OpusTags";
+
+/// Comment field name of records written by id3stego.
+const COMMENT_FIELD: &str = "ID3STEGO";
+const PICTURE_FIELD: &str = "METADATA_BLOCK_PICTURE";
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+/// One page: its header fields needed here, lacing values, and body.
+struct Page<'a> {
+    serial: u32,
+    sequence: u32,
+    lacing: &'a [u8],
+    body: &'a [u8],
+    /// The whole page as found in the file.
+    bytes: &'a [u8],
+}
+
+/// Pages of an ogg file, with the header packets of its first logical stream.
+struct Stream<'a> {
+    pages: Vec<Page<'a>>,
+    /// Bytes after the last page (copied unchanged).
+    tail: &'a [u8],
+    serial: u32,
+    /// Indices of the pages holding the header packets after the identification header.
+    header_pages: Vec<usize>,
+    /// Header packets after the identification header (comment header first).
+    packets: Vec<Vec<u8>>,
+}
+
+/// Vorbis or Opus comment header.
+struct CommentHeader {
+    magic: &'static [u8],
+    vendor: Vec<u8>,
+    comments: Vec<Vec<u8>>,
+    /// Bytes after the comments (vorbis framing bit, or opus binary data), copied unchanged.
+    trailer: Vec<u8>,
+}
+
+/// Returns true if `bytes` starts with an ogg page.
+pub(crate) fn is_ogg(bytes: &[u8]) -> bool {
+    bytes.starts_with(CAPTURE_PATTERN)
+}
+
+/// Reads every record stored by id3stego in the ogg file `bytes`.
+pub(crate) fn read_records(bytes: &[u8]) -> Result<Vec<Record>> {
+    let stream = parse(bytes)?;
+    let header = CommentHeader::parse(&stream.packets[0])?;
+    let mut records = Vec::new();
+    for comment in &header.comments {
+        if let Some(record) = comment_record(comment)? {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+/// Returns a copy of the ogg file `bytes` with one new comment per record (ID3STEGO comments
+/// text encoded with `encoding`).
+pub(crate) fn add_records(bytes: &[u8], records: &[Record], encoding: TextEncoding) -> Result<Vec<u8>> {
+    let stream = parse(bytes)?;
+    let mut header = CommentHeader::parse(&stream.packets[0])?;
+    header.comments.extend(records.iter().map(|record| record_comment(record, encoding)));
+    write(&stream, &header)
+}
+
+/// Returns a copy of the ogg file `bytes` without the comments holding id3stego records, with
+/// the removed records (`None` for damaged ID3STEGO comments, which are removed too).
+pub(crate) fn remove_records(bytes: &[u8]) -> Result<(Vec<u8>, Vec<Option<Record>>)> {
+    let stream = parse(bytes)?;
+    let mut header = CommentHeader::parse(&stream.packets[0])?;
+    let mut removed = Vec::new();
+    header.comments.retain(|comment| match comment_record(comment) {
+        Ok(Some(record)) => {
+            removed.push(Some(record));
+            false
+        }
+        Err(_) => {
+            removed.push(None);
+            false
+        }
+        Ok(None) => true,
+    });
+    if removed.is_empty() {
+        return Ok((bytes.to_vec(), removed));
+    }
+    Ok((write(&stream, &header)?, removed))
+}
+
+fn invalid(reason: &'static str) -> Error {
+    Error::InvalidContainer { format: "ogg", reason }
+}
+
+fn parse(bytes: &[u8]) -> Result<Stream<'_>> {
+    if !is_ogg(bytes) {
+        return Err(invalid("missing OggS capture pattern"));
+    }
+    let mut pages = Vec::new();
+    let mut offset = 0;
+    while bytes[offset..].starts_with(CAPTURE_PATTERN) {
+        if bytes.len() < offset + PAGE_HEADER_LEN {
+            return Err(invalid("page header is truncated"));
+        }
+        let header = &bytes[offset..offset + PAGE_HEADER_LEN];
+        if header[4] != 0 {
+            return Err(invalid("unknown page version"));
+        }
+        let lacing_start = offset + PAGE_HEADER_LEN;
+        let body_start = lacing_start + header[26] as usize;
+        if bytes.len() < body_start {
+            return Err(invalid("page header is truncated"));
+        }
+        let lacing = &bytes[lacing_start..body_start];
+        let end = body_start + lacing.iter().map(|value| *value as usize).sum::<usize>();
+        if bytes.len() < end {
+            return Err(invalid("page is truncated"));
+        }
+        if pages.is_empty() && header[5] & BEGIN_OF_STREAM == 0 {
+            return Err(invalid("first page does not begin a stream"));
+        }
+        pages.push(Page {
+            serial: u32::from_le_bytes([header[14], header[15], header[16], header[17]]),
+            sequence: u32::from_le_bytes([header[18], header[19], header[20], header[21]]),
+            lacing,
+            body: &bytes[body_start..end],
+            bytes: &bytes[offset..end],
+        });
+        offset = end;
+    }
+
+    let first = &pages[0];
+    let header_count = if first.body.starts_with(VORBIS_IDENTIFICATION) {
+        // comment and setup headers
+        2
+    }
+    else if first.body.starts_with(OPUS_IDENTIFICATION) {
+        // comment header only
+        1
+    }
+    else {
+        return Err(invalid("first stream is not vorbis or opus"));
+    };
+
+    // reassemble the header packets following the identification header
+    let serial = first.serial;
+    let mut header_pages = Vec::new();
+    let mut packets = Vec::new();
+    let mut packet = Vec::new();
+    for (index, page) in pages.iter().enumerate().skip(1).filter(|(_, page)| page.serial == serial) {
+        if packets.len() == header_count {
+            break;
+        }
+        header_pages.push(index);
+        let mut body_offset = 0;
+        for value in page.lacing {
+            if packets.len() == header_count {
+                return Err(invalid("audio packet shares a page with the header packets"));
+            }
+            let len = *value as usize;
+            packet.extend_from_slice(&page.body[body_offset..body_offset + len]);
+            body_offset += len;
+            if len < 255 {
+                packets.push(std::mem::take(&mut packet));
+            }
+        }
+    }
+    if packets.len() < header_count {
+        return Err(invalid("header packets are truncated"));
+    }
+    Ok(Stream { pages, tail: &bytes[offset..], serial, header_pages, packets })
+}
+
+fn write(stream: &Stream, header: &CommentHeader) -> Result<Vec<u8>> {
+    // header pages are replaced by new ones holding the new comment header and the other header
+    // packets; later pages of the stream are renumbered if the number of header pages changed
+    let mut packets = Vec::with_capacity(stream.packets.len());
+    packets.push(header.to_packet()?);
+    packets.extend(stream.packets[1..].iter().cloned());
+    let new_pages = paginate(stream.serial, &packets);
+    let sequence_shift = (new_pages.len() as u32).wrapping_sub(stream.header_pages.len() as u32);
+    let first_header_page = stream.header_pages[0];
+    let last_header_page = stream.header_pages[stream.header_pages.len() - 1];
+
+    let new_len: usize = new_pages.iter().map(Vec::len).sum();
+    let mut output = Vec::with_capacity(stream.pages.iter().map(|page| page.bytes.len()).sum::<usize>() + new_len);
+    for (index, page) in stream.pages.iter().enumerate() {
+        if index == first_header_page {
+            for new_page in &new_pages {
+                output.extend_from_slice(new_page);
+            }
+        }
+        if stream.header_pages.contains(&index) {
+            continue;
+        }
+        if page.serial == stream.serial && index > last_header_page && sequence_shift != 0 {
+            let mut renumbered = page.bytes.to_vec();
+            renumbered[18..22].copy_from_slice(&page.sequence.wrapping_add(sequence_shift).to_le_bytes());
+            set_crc(&mut renumbered);
+            output.extend_from_slice(&renumbered);
+        }
+        else {
+            output.extend_from_slice(page.bytes);
+        }
+    }
+    output.extend_from_slice(stream.tail);
+    Ok(output)
+}
+
+fn paginate(serial: u32, packets: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    // lays out packets in pages of up to 255 segments, starting at page sequence number 1
+    // (right after the identification header page)
+    let mut lacing = Vec::new();
+    for packet in packets {
+        lacing.extend(std::iter::repeat_n(255, packet.len() / 255));
+        lacing.push((packet.len() % 255) as u8);
+    }
+    let body = packets.concat();
+
+    let mut pages = Vec::new();
+    let mut body_offset = 0;
+    let mut continued = false;
+    for (index, page_lacing) in lacing.chunks(MAX_SEGMENTS).enumerate() {
+        let body_len: usize = page_lacing.iter().map(|value| *value as usize).sum();
+        // header packets have granule position 0, pages where no packet ends have none
+        let granule = if page_lacing.iter().any(|value| *value < 255) { 0 } else { NO_GRANULE };
+        let mut page = Vec::with_capacity(PAGE_HEADER_LEN + page_lacing.len() + body_len);
+        page.extend_from_slice(CAPTURE_PATTERN);
+        page.push(0);
+        page.push(if continued { CONTINUED_PACKET } else { 0 });
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&serial.to_le_bytes());
+        page.extend_from_slice(&(index as u32 + 1).to_le_bytes());
+        page.extend_from_slice(&[0; 4]);
+        page.push(page_lacing.len() as u8);
+        page.extend_from_slice(page_lacing);
+        page.extend_from_slice(&body[body_offset..body_offset + body_len]);
+        set_crc(&mut page);
+        pages.push(page);
+        body_offset += body_len;
+        continued = page_lacing[page_lacing.len() - 1] == 255;
+    }
+    pages
+}
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut remainder = (index as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            remainder = if remainder & 0x8000_0000 != 0 { (remainder << 1) ^ 0x04c1_1db7 } else { remainder << 1 };
+            bit += 1;
+        }
+        table[index] = remainder;
+        index += 1;
+    }
+    table
+}
+
+fn set_crc(page: &mut [u8]) {
+    // crc is computed over the whole page with the crc field zeroed
+    page[CRC_OFFSET..CRC_OFFSET + 4].fill(0);
+    let crc = page
+        .iter()
+        .fold(0u32, |crc, byte| (crc << 8) ^ CRC_TABLE[((crc >> 24) as u8 ^ byte) as usize]);
+    page[CRC_OFFSET..CRC_OFFSET + 4].copy_from_slice(&crc.to_le_bytes());
+}
+
+fn comment_record(comment: &[u8]) -> Result<Option<Record>> {
+    // success: return the record held by an id3stego comment, or none for any other comment
+    // failure: an ID3STEGO comment that cannot be read
+    let separator = match comment.iter().position(|byte| *byte == b'=') {
+        Some(separator) => separator,
+        None => return Ok(None),
+    };
+    let (name, value) = (&comment[..separator], &comment[separator + 1..]);
+    if name.eq_ignore_ascii_case(COMMENT_FIELD.as_bytes()) {
+        let packed = std::str::from_utf8(value).ok().and_then(TextEncoding::decode);
+        match packed.and_then(|packed| Record::from_native(Carrier::OggComment, &packed)) {
+            Some(record) => Ok(Some(record)),
+            None => Err(invalid("ID3STEGO comment is not a base64 or base85 encoded record")),
+        }
+    }
+    else if name.eq_ignore_ascii_case(PICTURE_FIELD.as_bytes()) {
+        let picture = BASE64.decode(value).ok();
+        Ok(picture.and_then(|picture| flac::parse_picture(&picture, Carrier::OggPicture)).filter(is_id3stego_record))
+    }
+    else {
+        Ok(None)
+    }
+}
+
+fn record_comment(record: &Record, encoding: TextEncoding) -> Vec<u8> {
+    match record.carrier {
+        Carrier::OggPicture => format!("{}={}", PICTURE_FIELD, BASE64.encode(flac::picture_data(record))),
+        _ => format!("{}={}", COMMENT_FIELD, encoding.encode(&record.to_native())),
+    }
+    .into_bytes()
+}
+
+impl CommentHeader {
+    fn parse(packet: &[u8]) -> Result<CommentHeader> {
+        let magic = if packet.starts_with(VORBIS_COMMENT) {
+            VORBIS_COMMENT
+        }
+        else if packet.starts_with(OPUS_TAGS) {
+            OPUS_TAGS
+        }
+        else {
+            return Err(invalid("comment header is missing"));
+        };
+        let truncated = || invalid("comment header is truncated");
+        let mut offset = magic.len();
+        let vendor = read_field(packet, &mut offset).ok_or_else(truncated)?.to_vec();
+        let count = read_u32(packet, &mut offset).ok_or_else(truncated)?;
+        let mut comments = Vec::new();
+        for _ in 0..count {
+            comments.push(read_field(packet, &mut offset).ok_or_else(truncated)?.to_vec());
+        }
+        Ok(CommentHeader { magic, vendor, comments, trailer: packet[offset..].to_vec() })
+    }
+
+    fn to_packet(&self) -> Result<Vec<u8>> {
+        let too_large = |size| Error::PayloadTooLarge { size, max: u32::MAX as usize };
+        let comments_len: usize = self.comments.iter().map(|comment| 4 + comment.len()).sum();
+        let mut packet = Vec::with_capacity(self.magic.len() + 8 + self.vendor.len() + comments_len + self.trailer.len());
+        packet.extend_from_slice(self.magic);
+        packet.extend_from_slice(&(self.vendor.len() as u32).to_le_bytes());
+        packet.extend_from_slice(&self.vendor);
+        packet.extend_from_slice(&(self.comments.len() as u32).to_le_bytes());
+        for comment in &self.comments {
+            let len = u32::try_from(comment.len()).map_err(|_| too_large(comment.len()))?;
+            packet.extend_from_slice(&len.to_le_bytes());
+            packet.extend_from_slice(comment);
+        }
+        packet.extend_from_slice(&self.trailer);
+        Ok(packet)
+    }
+}
+
+fn read_u32(data: &[u8], offset: &mut usize) -> Option<u32> {
+    let value = u32::from_le_bytes(data.get(*offset..*offset + 4)?.try_into().ok()?);
+    *offset += 4;
+    Some(value)
+}
+
+fn read_field<'a>(data: &'a [u8], offset: &mut usize) -> Option<&'a [u8]> {
+    // reads one field (little endian u32 length, then data) at offset, moving offset past it
+    let len = read_u32(data, offset)? as usize;
+    let field = data.get(*offset..offset.checked_add(len)?)?;
+    *offset += len;
+    Some(field)
+}
@@ -0,0 +1,192 @@
+use std::path::{Path, PathBuf};
+use rand::{thread_rng, Rng};
+use rand::distributions::Alphanumeric;
+
+pub fn add_filename_prefix_preserve_path(file_path_str: &str, file_name_str: &str, prefix: &str) -> String {
+    // constructs output path and filename in form of file_path_str\prefix-file_name_str
+    //
+    // ex as used in put mode:
+    //  file_path_str = "c:\id3stego\test.mp3", file_name_str = "test.mp3", prefix = "output"
+    //      -> "c:\id3stego\test.mp3" -> "c:\id3stego\prefix-test.mp3"
+    //
+    // ex as used in get mode:
+    //  file_path_str = "c:\id3stego\test.mp3", file_name_str = "test.jpg", prefix = "extracted"
+    //      -> "c:\id3stego\extracted-test.jpg"
+
+    // extract filename from full file path
+    let filename_only = get_filename_drop_path(Path::new(file_name_str), prefix);
+
+    // add prefix to filename
+    let mut prefixed_filename = prefix.to_string();
+    prefixed_filename.push_str(&filename_only);
+
+    // reconstruct full file path by combining path contained in file_path_str with filename_only
+    let file_path = Path::new(file_path_str);
+    let new_file_path = file_path.with_file_name(&prefixed_filename);
+    match new_file_path.to_str() {
+        Some(new_file_path_str) => {
+            new_file_path_str.to_string()
+        }
+        None => {
+            // If unable to reconstruct new path, use filename_only
+            filename_only
+        }
+    }
+}
+
+pub fn get_filename_drop_path(file_path: &Path, prefix: &str) -> String {
+    // extracts filename from path string
+    // ex: "c:\id3stego\test.jpg" --> "test.jpg"
+
+    match file_path.file_name() {
+        Some(filename_os_str) => {
+            match filename_os_str.to_str() {
+                Some(filename_str) => {
+                    filename_str.to_string()
+                }
+                None => {
+                    // If unable to extract filename from full path, use default filename string
+                    generate_random_filename_with_prefix(prefix)
+                }
+            }
+        }
+        None => {
+            // If unable to extract filename from full path, use default filename string
+            generate_random_filename_with_prefix(prefix)
+        }
+    }
+}
+
+pub fn generate_random_filename_with_prefix(prefix: &str) -> String {
+    // generate default output filename (prefix + 5 random chars)
+    // used if None encountered when extracting filename from full path (unlikely)
+    // or if None encountered when reconstructing full file path with new filename (also unlikely)
+
+    let mut filename = prefix.to_string();
+    filename.push_str(&random_alphanumeric(5));
+
+    // return prefix + 5 random chars
+    filename
+}
+
+pub(crate) fn random_alphanumeric(len: usize) -> String {
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+/// What to do when an output file (or directory) already exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Leave the existing file alone and do not write the output.
+    Skip,
+    /// Replace the existing file.
+    Overwrite,
+    /// Write the output under a new name with a counter (ex: test-1.jpg, test-2.jpg).
+    Rename,
+}
+
+pub fn split_stem_ext(filename: &str) -> (String, String) {
+    // splits filename into stem and extension (with leading '.', or empty if none)
+    // ex: "test.jpg" -> ("test", ".jpg"), "archive.tar.gz" -> ("archive.tar", ".gz"), ".profile" -> (".profile", "")
+
+    let path = Path::new(filename);
+    let stem = match path.file_stem() {
+        Some(stem) => stem.to_string_lossy().to_string(),
+        None => filename.to_string(),
+    };
+    let ext = match path.extension() {
+        Some(ext) => format!(".{}", ext.to_string_lossy()),
+        None => String::new(),
+    };
+    (stem, ext)
+}
+
+pub fn expand_name_template(template: &str, values: &[(&str, &str)]) -> Result<String, String> {
+    // replaces each {placeholder} in template with its value
+    // ex: template = "{stem}-{index}{ext}", values = [("stem", "test"), ("ext", ".jpg"), ("index", "2")] -> "test-2.jpg"
+    //
+    // success: return expanded name
+    // failure: return placeholder that is unknown or has no value here (or "{" if a brace is not closed)
+
+    let mut expanded = String::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        expanded.push_str(&rest[..open]);
+        let close = match rest[open..].find('}') {
+            Some(close) => open + close,
+            None => return Err("{".to_string()),
+        };
+        let placeholder = &rest[open + 1..close];
+        match values.iter().find(|(name, _)| *name == placeholder) {
+            Some((_, value)) => expanded.push_str(value),
+            None => return Err(placeholder.to_string()),
+        }
+        rest = &rest[close + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+pub fn resolve_conflict(path: &Path, policy: ConflictPolicy) -> Option<PathBuf> {
+    // returns path to write output to, or none if output should be skipped
+    // ex with rename policy: "c:\id3stego\test.jpg" exists -> "c:\id3stego\test-1.jpg" (or -2, -3, ...)
+
+    if !path.exists() && !path.is_symlink() {
+        return Some(path.to_path_buf());
+    }
+    match policy {
+        ConflictPolicy::Skip => None,
+        ConflictPolicy::Overwrite => Some(path.to_path_buf()),
+        ConflictPolicy::Rename => {
+            let filename = get_filename_drop_path(path, "");
+            let (stem, ext) = split_stem_ext(&filename);
+            (1..).map(|counter| path.with_file_name(format!("{}-{}{}", stem, counter, ext)))
+                .find(|renamed| !renamed.exists() && !renamed.is_symlink())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_name_templates() {
+        let values = [("stem", "test"), ("ext", ".jpg"), ("index", "2")];
+        assert_eq!(expand_name_template("{stem}-{index}{ext}", &values), Ok("test-2.jpg".to_string()));
+        assert_eq!(expand_name_template("plain", &values), Ok("plain".to_string()));
+        assert_eq!(expand_name_template("{key}{ext}", &values), Err("key".to_string()));
+        assert_eq!(expand_name_template("{stem", &values), Err("{".to_string()));
+    }
+
+    #[test]
+    fn splits_stem_and_extension() {
+        assert_eq!(split_stem_ext("test.jpg"), ("test".to_string(), ".jpg".to_string()));
+        assert_eq!(split_stem_ext("archive.tar.gz"), ("archive.tar".to_string(), ".gz".to_string()));
+        assert_eq!(split_stem_ext(".profile"), (".profile".to_string(), String::new()));
+    }
+
+    #[test]
+    fn adds_filename_prefix() {
+        let output = add_filename_prefix_preserve_path("dir/test.mp3", "other/test.jpg", "extracted-");
+        assert_eq!(output, Path::new("dir").join("extracted-test.jpg").to_string_lossy());
+    }
+
+    #[test]
+    fn resolves_conflicts() {
+        let dir = std::env::temp_dir().join(format!("id3stego-paths-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.jpg");
+        assert_eq!(resolve_conflict(&path, ConflictPolicy::Skip), Some(path.clone()));
+
+        std::fs::write(&path, b"existing").unwrap();
+        std::fs::write(dir.join("test-1.jpg"), b"existing").unwrap();
+        assert_eq!(resolve_conflict(&path, ConflictPolicy::Skip), None);
+        assert_eq!(resolve_conflict(&path, ConflictPolicy::Overwrite), Some(path.clone()));
+        assert_eq!(resolve_conflict(&path, ConflictPolicy::Rename), Some(dir.join("test-2.jpg")));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,177 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use crate::paths::random_alphanumeric;
+
+// Output files are never written in place: they are written to a temp file in the same
+// directory (so the rename cannot cross file systems), synced to disk, then renamed over the
+// output path. A crash leaves either the old file or the new one, never half of it. Temp files
+// still being written are remembered, so a signal handler can remove them (remove_temp_files).
+
+const TEMP_SUFFIX: &str = ".id3stego-tmp";
+
+static PENDING: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// A readable, writable, seekable buffer that can also be truncated.
+///
+/// Embedding rewrites the whole audio stream, which may shrink it (e.g. when an existing tag
+/// carried padding), so plain `Read + Write + Seek` is not quite enough. Implemented for files
+/// and in-memory cursors, mirroring the id3 crate's own storage trait.
+pub trait Storage: io::Read + io::Write + io::Seek {
+    fn set_len(&mut self, new_len: u64) -> io::Result<()>;
+}
+
+impl<T> Storage for &mut T
+where
+    T: Storage,
+{
+    fn set_len(&mut self, new_len: u64) -> io::Result<()> {
+        (*self).set_len(new_len)
+    }
+}
+
+impl Storage for fs::File {
+    fn set_len(&mut self, new_len: u64) -> io::Result<()> {
+        fs::File::set_len(self, new_len)
+    }
+}
+
+impl Storage for io::Cursor<Vec<u8>> {
+    fn set_len(&mut self, new_len: u64) -> io::Result<()> {
+        self.get_mut().resize(new_len as usize, 0);
+        Ok(())
+    }
+}
+
+/// Reads the full contents of `storage`, starting from the beginning.
+pub(crate) fn read_all<R: io::Read + io::Seek>(storage: &mut R) -> io::Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    storage.seek(io::SeekFrom::Start(0))?;
+    storage.read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// Replaces the full contents of `storage` with `bytes`.
+pub(crate) fn replace_all<F: Storage>(storage: &mut F, bytes: &[u8]) -> io::Result<()> {
+    storage.seek(io::SeekFrom::Start(0))?;
+    storage.write_all(bytes)?;
+    storage.set_len(bytes.len() as u64)?;
+    storage.flush()
+}
+
+/// Writes `bytes` to `path` atomically: into a temp file next to it, synced, then renamed over it.
+///
+/// If `path` already exists, its permissions are kept. On failure the temp file is removed and
+/// `path` is left untouched.
+pub fn write_atomic(path: impl AsRef<Path>, bytes: &[u8]) -> io::Result<()> {
+    let path = path.as_ref();
+    let temp_path = temp_path_for(path);
+    let result = write_temp_file(&temp_path, path, bytes).and_then(|_| fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    forget_temp_path(&temp_path);
+    result?;
+    sync_parent_dir(path);
+    Ok(())
+}
+
+fn write_temp_file(temp_path: &Path, path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut file = fs::File::options().write(true).create_new(true).open(temp_path)?;
+    if let Ok(metadata) = fs::metadata(path) {
+        file.set_permissions(metadata.permissions())?;
+    }
+    io::Write::write_all(&mut file, bytes)?;
+    file.sync_all()
+}
+
+/// Removes every temp file (or directory) still being written by this process.
+///
+/// Meant for signal handlers (e.g. Ctrl-C), so that an interrupted run leaves no partial
+/// output behind. Output paths themselves are never touched.
+pub fn remove_temp_files() {
+    let pending = match PENDING.lock() {
+        Ok(mut pending) => std::mem::take(&mut *pending),
+        Err(_) => return,
+    };
+    for temp_path in pending {
+        if temp_path.is_dir() {
+            let _ = fs::remove_dir_all(&temp_path);
+        }
+        else {
+            let _ = fs::remove_file(&temp_path);
+        }
+    }
+}
+
+/// Returns an unused temp path next to `path` (ex: notes.txt -> .notes.txt.AbCdE.id3stego-tmp),
+/// remembered until [`forget_temp_path`] is called.
+pub(crate) fn temp_path_for(path: &Path) -> PathBuf {
+    let filename = path.file_name().map(|filename| filename.to_string_lossy().to_string()).unwrap_or_default();
+    let temp_path = path.with_file_name(format!(".{}.{}{}", filename, random_alphanumeric(5), TEMP_SUFFIX));
+    if let Ok(mut pending) = PENDING.lock() {
+        pending.push(temp_path.clone());
+    }
+    temp_path
+}
+
+/// Stops remembering `temp_path` (it was renamed into place or removed).
+pub(crate) fn forget_temp_path(temp_path: &Path) {
+    if let Ok(mut pending) = PENDING.lock() {
+        pending.retain(|pending_path| pending_path != temp_path);
+    }
+}
+
+/// Syncs the directory holding `path`, so a rename into it survives a crash (best effort, unix only).
+pub(crate) fn sync_parent_dir(path: &Path) {
+    #[cfg(unix)]
+    {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        if let Ok(dir) = fs::File::open(parent) {
+            let _ = dir.sync_all();
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_and_truncates_buffers() {
+        let mut buffer = io::Cursor::new(b"a longer old file".to_vec());
+        replace_all(&mut buffer, b"new file").unwrap();
+        assert_eq!(read_all(&mut buffer).unwrap(), b"new file");
+    }
+
+    #[test]
+    fn writes_atomically() {
+        let dir = std::env::temp_dir().join(format!("id3stego-storage-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("output.mp3");
+        write_atomic(&path, b"first").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        }
+
+        write_atomic(&path, b"second").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        // only the output file is left, and no temp path is still pending
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        assert!(!PENDING.lock().unwrap().iter().any(|pending| pending.starts_with(&dir)));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,140 @@
+use std::path::Path;
+use id3::{Tag, TagLike};
+use crate::carrier::{self, Record};
+use crate::chunk::{self, ChunkHeader};
+use crate::container::{Format, TagVersion};
+use crate::error::Result;
+use crate::extract::is_id3stego_frame;
+use crate::storage::{self, Storage};
+
+/// Describes the embedded files removed by a [`Stripper`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Stripped {
+    /// Frame description keys of the removed files (chunks of one file share a key).
+    pub keys: Vec<String>,
+    /// Number of frames removed.
+    pub frames: usize,
+    /// Whether the whole id3v2 tag was removed (nothing but id3stego frames was left in it).
+    pub tag_removed: bool,
+}
+
+/// Removes files previously embedded by id3stego from mp3, wav, aiff, flac, ogg, and m4a files.
+#[derive(Debug, Clone)]
+pub struct Stripper {
+    remove_empty_tag: bool,
+}
+
+impl Default for Stripper {
+    fn default() -> Stripper {
+        Stripper { remove_empty_tag: true }
+    }
+}
+
+impl Stripper {
+    pub fn new() -> Stripper {
+        Stripper::default()
+    }
+
+    /// Sets whether a tag left without any frames is removed entirely (true by default), or
+    /// kept as an empty tag.
+    pub fn remove_empty_tag(mut self, remove_empty_tag: bool) -> Stripper {
+        self.remove_empty_tag = remove_empty_tag;
+        self
+    }
+
+    /// Removes every embedded file from the audio file at `audio_path`, writing the result to
+    /// `output_path` (which may be `audio_path` itself, to strip it in place).
+    ///
+    /// Nothing is written if no embedded file was found.
+    pub fn strip_path(&self, audio_path: impl AsRef<Path>, output_path: impl AsRef<Path>) -> Result<Stripped> {
+        let audio = std::fs::read(&audio_path)?;
+        let (output, stripped) = match self.strip_bytes(&audio)? {
+            Some(result) => result,
+            None => return Ok(Stripped::default()),
+        };
+        storage::write_atomic(output_path, &output)?;
+        Ok(stripped)
+    }
+
+    /// Removes every embedded file from the audio file held by `audio`.
+    ///
+    /// The whole stream is read from the start and, if anything was found, rewritten in place.
+    pub fn strip<F: Storage>(&self, mut audio: F) -> Result<Stripped> {
+        let bytes = storage::read_all(&mut audio)?;
+        match self.strip_bytes(&bytes)? {
+            Some((output, stripped)) => {
+                storage::replace_all(&mut audio, &output)?;
+                Ok(stripped)
+            }
+            None => Ok(Stripped::default()),
+        }
+    }
+
+    fn strip_bytes(&self, audio: &[u8]) -> Result<Option<(Vec<u8>, Stripped)>> {
+        let format = Format::detect(audio)?;
+        let mut stripped = Stripped::default();
+
+        // native carriers (outside the id3v2 tag) first
+        let (without_native, removed) = format.remove_native_records(audio)?;
+        for record in removed {
+            // damaged blocks are removed too
+            let key = record.map(|record| record_key(&record)).unwrap_or_default();
+            stripped.add(key);
+        }
+
+        let tag = match format.read_tag(&without_native)? {
+            Some(tag) => tag,
+            None if stripped.frames == 0 => return Ok(None),
+            None => return Ok(Some((without_native, stripped))),
+        };
+        let native_frames = stripped.frames;
+        for frame in tag.frames().filter(|frame| is_id3stego_frame(frame)) {
+            let key = match Record::from_frame(frame) {
+                Some(Ok(record)) => record_key(&record),
+                // damaged frames are removed too
+                _ => carrier::frame_key(frame).unwrap_or_default().to_string(),
+            };
+            stripped.add(key);
+        }
+        if stripped.frames == native_frames {
+            return match native_frames {
+                0 => Ok(None),
+                _ => Ok(Some((without_native, stripped))),
+            };
+        }
+
+        // rebuild the tag from every other frame, keeping its version
+        let version = TagVersion::of(Some(&tag));
+        let mut kept = Tag::with_version(version.id3_version());
+        for frame in tag.frames().filter(|frame| !is_id3stego_frame(frame)) {
+            kept.add_frame(frame.clone());
+        }
+
+        let output = if self.remove_empty_tag && kept.frames().next().is_none() {
+            stripped.tag_removed = true;
+            format.remove_tag(&without_native)?
+        }
+        else {
+            format.write_tag(&without_native, &kept, version)?
+        };
+        Ok(Some((output, stripped)))
+    }
+}
+
+impl Stripped {
+    fn add(&mut self, key: String) {
+        // chunks of one file share a key
+        if !self.keys.contains(&key) {
+            self.keys.push(key);
+        }
+        self.frames += 1;
+    }
+}
+
+fn record_key(record: &Record) -> String {
+    // key of the embedded file a record belongs to (without the chunk number of chunked files)
+    match ChunkHeader::parse(&record.data) {
+        Some((header, _)) => chunk::key_from_description(&record.key, &header).to_string(),
+        None => record.key.clone(),
+    }
+}
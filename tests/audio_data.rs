@@ -0,0 +1,103 @@
+mod common;
+
+use std::io::Cursor;
+use id3stego::{Carrier, Embedder, Error, Extractor, MAX_MATRIX_K};
+use common::{get, get_all, mp3, put, round_trip, strip, MP3_FRAME_LEN};
+
+const NOTES: &[u8] = b"hidden in the audio data";
+
+/// Embeds `NOTES` with `embedder` (hiding it in the audio data with the stego key "correct
+/// horse"), checks that it is only found with the same stego key, and that stripping leaves the
+/// audio data alone. Returns the audio file with the embedded file.
+fn hidden_round_trip(embedder: Embedder, audio: &[u8]) -> Vec<u8> {
+    let (output, embedded) = put(&embedder.stego_key("correct horse"), audio, &[("notes.txt", NOTES)]);
+    assert_eq!(output.len(), audio.len());
+    assert!(embedded[0].embedding.unwrap().changes > 0);
+
+    let extracted = get_all(&Extractor::new().stego_key("correct horse"), &output);
+    assert_eq!(extracted.len(), 1);
+    assert_eq!(extracted[0].data, NOTES);
+    assert_eq!(extracted[0].carrier, embedded[0].carrier);
+    assert!(get(&Extractor::new().stego_key("wrong horse"), &output).is_empty());
+    assert!(get(&Extractor::new(), &output).is_empty());
+
+    // the original LSBs are lost, so there is nothing to strip
+    let (stripped_audio, stripped) = strip(&output);
+    assert!(stripped.keys.is_empty());
+    assert_eq!(stripped_audio, output);
+    output
+}
+
+fn changed_bytes(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).filter(|(a, b)| a != b).count()
+}
+
+#[test]
+fn pcm_lsb_round_trip() {
+    for audio in [common::wav(4000), common::aiff(4000)] {
+        let output = hidden_round_trip(Embedder::new().carrier(Carrier::PcmLsb), &audio);
+        // LSB replacement only flips the lowest bit of some samples
+        assert!(audio.iter().zip(&output).all(|(a, b)| (a ^ b) & !1 == 0));
+    }
+}
+
+#[test]
+fn pcm_lsb_matching_and_matrix_embedding_round_trip() {
+    let audio = common::wav(20_000);
+    let plain = hidden_round_trip(Embedder::new().carrier(Carrier::PcmLsb), &audio);
+    let matched = hidden_round_trip(Embedder::new().carrier(Carrier::PcmLsb).lsb_matching(true), &audio);
+    let matrix = hidden_round_trip(Embedder::new().carrier(Carrier::PcmLsb).matrix_embedding(3), &audio);
+    assert_ne!(plain, matched);
+    // matrix embedding changes fewer samples for the same file
+    assert!(changed_bytes(&audio, &matrix) < changed_bytes(&audio, &plain));
+}
+
+#[test]
+fn invalid_matrix_code_is_refused() {
+    let embedder = Embedder::new().carrier(Carrier::PcmLsb).stego_key("correct horse");
+    let embedder = embedder.matrix_embedding(MAX_MATRIX_K + 1);
+    let err = embedder.embed(Cursor::new(common::wav(4000)), "notes.txt", NOTES.to_vec()).unwrap_err();
+    assert!(matches!(err, Error::InvalidMatrixCode { .. }));
+}
+
+#[test]
+fn stego_key_is_required() {
+    let err = Embedder::new().carrier(Carrier::PcmLsb).embed(Cursor::new(common::wav(4000)), "a.txt", NOTES.to_vec());
+    assert!(matches!(err, Err(Error::StegoKeyRequired)));
+}
+
+#[test]
+fn capacity_is_enforced() {
+    let embedder = Embedder::new().carrier(Carrier::PcmLsb).stego_key("correct horse");
+    let capacity = embedder.capacity(Cursor::new(common::wav(4000))).unwrap();
+    assert_eq!(capacity.positions, 4000);
+    let data = vec![0; capacity.bytes];
+    let err = embedder.embed(Cursor::new(common::wav(4000)), "large.bin", data).unwrap_err();
+    assert!(matches!(err, Error::CapacityExceeded { .. }));
+}
+
+#[test]
+fn mp3_global_gain_round_trip() {
+    let audio = mp3(1000);
+    let output = hidden_round_trip(Embedder::new().carrier(Carrier::Mp3GlobalGain), &audio);
+    // every frame keeps its length and its main data, only side info changed
+    for (frame, (a, b)) in audio.chunks(MP3_FRAME_LEN).zip(output.chunks(MP3_FRAME_LEN)).enumerate() {
+        assert_eq!(a[..4], b[..4], "frame {}", frame);
+        assert_eq!(a[21..], b[21..], "frame {}", frame);
+    }
+}
+
+#[test]
+fn mp3_ancillary_round_trip() {
+    let audio = mp3(20);
+    let embedder = Embedder::new().carrier(Carrier::Mp3Ancillary);
+    let capacity = embedder.capacity(Cursor::new(audio.clone())).unwrap();
+    assert_eq!(capacity.positions, 20 * 196);
+
+    let output = round_trip(&embedder, &audio, "notes.txt", NOTES);
+    assert_eq!(output.len(), audio.len());
+    // the main data of every frame is left alone
+    for (a, b) in audio.chunks(MP3_FRAME_LEN).zip(output.chunks(MP3_FRAME_LEN)) {
+        assert_eq!(a[..221], b[..221]);
+    }
+}
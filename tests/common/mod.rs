@@ -0,0 +1,334 @@
+// In-memory audio files for the integration tests: just enough of each container for id3stego to
+// recognize and rewrite it, with deterministic contents so that stripping can be compared byte
+// for byte with the original.
+#![allow(dead_code)]
+
+use std::io::Cursor;
+use id3stego::{Embedded, Embedder, Extracted, Extractor, Listed, Stripped, Stripper};
+
+/// Bytes of a frame of the mp3 fixtures (MPEG-1 layer III, 128 kbit/s, 44.1 kHz, no padding).
+pub const MP3_FRAME_LEN: usize = 417;
+const MP3_SIDE_INFO_LEN: usize = 17;
+/// Bits of huffman data in each granule of the mp3 fixtures (the rest of a frame is ancillary).
+const MP3_PART2_3_LENGTH: u32 = 800;
+const MP3_GLOBAL_GAIN: u32 = 150;
+
+/// Deterministic pseudo-random bytes (a linear congruential generator), so fixtures are the same
+/// on every run.
+pub fn noise(len: usize, seed: u32) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (state >> 16) as u8
+        })
+        .collect()
+}
+
+/// An mp3 file of `frames` mono MPEG-1 layer III frames, without any tag. Each frame uses 200
+/// bytes of main data and leaves the rest of its share (196 bytes) as zeroed ancillary data.
+pub fn mp3(frames: usize) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(frames * MP3_FRAME_LEN);
+    let main_data_len = (2 * MP3_PART2_3_LENGTH / 8) as usize;
+    for frame in 0..frames {
+        // sync, MPEG-1, layer III, no crc / 128 kbit/s, 44.1 kHz / mono, original
+        bytes.extend_from_slice(&[0xff, 0xfb, 0x90, 0xc4]);
+        bytes.extend_from_slice(&mp3_side_info());
+        bytes.extend_from_slice(&noise(main_data_len, frame as u32));
+        bytes.resize(bytes.len() + MP3_FRAME_LEN - 4 - MP3_SIDE_INFO_LEN - main_data_len, 0);
+    }
+    bytes
+}
+
+fn mp3_side_info() -> Vec<u8> {
+    // main_data_begin 0, private bits, scfsi, then two granules of one channel
+    let mut bits = BitWriter::default();
+    bits.write(0, 9);
+    bits.write(0, 5);
+    bits.write(0, 4);
+    for _ in 0..2 {
+        bits.write(MP3_PART2_3_LENGTH, 12);
+        bits.write(100, 9);
+        bits.write(MP3_GLOBAL_GAIN, 8);
+        // scalefac_compress, window switching, table select, region counts, flags
+        bits.write(0, 4);
+        bits.write(0, 1);
+        bits.write(0, 15);
+        bits.write(0, 7);
+        bits.write(0, 3);
+    }
+    assert_eq!(bits.bytes.len(), MP3_SIDE_INFO_LEN);
+    bits.bytes
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: usize,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, len: usize) {
+        for bit in (0..len).rev() {
+            if self.bits.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            if value >> bit & 1 == 1 {
+                *self.bytes.last_mut().unwrap() |= 0x80 >> (self.bits % 8);
+            }
+            self.bits += 1;
+        }
+    }
+}
+
+/// A wav file of `samples` 16 bit mono PCM samples at 8 kHz.
+pub fn wav(samples: usize) -> Vec<u8> {
+    let data = noise(samples * 2, 7);
+    let mut fmt = Vec::new();
+    fmt.extend_from_slice(&1u16.to_le_bytes());
+    fmt.extend_from_slice(&1u16.to_le_bytes());
+    fmt.extend_from_slice(&8000u32.to_le_bytes());
+    fmt.extend_from_slice(&16000u32.to_le_bytes());
+    fmt.extend_from_slice(&2u16.to_le_bytes());
+    fmt.extend_from_slice(&16u16.to_le_bytes());
+
+    let mut body = b"WAVE".to_vec();
+    body.extend_from_slice(&riff_chunk(b"fmt ", &fmt));
+    body.extend_from_slice(&riff_chunk(b"data", &data));
+    riff_chunk(b"RIFF", &body)
+}
+
+fn riff_chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = id.to_vec();
+    chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        chunk.push(0);
+    }
+    chunk
+}
+
+/// An aiff file of `samples` 16 bit mono PCM samples at 8 kHz.
+pub fn aiff(samples: usize) -> Vec<u8> {
+    let mut common = Vec::new();
+    common.extend_from_slice(&1u16.to_be_bytes());
+    common.extend_from_slice(&(samples as u32).to_be_bytes());
+    common.extend_from_slice(&16u16.to_be_bytes());
+    // 8000 as an 80 bit extended float
+    common.extend_from_slice(&[0x40, 0x0b, 0xfa, 0, 0, 0, 0, 0, 0, 0]);
+    let mut sound = vec![0; 8];
+    sound.extend_from_slice(&noise(samples * 2, 11));
+
+    let mut body = b"AIFF".to_vec();
+    body.extend_from_slice(&iff_chunk(b"COMM", &common));
+    body.extend_from_slice(&iff_chunk(b"SSND", &sound));
+    iff_chunk(b"FORM", &body)
+}
+
+fn iff_chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = id.to_vec();
+    chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    chunk.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        chunk.push(0);
+    }
+    chunk
+}
+
+/// A flac file with a STREAMINFO block, trailing PADDING, and some bytes standing in for the
+/// audio frames.
+pub fn flac() -> Vec<u8> {
+    let mut bytes = b"fLaC".to_vec();
+    bytes.extend_from_slice(&[0, 0, 0, 34]);
+    bytes.extend_from_slice(&noise(34, 3));
+    bytes.extend_from_slice(&[0x81, 0, 0, 16]);
+    bytes.extend_from_slice(&[0; 16]);
+    bytes.extend_from_slice(&[0xff, 0xf8]);
+    bytes.extend_from_slice(&noise(200, 5));
+    bytes
+}
+
+/// An ogg opus file: identification header, comment header, and one audio page.
+pub fn opus() -> Vec<u8> {
+    let mut head = b"OpusHead".to_vec();
+    head.extend_from_slice(&[1, 1, 0x38, 0x01, 0x80, 0xbb, 0, 0, 0, 0, 0]);
+    let mut tags = b"OpusTags".to_vec();
+    tags.extend_from_slice(&comment_list(&["TITLE=Song"]));
+    ogg(head, vec![tags])
+}
+
+/// An ogg vorbis file: identification header, comment and setup headers, and one audio page.
+pub fn vorbis() -> Vec<u8> {
+    let mut identification = b"\x01vorbis".to_vec();
+    identification.extend_from_slice(&[0; 4]);
+    identification.push(1);
+    identification.extend_from_slice(&44100u32.to_le_bytes());
+    identification.extend_from_slice(&[0; 12]);
+    identification.extend_from_slice(&[0xb8, 1]);
+    let mut comment = b"\x03vorbis".to_vec();
+    comment.extend_from_slice(&comment_list(&["TITLE=Song", "ARTIST=Band"]));
+    comment.push(1);
+    let mut setup = b"\x05vorbis".to_vec();
+    setup.extend_from_slice(&noise(300, 13));
+    ogg(identification, vec![comment, setup])
+}
+
+fn comment_list(comments: &[&str]) -> Vec<u8> {
+    let vendor = b"fixture";
+    let mut list = (vendor.len() as u32).to_le_bytes().to_vec();
+    list.extend_from_slice(vendor);
+    list.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for comment in comments {
+        list.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+        list.extend_from_slice(comment.as_bytes());
+    }
+    list
+}
+
+fn ogg(identification: Vec<u8>, headers: Vec<Vec<u8>>) -> Vec<u8> {
+    // header pages are laid out the way id3stego lays them out, so that stripping restores them
+    // byte for byte
+    let mut bytes = ogg_page(0x02, 0, 0, &[identification]);
+    bytes.extend_from_slice(&ogg_page(0, 0, 1, &headers));
+    bytes.extend_from_slice(&ogg_page(0x04, 960, 2, &[noise(120, 17)]));
+    bytes
+}
+
+fn ogg_page(header_type: u8, granule: u64, sequence: u32, packets: &[Vec<u8>]) -> Vec<u8> {
+    let mut lacing = Vec::new();
+    for packet in packets {
+        lacing.extend(std::iter::repeat_n(255, packet.len() / 255));
+        lacing.push((packet.len() % 255) as u8);
+    }
+    let mut page = b"OggS".to_vec();
+    page.push(0);
+    page.push(header_type);
+    page.extend_from_slice(&granule.to_le_bytes());
+    page.extend_from_slice(&0x1234u32.to_le_bytes());
+    page.extend_from_slice(&sequence.to_le_bytes());
+    page.extend_from_slice(&[0; 4]);
+    page.push(lacing.len() as u8);
+    page.extend_from_slice(&lacing);
+    page.extend_from_slice(&packets.concat());
+    let crc = ogg_crc(&page);
+    page[22..26].copy_from_slice(&crc.to_le_bytes());
+    page
+}
+
+fn ogg_crc(page: &[u8]) -> u32 {
+    let mut crc = 0u32;
+    for byte in page {
+        crc ^= (*byte as u32) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 { (crc << 1) ^ 0x04c1_1db7 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// Audio bytes of the m4a fixture, found through its chunk offset table.
+pub const MP4_AUDIO: &[u8] = b"m4a fixture audio samples";
+
+/// An m4a file: ftyp, a moov atom with one track whose chunk offset table points into mdat, and
+/// mdat after moov.
+pub fn m4a() -> Vec<u8> {
+    m4a_with(Vec::new())
+}
+
+/// An m4a file with a title tag of its own (an iTunes style moov/udta/meta/ilst).
+pub fn tagged_m4a() -> Vec<u8> {
+    let mut handler = vec![0; 8];
+    handler.extend_from_slice(b"mdirappl");
+    handler.extend_from_slice(&[0; 9]);
+    let mut title = vec![0, 0, 0, 1, 0, 0, 0, 0];
+    title.extend_from_slice(b"Song");
+    let ilst = atom(b"ilst", &atom(b"\xa9nam", &atom(b"data", &title)));
+    let meta = atom(b"meta", &[vec![0; 4], atom(b"hdlr", &handler), ilst].concat());
+    m4a_with(atom(b"udta", &meta))
+}
+
+fn m4a_with(udta: Vec<u8>) -> Vec<u8> {
+    let ftyp = atom(b"ftyp", b"M4A \0\0\0\0M4A mp42isom");
+    let mvhd = atom(b"mvhd", &[0; 100]);
+    let moov = |offset: u32| {
+        let mut stco = vec![0; 4];
+        stco.extend_from_slice(&1u32.to_be_bytes());
+        stco.extend_from_slice(&offset.to_be_bytes());
+        let stbl = atom(b"stbl", &atom(b"stco", &stco));
+        let minf = atom(b"minf", &stbl);
+        let mdia = atom(b"mdia", &minf);
+        let trak = atom(b"trak", &mdia);
+        atom(b"moov", &[mvhd.clone(), trak, udta.clone()].concat())
+    };
+    let audio_offset = (ftyp.len() + moov(0).len() + 8) as u32;
+    [ftyp.clone(), moov(audio_offset), atom(b"mdat", MP4_AUDIO)].concat()
+}
+
+/// Returns the bytes the (first) chunk offset of the m4a file `bytes` points at.
+pub fn mp4_audio(bytes: &[u8]) -> &[u8] {
+    let stco = find(bytes, b"stco").expect("stco atom");
+    let offset = u32::from_be_bytes(bytes[stco + 12..stco + 16].try_into().unwrap()) as usize;
+    &bytes[offset..offset + MP4_AUDIO.len()]
+}
+
+fn atom(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut atom = ((8 + body.len()) as u32).to_be_bytes().to_vec();
+    atom.extend_from_slice(kind);
+    atom.extend_from_slice(body);
+    atom
+}
+
+/// Offset of the first occurrence of `needle` in `haystack`.
+pub fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Embeds `files` into a copy of `audio`, returning the new audio file.
+pub fn put(embedder: &Embedder, audio: &[u8], files: &[(&str, &[u8])]) -> (Vec<u8>, Vec<Embedded>) {
+    let mut output = Cursor::new(audio.to_vec());
+    let files = files.iter().map(|(filename, data)| (filename.to_string(), data.to_vec())).collect();
+    let embedded = embedder.embed_files(&mut output, files).expect("embed files");
+    (output.into_inner(), embedded)
+}
+
+/// Extracts every embedded file from `audio`, each one extracted or failed on its own.
+pub fn get(extractor: &Extractor, audio: &[u8]) -> Vec<id3stego::Result<Extracted>> {
+    extractor.extract(Cursor::new(audio)).expect("read audio file")
+}
+
+/// Extracts every embedded file from `audio`, failing the test if any of them fails.
+pub fn get_all(extractor: &Extractor, audio: &[u8]) -> Vec<Extracted> {
+    get(extractor, audio).into_iter().map(|extracted| extracted.expect("extract embedded file")).collect()
+}
+
+/// Lists every embedded file in `audio`, each one listed or failed on its own.
+pub fn list(audio: &[u8]) -> Vec<id3stego::Result<Listed>> {
+    Extractor::new().list(Cursor::new(audio)).expect("read audio file")
+}
+
+/// Strips every embedded file from a copy of `audio`, returning the new audio file.
+pub fn strip(audio: &[u8]) -> (Vec<u8>, Stripped) {
+    let mut output = Cursor::new(audio.to_vec());
+    let stripped = Stripper::new().strip(&mut output).expect("strip audio file");
+    (output.into_inner(), stripped)
+}
+
+/// Embeds `data` into `audio` with `embedder`, checks that it is extracted unchanged and that
+/// stripping restores `audio` byte for byte, and returns the audio file with the embedded file.
+pub fn round_trip(embedder: &Embedder, audio: &[u8], filename: &str, data: &[u8]) -> Vec<u8> {
+    let (output, embedded) = put(embedder, audio, &[(filename, data)]);
+    assert_ne!(output, audio);
+
+    let extracted = get_all(&Extractor::new(), &output);
+    assert_eq!(extracted.len(), 1);
+    assert_eq!(extracted[0].key, embedded[0].key);
+    assert_eq!(extracted[0].filename, filename);
+    assert_eq!(extracted[0].carrier, embedded[0].carrier);
+    assert_eq!(extracted[0].data, data);
+
+    let (stripped_audio, stripped) = strip(&output);
+    assert_eq!(stripped.keys, vec![embedded[0].key.clone()]);
+    assert_eq!(stripped_audio, audio);
+    assert!(get(&Extractor::new(), &stripped_audio).is_empty());
+    output
+}
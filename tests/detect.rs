@@ -0,0 +1,45 @@
+mod common;
+
+use std::io::Cursor;
+use id3::{Tag, TagLike, Version};
+use id3stego::{Check, Detector, Embedder, Format};
+
+#[test]
+fn clean_tags_have_no_findings() {
+    let mut tag = Tag::new();
+    tag.set_title("Song");
+    tag.set_artist("Band");
+    let mut audio = Vec::new();
+    tag.write_to(&mut audio, Version::Id3v24).unwrap();
+    audio.extend_from_slice(&common::mp3(40));
+
+    let detection = Detector::new().detect(Cursor::new(audio)).unwrap();
+    assert_eq!(detection.format, Format::Mp3);
+    assert!(detection.tag_size.is_some());
+    assert!(detection.findings.is_empty(), "{:?}", detection.findings);
+}
+
+#[test]
+fn encrypted_files_are_found() {
+    let embedder = Embedder::new().passphrase("correct horse");
+    let (output, embedded) = common::put(&embedder, &common::mp3(40), &[("secret.bin", &common::noise(4000, 1))]);
+
+    let detection = Detector::new().detect(Cursor::new(&output)).unwrap();
+    let finding = detection.findings.iter().find(|finding| finding.check == Check::Entropy).unwrap();
+    assert!(finding.frame.as_ref().unwrap().contains(&embedded[0].key));
+    assert!(finding.score >= 50, "{:?}", finding);
+    // highest score first
+    assert!(detection.findings.windows(2).all(|pair| pair[0].score >= pair[1].score));
+
+    let quiet = Detector::new().min_score(101).detect(Cursor::new(&output)).unwrap();
+    assert!(quiet.findings.is_empty());
+}
+
+#[test]
+fn files_without_a_tag_have_no_findings() {
+    for audio in [common::mp3(40), common::flac()] {
+        let detection = Detector::new().detect(Cursor::new(audio)).unwrap();
+        assert_eq!(detection.tag_size, None);
+        assert!(detection.findings.is_empty(), "{:?}", detection.findings);
+    }
+}
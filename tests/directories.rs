@@ -0,0 +1,68 @@
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use id3stego::{Embedder, Error, Extractor, Stripper};
+
+/// A fresh scratch directory for one test, removed when dropped.
+struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+    fn new(name: &str) -> ScratchDir {
+        let path = std::env::temp_dir().join(format!("id3stego-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        ScratchDir(path)
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn directory_round_trip() {
+    let scratch = ScratchDir::new("directory");
+    let notes = scratch.0.join("notes");
+    fs::create_dir_all(notes.join("sub")).unwrap();
+    fs::write(notes.join("a.txt"), b"first").unwrap();
+    fs::write(notes.join("sub").join("b.txt"), b"second").unwrap();
+    fs::write(scratch.0.join("test.mp3"), common::mp3(4)).unwrap();
+
+    let output_path = scratch.0.join("output-test.mp3");
+    let embedded = Embedder::new().embed_path(scratch.0.join("test.mp3"), &notes, &output_path).unwrap();
+    assert!(embedded.archive);
+    assert_eq!(embedded.filename, "notes");
+
+    let extracted = Extractor::new().extract_path(&output_path).unwrap().remove(0).unwrap();
+    assert!(extracted.archive);
+    let out_dir = scratch.0.join("extracted-notes");
+    let unpacked = extracted.unpack(&out_dir).unwrap();
+    assert!(unpacked.contains(&out_dir.join("sub").join("b.txt")));
+    assert_eq!(fs::read(out_dir.join("a.txt")).unwrap(), b"first");
+    assert_eq!(fs::read(out_dir.join("sub").join("b.txt")).unwrap(), b"second");
+
+    // unpacking again replaces the existing directory as a whole
+    fs::write(out_dir.join("stale.txt"), b"stale").unwrap();
+    extracted.unpack(&out_dir).unwrap();
+    assert!(!out_dir.join("stale.txt").exists());
+    assert_eq!(fs::read(out_dir.join("a.txt")).unwrap(), b"first");
+    // no temp directory is left behind
+    assert_eq!(fs::read_dir(&scratch.0).unwrap().count(), 4);
+
+    let stripped = Stripper::new().strip_path(&output_path, &output_path).unwrap();
+    assert_eq!(stripped.keys, vec![embedded.key]);
+    assert_eq!(fs::read(&output_path).unwrap(), common::mp3(4));
+}
+
+#[test]
+fn plain_files_are_not_unpacked() {
+    let scratch = ScratchDir::new("not-archive");
+    let (output, _) = common::put(&Embedder::new(), &common::mp3(4), &[("a.txt", b"first")]);
+    let extracted = common::get_all(&Extractor::new(), &output).remove(0);
+    let err = extracted.unpack(scratch.0.join("a")).unwrap_err();
+    assert!(matches!(err, Error::NotAnArchive { .. }));
+    assert!(!scratch.0.join("a").exists());
+}
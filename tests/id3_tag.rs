@@ -0,0 +1,165 @@
+mod common;
+
+use std::io::Cursor;
+use id3::{Tag, TagLike, Version};
+use id3stego::{Carrier, Embedder, Error, Extractor, TagVersion, TextEncoding, MAX_FRAME_SIZE};
+use common::{get, get_all, list, mp3, put, round_trip, strip};
+
+fn tagged_mp3(version: Version) -> Vec<u8> {
+    let mut tag = Tag::new();
+    tag.set_title("Song");
+    let mut bytes = Vec::new();
+    tag.write_to(&mut bytes, version).unwrap();
+    bytes.extend_from_slice(&mp3(4));
+    bytes
+}
+
+fn read_tag(audio: &[u8]) -> Tag {
+    Tag::read_from2(Cursor::new(audio)).unwrap()
+}
+
+#[test]
+fn geob_round_trip() {
+    let output = round_trip(&Embedder::new(), &mp3(4), "notes.txt", b"some notes");
+    let listed = list(&output);
+    assert_eq!(listed.len(), 1);
+    let listed = listed[0].as_ref().unwrap();
+    assert_eq!(listed.carrier, Carrier::Geob);
+    assert_eq!(listed.filename, "notes.txt");
+    assert_eq!(listed.size, Some(10));
+    assert_eq!(listed.compressed, Some(false));
+    assert!(listed.sha256.is_some());
+}
+
+#[test]
+fn priv_txxx_and_comm_round_trip() {
+    for carrier in [Carrier::Priv, Carrier::Txxx, Carrier::Comm] {
+        for encoding in [TextEncoding::Base64, TextEncoding::Base85] {
+            let embedder = Embedder::new().carrier(carrier).text_encoding(encoding);
+            let output = round_trip(&embedder, &mp3(4), "data.bin", &common::noise(1000, 1));
+            assert_eq!(get_all(&Extractor::new(), &output)[0].carrier, carrier);
+        }
+    }
+}
+
+#[test]
+fn wav_and_aiff_id3_chunk_round_trip() {
+    round_trip(&Embedder::new(), &common::wav(1000), "notes.txt", b"wav notes");
+    round_trip(&Embedder::new(), &common::aiff(1000), "notes.txt", b"aiff notes");
+}
+
+#[test]
+fn strip_keeps_other_frames() {
+    let audio = tagged_mp3(Version::Id3v24);
+    let (output, _) = put(&Embedder::new(), &audio, &[("notes.txt", b"some notes")]);
+    assert_eq!(read_tag(&output).title(), Some("Song"));
+
+    let (stripped_audio, stripped) = strip(&output);
+    assert_eq!(stripped.frames, 1);
+    assert!(!stripped.tag_removed);
+    assert_eq!(read_tag(&stripped_audio).title(), Some("Song"));
+    assert!(get(&Extractor::new(), &stripped_audio).is_empty());
+}
+
+#[test]
+fn strip_removes_emptied_tag() {
+    let (output, _) = put(&Embedder::new(), &mp3(4), &[("notes.txt", b"some notes")]);
+    let mut kept = Cursor::new(output.clone());
+    let stripped = id3stego::Stripper::new().remove_empty_tag(false).strip(&mut kept).unwrap();
+    assert!(!stripped.tag_removed);
+    assert_eq!(read_tag(kept.get_ref()).frames().count(), 0);
+
+    let (removed, stripped) = strip(&output);
+    assert!(stripped.tag_removed);
+    assert_eq!(removed, mp3(4));
+}
+
+#[test]
+fn tag_version_is_kept_unless_chosen() {
+    let audio = tagged_mp3(Version::Id3v23);
+    let (output, embedded) = put(&Embedder::new(), &audio, &[("notes.txt", b"some notes")]);
+    assert_eq!(embedded[0].tag_version, Some(TagVersion::Id3v23));
+    assert_eq!(read_tag(&output).version(), Version::Id3v23);
+
+    let embedder = Embedder::new().tag_version(TagVersion::Id3v24);
+    let (output, embedded) = put(&embedder, &audio, &[("notes.txt", b"some notes")]);
+    assert_eq!(embedded[0].tag_version, Some(TagVersion::Id3v24));
+    assert_eq!(read_tag(&output).version(), Version::Id3v24);
+    assert_eq!(get_all(&Extractor::new(), &output)[0].data, b"some notes");
+}
+
+#[test]
+fn custom_keys_and_key_patterns() {
+    let embedder = Embedder::new().key("backup");
+    let (output, embedded) = put(&embedder, &mp3(4), &[("a.txt", b"first"), ("b.txt", b"second")]);
+    let keys: Vec<&str> = embedded.iter().map(|embedded| embedded.key.as_str()).collect();
+    assert_eq!(keys, ["backup-1", "backup-2"]);
+
+    let extracted = get_all(&Extractor::new().key("backup-2"), &output);
+    assert_eq!(extracted.len(), 1);
+    assert_eq!(extracted[0].data, b"second");
+    assert_eq!(get_all(&Extractor::new().key("backup-*"), &output).len(), 2);
+    assert!(get_all(&Extractor::new().key("other"), &output).is_empty());
+
+    let mut reused = Cursor::new(output);
+    let err = Embedder::new().key("backup-1").embed(&mut reused, "c.txt", b"third".to_vec()).unwrap_err();
+    assert!(matches!(err, Error::FrameKeyInUse { .. }));
+    let err = Embedder::new().key("no spaces").embed(&mut reused, "c.txt", b"third".to_vec()).unwrap_err();
+    assert!(matches!(err, Error::InvalidFrameKey { .. }));
+}
+
+#[test]
+fn multiple_files_in_one_put() {
+    let (output, embedded) = put(&Embedder::new(), &mp3(4), &[("a.txt", b"first"), ("b.txt", b"second")]);
+    assert_eq!(embedded.len(), 2);
+    assert_ne!(embedded[0].key, embedded[1].key);
+
+    let mut extracted = get_all(&Extractor::new(), &output);
+    extracted.sort_by(|a, b| a.filename.cmp(&b.filename));
+    assert_eq!(extracted[0].data, b"first");
+    assert_eq!(extracted[1].data, b"second");
+
+    let (stripped_audio, stripped) = strip(&output);
+    assert_eq!(stripped.keys.len(), 2);
+    assert_eq!(stripped_audio, mp3(4));
+}
+
+#[test]
+fn tampered_digest_is_reported() {
+    let data = b"tamper with this embedded file";
+    let (mut output, _) = put(&Embedder::new(), &mp3(4), &[("notes.txt", data)]);
+    let offset = common::find(&output, data).unwrap();
+    output[offset] ^= 1;
+
+    let extracted = get(&Extractor::new(), &output);
+    assert!(matches!(extracted[0], Err(Error::Corrupted { .. })));
+}
+
+#[test]
+fn damaged_files_are_listed_on_their_own() {
+    let (mut output, embedded) = put(&Embedder::new(), &mp3(4), &[("a.txt", b"first"), ("b.txt", b"second")]);
+    // unsupported envelope version of the first file
+    let offset = common::find(&output, b"I3SP").unwrap();
+    output[offset + 4] = 0xff;
+
+    let listed = list(&output);
+    assert_eq!(listed.len(), 2);
+    assert!(listed.iter().any(|listed| matches!(listed, Err(Error::InvalidHeader { .. }))));
+    let listed: Vec<_> = listed.into_iter().filter_map(Result::ok).collect();
+    assert_eq!(listed.len(), 1);
+    assert!(embedded.iter().any(|embedded| embedded.key == listed[0].key));
+}
+
+#[test]
+fn large_files_are_split_into_chunks() {
+    let data = common::noise(MAX_FRAME_SIZE + 1000, 9);
+    let (output, embedded) = put(&Embedder::new(), &mp3(4), &[("large.bin", &data)]);
+    assert_eq!(embedded[0].frames, 2);
+    assert_eq!(list(&output)[0].as_ref().unwrap().frames, 2);
+    assert_eq!(get_all(&Extractor::new(), &output)[0].data, data);
+
+    let (stripped_audio, stripped) = strip(&output);
+    assert_eq!(stripped.frames, 2);
+    assert_eq!(stripped.keys, vec![embedded[0].key.clone()]);
+    assert_eq!(stripped_audio, mp3(4));
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use id3stego::{Carrier, Embedder, Error, Extractor, Format, TextEncoding};
+use common::{get_all, list, put, round_trip};
+
+#[test]
+fn flac_blocks_round_trip() {
+    for carrier in [Carrier::FlacApplication, Carrier::FlacPicture] {
+        let output = round_trip(&Embedder::new().carrier(carrier), &common::flac(), "notes.txt", b"flac notes");
+        assert_eq!(get_all(&Extractor::new(), &output)[0].carrier, carrier);
+        // stored in front of the trailing padding, audio frames unchanged
+        assert!(output.ends_with(&common::flac()[common::flac().len() - 222..]));
+    }
+}
+
+#[test]
+fn ogg_comments_round_trip() {
+    for audio in [common::opus(), common::vorbis()] {
+        for carrier in [Carrier::OggComment, Carrier::OggPicture] {
+            let embedder = Embedder::new().carrier(carrier).text_encoding(TextEncoding::Base85);
+            let output = round_trip(&embedder, &audio, "notes.txt", b"ogg notes");
+            assert_eq!(get_all(&Extractor::new(), &output)[0].carrier, carrier);
+            // the other comments are kept
+            assert!(common::find(&output, b"TITLE=Song").is_some());
+        }
+    }
+}
+
+#[test]
+fn large_ogg_comment_headers_span_pages() {
+    let data = common::noise(100_000, 21);
+    let output = round_trip(&Embedder::new(), &common::opus(), "large.bin", &data);
+    assert!(output.windows(4).filter(|window| window == b"OggS").count() > 3);
+}
+
+#[test]
+fn mp4_atoms_round_trip() {
+    for carrier in [Carrier::Mp4Freeform, Carrier::Mp4Uuid] {
+        let output = round_trip(&Embedder::new().carrier(carrier), &common::m4a(), "notes.txt", b"m4a notes");
+        assert_eq!(get_all(&Extractor::new(), &output)[0].carrier, carrier);
+        // moov grew, so the chunk offsets moved along with mdat
+        assert_eq!(common::mp4_audio(&output), common::MP4_AUDIO);
+    }
+}
+
+#[test]
+fn mp4_atoms_round_trip_with_own_tags() {
+    // freeform atoms are added to the file's own ilst, which stripping keeps
+    for carrier in [Carrier::Mp4Freeform, Carrier::Mp4Uuid] {
+        let output = round_trip(&Embedder::new().carrier(carrier), &common::tagged_m4a(), "a.txt", b"m4a notes");
+        assert!(common::find(&output, b"Song").is_some());
+        assert_eq!(common::mp4_audio(&output), common::MP4_AUDIO);
+    }
+}
+
+#[test]
+fn wav_chunks_round_trip() {
+    for carrier in [Carrier::WavChunk, Carrier::WavInfo] {
+        let output = round_trip(&Embedder::new().carrier(carrier), &common::wav(1000), "notes.txt", b"wav notes");
+        assert_eq!(get_all(&Extractor::new(), &output)[0].carrier, carrier);
+    }
+}
+
+#[test]
+fn aiff_chunks_round_trip() {
+    for carrier in [Carrier::AiffAppl, Carrier::AiffAnno] {
+        let output = round_trip(&Embedder::new().carrier(carrier), &common::aiff(1000), "notes.txt", b"aiff notes");
+        assert_eq!(get_all(&Extractor::new(), &output)[0].carrier, carrier);
+    }
+}
+
+#[test]
+fn wav_chunk_and_id3_tag_together() {
+    let audio = common::wav(1000);
+    let (output, _) = put(&Embedder::new().carrier(Carrier::WavChunk), &audio, &[("a.txt", b"chunk")]);
+    let (output, _) = put(&Embedder::new(), &output, &[("b.txt", b"tag")]);
+    let listed = list(&output);
+    assert_eq!(listed.len(), 2);
+    let (stripped_audio, stripped) = common::strip(&output);
+    assert_eq!(stripped.keys.len(), 2);
+    assert_eq!(stripped_audio, audio);
+}
+
+#[test]
+fn unsupported_carriers_are_refused() {
+    let cases = [
+        (common::flac(), Carrier::Geob, Format::Flac),
+        (common::opus(), Carrier::FlacPicture, Format::Ogg),
+        (common::m4a(), Carrier::WavChunk, Format::Mp4),
+        (common::mp3(4), Carrier::AiffAppl, Format::Mp3),
+    ];
+    for (audio, carrier, format) in cases {
+        let mut output = std::io::Cursor::new(audio);
+        let err = Embedder::new().carrier(carrier).embed(&mut output, "a.txt", b"data".to_vec()).unwrap_err();
+        assert!(matches!(err, Error::UnsupportedCarrier { carrier: c, format: f } if c == carrier && f == format));
+    }
+}
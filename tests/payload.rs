@@ -0,0 +1,118 @@
+mod common;
+
+use id3stego::{Carrier, Compression, EncryptionMethod, Embedder, Error, Extractor, Identity};
+use common::{find, get, get_all, list, mp3, put, strip};
+
+const SECRET: &[u8] = b"attack at dawn, attack at dawn, attack at dawn";
+
+#[test]
+fn passphrase_round_trip() {
+    let embedder = Embedder::new().passphrase("correct horse");
+    let (output, embedded) = put(&embedder, &mp3(4), &[("secret.txt", SECRET)]);
+    assert_eq!(embedded[0].encryption, Some(EncryptionMethod::Passphrase));
+    // neither the file nor its name or mime-type are left in the clear
+    assert!(find(&output, SECRET).is_none());
+    assert!(find(&output, b"secret.txt").is_none());
+    assert!(find(&output, b"text/plain").is_none());
+
+    let extracted = get_all(&Extractor::new().passphrase("correct horse"), &output);
+    assert_eq!(extracted[0].data, SECRET);
+    assert_eq!(extracted[0].filename, "secret.txt");
+    assert_eq!(extracted[0].encryption, Some(EncryptionMethod::Passphrase));
+
+    let (stripped_audio, _) = strip(&output);
+    assert_eq!(stripped_audio, mp3(4));
+}
+
+#[test]
+fn wrong_or_missing_passphrase_is_reported() {
+    let embedder = Embedder::new().passphrase("correct horse");
+    let (output, _) = put(&embedder, &mp3(4), &[("secret.txt", SECRET)]);
+    assert!(matches!(get(&Extractor::new().passphrase("wrong horse"), &output)[0], Err(Error::Decryption { .. })));
+    assert!(matches!(get(&Extractor::new(), &output)[0], Err(Error::PassphraseRequired { .. })));
+}
+
+#[test]
+fn encrypted_files_are_listed_without_their_metadata() {
+    let embedder = Embedder::new().passphrase("correct horse").compression(Compression::Zstd);
+    let (output, _) = put(&embedder, &mp3(4), &[("secret.txt", SECRET)]);
+    let listed = list(&output);
+    let listed = listed[0].as_ref().unwrap();
+    assert_eq!(listed.filename, "");
+    assert_eq!(listed.mime_type, "application/octet-stream");
+    assert_eq!(listed.size, None);
+    assert_eq!(listed.compressed, None);
+    assert_eq!(listed.sha256, None);
+    assert_eq!(listed.encryption, Some(EncryptionMethod::Passphrase));
+}
+
+#[test]
+fn tampered_ciphertext_fails_to_decrypt() {
+    let embedder = Embedder::new().carrier(Carrier::Priv).passphrase("correct horse");
+    let (mut output, _) = put(&embedder, &mp3(4), &[("secret.txt", SECRET)]);
+    // last byte of the frame is part of the authentication tag
+    let end = find(&output, &[0xff, 0xfb, 0x90, 0xc4]).unwrap();
+    output[end - 1] ^= 1;
+    assert!(matches!(get(&Extractor::new().passphrase("correct horse"), &output)[0], Err(Error::Decryption { .. })));
+}
+
+#[test]
+fn recipients_round_trip() {
+    let alice = Identity::generate();
+    let bob = Identity::generate();
+    let embedder = Embedder::new().recipients([alice.recipient(), bob.recipient()]);
+    let (output, embedded) = put(&embedder, &mp3(4), &[("secret.txt", SECRET)]);
+    assert_eq!(embedded[0].encryption, Some(EncryptionMethod::Recipients));
+    assert!(find(&output, SECRET).is_none());
+
+    for identity in [alice, bob] {
+        let extracted = get_all(&Extractor::new().identity(identity), &output);
+        assert_eq!(extracted[0].data, SECRET);
+        assert_eq!(extracted[0].encryption, Some(EncryptionMethod::Recipients));
+    }
+}
+
+#[test]
+fn wrong_or_missing_identity_is_reported() {
+    let embedder = Embedder::new().recipient(Identity::generate().recipient());
+    let (output, _) = put(&embedder, &mp3(4), &[("secret.txt", SECRET)]);
+    let extractor = Extractor::new().identity(Identity::generate());
+    assert!(matches!(get(&extractor, &output)[0], Err(Error::NoMatchingIdentity { .. })));
+    assert!(matches!(get(&Extractor::new(), &output)[0], Err(Error::IdentityRequired { .. })));
+}
+
+#[test]
+fn compression_round_trip() {
+    let data = SECRET.repeat(100);
+    for compression in [Compression::Zstd, Compression::Deflate] {
+        let (output, embedded) = put(&Embedder::new().compression(compression), &mp3(4), &[("a.txt", &data)]);
+        assert_eq!(embedded[0].compression, Some(compression));
+        assert!(output.len() < mp3(4).len() + data.len() / 10);
+
+        let extracted = get_all(&Extractor::new(), &output);
+        assert_eq!(extracted[0].data, data);
+        assert_eq!(extracted[0].compression, Some(compression));
+        assert_eq!(list(&output)[0].as_ref().unwrap().compressed, Some(true));
+    }
+}
+
+#[test]
+fn compressed_and_encrypted_round_trip() {
+    let data = SECRET.repeat(100);
+    let embedder = Embedder::new().compression(Compression::Deflate).passphrase("correct horse");
+    let (output, _) = put(&embedder, &mp3(4), &[("a.txt", &data)]);
+    let extracted = get_all(&Extractor::new().passphrase("correct horse"), &output);
+    assert_eq!(extracted[0].data, data);
+    assert_eq!(extracted[0].compression, Some(Compression::Deflate));
+}
+
+#[test]
+fn decompression_is_capped() {
+    let data = vec![0; 2_000_000];
+    for compression in [Compression::Zstd, Compression::Deflate] {
+        let (output, _) = put(&Embedder::new().compression(compression), &mp3(4), &[("zeros.bin", &data)]);
+        let capped = Extractor::new().max_decompressed_size(1_000_000);
+        assert!(matches!(get(&capped, &output)[0], Err(Error::Decompression { .. })));
+        assert_eq!(get_all(&Extractor::new(), &output)[0].data.len(), data.len());
+    }
+}